@@ -0,0 +1,425 @@
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::errors::LauncherError;
+use crate::helpers::{self, JsonStore};
+use crate::samp;
+use crate::text::{self, TextSpan};
+
+/// The client's chat log, overwritten every time the game starts
+pub const CHATLOG_FILE_NAME: &str = "chatlog.txt";
+
+/// Index of the archive, its lock also covers the archived logs
+static CHATLOGS_STORE: JsonStore = JsonStore::new("chatlogs/chatlogs.json");
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ArchivedChatlog {
+    pub id: String,
+    pub ip: String,
+    pub port: i32,
+    /// Start and end of the game session the log was written in
+    pub started_at: u64,
+    pub ended_at: u64,
+    pub line_count: usize,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ChatlogsData {
+    chatlogs: Vec<ArchivedChatlog>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ChatLine {
+    /// `hh:mm:ss` the client put in front of the line, if it did
+    pub time: Option<String>,
+    /// The line without its timestamp and color codes
+    pub text: String,
+    pub spans: Vec<TextSpan>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ChatlogMatch {
+    pub chatlog: ArchivedChatlog,
+    /// Zero-based line number in the log
+    pub line_number: usize,
+    pub line: ChatLine,
+}
+
+fn get_chatlogs_dir() -> Result<PathBuf, String> {
+    Ok(helpers::get_launcher_data_dir()?.join("chatlogs"))
+}
+
+fn get_chatlog_file(id: &str) -> Result<PathBuf, String> {
+    Ok(get_chatlogs_dir()?.join(format!("{}.txt", id)))
+}
+
+fn load_chatlogs_data() -> Result<ChatlogsData, LauncherError> {
+    CHATLOGS_STORE.load()
+}
+
+fn save_chatlogs_data(data: &ChatlogsData) -> Result<(), LauncherError> {
+    CHATLOGS_STORE.save(data)
+}
+
+fn lock() -> Result<std::sync::MutexGuard<'static, ()>, LauncherError> {
+    CHATLOGS_STORE.lock()
+}
+
+/// Splits off a leading `[hh:mm:ss] `
+fn split_time(line: &str) -> (Option<&str>, &str) {
+    let bytes = line.as_bytes();
+    let is_time = bytes.len() >= 10
+        && bytes[0] == b'['
+        && bytes[9] == b']'
+        && bytes[3] == b':'
+        && bytes[6] == b':'
+        && [1, 2, 4, 5, 7, 8]
+            .iter()
+            .all(|&i| bytes[i].is_ascii_digit());
+
+    if is_time {
+        (
+            Some(&line[1..9]),
+            line[10..].strip_prefix(' ').unwrap_or(&line[10..]),
+        )
+    } else {
+        (None, line)
+    }
+}
+
+pub fn parse_line(line: &str) -> ChatLine {
+    let (time, text) = split_time(line);
+    let text = text::parse(text);
+
+    ChatLine {
+        time: time.map(str::to_string),
+        text: text.plain,
+        spans: text.spans,
+    }
+}
+
+/// Reads a chat log. It's written in the system code page, so the encoding is
+/// detected rather than assumed.
+fn read_chatlog_text(path: &PathBuf) -> Result<String, LauncherError> {
+    let bytes = fs::read(path).map_err(|e| LauncherError::from_io(e, path))?;
+    Ok(match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => helpers::decode_buffer(e.into_bytes(), None).0,
+    })
+}
+
+/// Copies the chat log of a finished game session into the archive. Logs not
+/// written during the session (e.g. the game never connected) are skipped, as
+/// are the first `skip_lines` lines, which were archived for a server the game
+/// was connected to before.
+pub fn archive_chatlog(
+    game_dir: &str,
+    ip: &str,
+    port: i32,
+    started_at: u64,
+    ended_at: u64,
+    skip_lines: usize,
+) -> Result<Option<ArchivedChatlog>, LauncherError> {
+    let source = match samp::samp_user_files_dir(Some(game_dir)) {
+        Some(dir) => dir.join(CHATLOG_FILE_NAME),
+        None => return Ok(None),
+    };
+
+    let modified_at = fs::metadata(&source)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_secs());
+    if modified_at.is_none_or(|modified_at| modified_at < started_at) {
+        return Ok(None);
+    }
+
+    let text = read_chatlog_text(&source)?;
+    let lines: Vec<&str> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .skip(skip_lines)
+        .collect();
+    let line_count = lines.len();
+    if line_count == 0 {
+        return Ok(None);
+    }
+
+    let _guard = lock()?;
+    let mut data = load_chatlogs_data()?;
+
+    let chatlog = ArchivedChatlog {
+        id: format!(
+            "{:x}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0)
+        ),
+        ip: ip.to_string(),
+        port,
+        started_at,
+        ended_at,
+        line_count,
+    };
+
+    let dir = get_chatlogs_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| LauncherError::from_io(e, &dir))?;
+    let file = get_chatlog_file(&chatlog.id)?;
+    helpers::write_file_atomic(&file, lines.join("\n").as_bytes())?;
+
+    data.chatlogs.push(chatlog.clone());
+    save_chatlogs_data(&data)?;
+
+    info!(
+        "[chatlog.rs] Archived {} chat line(s) of {}:{}",
+        line_count, ip, port
+    );
+    Ok(Some(chatlog))
+}
+
+/// Archives the chat log of a finished session, logging rather than returning
+/// failures since there's no one to report them to. Returns the number of
+/// lines archived.
+pub fn archive_session_chatlog(
+    game_dir: &str,
+    ip: &str,
+    port: i32,
+    started_at: u64,
+    skip_lines: usize,
+) -> usize {
+    match archive_chatlog(game_dir, ip, port, started_at, helpers::now(), skip_lines) {
+        Ok(chatlog) => chatlog.map(|chatlog| chatlog.line_count).unwrap_or(0),
+        Err(e) => {
+            error!("[chatlog.rs] Archiving chat log failed: {}", e);
+            0
+        }
+    }
+}
+
+fn read_archived_lines(id: &str) -> Result<Vec<ChatLine>, LauncherError> {
+    let file = get_chatlog_file(id)?;
+    let text = fs::read_to_string(&file).map_err(|e| LauncherError::from_io(e, &file))?;
+    Ok(text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(parse_line)
+        .collect())
+}
+
+/// Archived chat logs, newest first, optionally only those of one server
+#[tauri::command]
+pub fn get_archived_chatlogs(
+    ip: Option<String>,
+    port: Option<i32>,
+) -> Result<Vec<ArchivedChatlog>, LauncherError> {
+    let _guard = lock()?;
+    let mut chatlogs: Vec<ArchivedChatlog> = load_chatlogs_data()?
+        .chatlogs
+        .into_iter()
+        .filter(|chatlog| ip.as_ref().is_none_or(|ip| &chatlog.ip == ip))
+        .filter(|chatlog| port.is_none_or(|port| chatlog.port == port))
+        .collect();
+    chatlogs.sort_by_key(|chatlog| std::cmp::Reverse(chatlog.started_at));
+    Ok(chatlogs)
+}
+
+#[tauri::command]
+pub fn get_archived_chatlog(id: String) -> Result<Vec<ChatLine>, LauncherError> {
+    let _guard = lock()?;
+    if !load_chatlogs_data()?
+        .chatlogs
+        .iter()
+        .any(|chatlog| chatlog.id == id)
+    {
+        return Err(LauncherError::NotFound(format!(
+            "No chat log with id {}",
+            id
+        )));
+    }
+    read_archived_lines(&id)
+}
+
+/// Searches the text of archived chat logs, ignoring case and color codes.
+/// Matches come newest log first, at most `limit` of them.
+pub fn search(
+    query: &str,
+    ip: Option<String>,
+    port: Option<i32>,
+    limit: usize,
+) -> Result<Vec<ChatlogMatch>, LauncherError> {
+    let chatlogs = get_archived_chatlogs(ip, port)?;
+    let logs = chatlogs
+        .into_iter()
+        .filter_map(|chatlog| match read_archived_lines(&chatlog.id) {
+            Ok(lines) => Some((chatlog, lines)),
+            Err(e) => {
+                error!("[chatlog.rs] Reading chat log {} failed: {}", chatlog.id, e);
+                None
+            }
+        });
+    Ok(search_logs(query, logs, limit))
+}
+
+/// The matching part of `search`. Logs are only read as far as needed to find
+/// `limit` matches.
+fn search_logs(
+    query: &str,
+    logs: impl Iterator<Item = (ArchivedChatlog, Vec<ChatLine>)>,
+    limit: usize,
+) -> Vec<ChatlogMatch> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() || limit == 0 {
+        return Vec::new();
+    }
+
+    let mut matches = Vec::new();
+    for (chatlog, lines) in logs {
+        for (line_number, line) in lines.into_iter().enumerate() {
+            if line.text.to_lowercase().contains(&query) {
+                matches.push(ChatlogMatch {
+                    chatlog: chatlog.clone(),
+                    line_number,
+                    line,
+                });
+                if matches.len() >= limit {
+                    return matches;
+                }
+            }
+        }
+    }
+
+    matches
+}
+
+#[tauri::command]
+pub async fn search_chatlogs(
+    query: String,
+    ip: Option<String>,
+    port: Option<i32>,
+    limit: Option<usize>,
+) -> Result<Vec<ChatlogMatch>, LauncherError> {
+    search(&query, ip, port, limit.unwrap_or(500))
+}
+
+#[tauri::command]
+pub fn delete_archived_chatlog(id: String) -> Result<(), LauncherError> {
+    let _guard = lock()?;
+    let mut data = load_chatlogs_data()?;
+
+    let count = data.chatlogs.len();
+    data.chatlogs.retain(|chatlog| chatlog.id != id);
+    if data.chatlogs.len() == count {
+        return Err(LauncherError::NotFound(format!(
+            "No chat log with id {}",
+            id
+        )));
+    }
+
+    let file = get_chatlog_file(&id)?;
+    if file.exists() {
+        fs::remove_file(&file).map_err(|e| LauncherError::from_io(e, &file))?;
+    }
+    save_chatlogs_data(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_time_strips_timestamp() {
+        assert_eq!(
+            split_time("[12:34:56] Hello there"),
+            (Some("12:34:56"), "Hello there")
+        );
+        assert_eq!(split_time("[00:00:00]"), (Some("00:00:00"), ""));
+        assert_eq!(
+            split_time("[00:00:00]no space"),
+            (Some("00:00:00"), "no space")
+        );
+        assert_eq!(
+            split_time("[23:59:59] Привет, мир"),
+            (Some("23:59:59"), "Привет, мир")
+        );
+    }
+
+    #[test]
+    fn split_time_keeps_lines_without_timestamp() {
+        for line in [
+            "",
+            "Hello",
+            "[12:34:5]",
+            "[12:34] chat",
+            "[1a:34:56] chat",
+            "(12:34:56) chat",
+            "[12-34-56] chat",
+            "[Привет]",
+            " [12:34:56] chat",
+        ] {
+            assert_eq!(split_time(line), (None, line), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn parse_line_strips_time_and_colors() {
+        let line = parse_line("[01:02:03] {FF0000}Admin: {FFFFFF}hi");
+        assert_eq!(line.time.as_deref(), Some("01:02:03"));
+        assert_eq!(line.text, "Admin: hi");
+    }
+
+    fn chatlog(id: &str) -> ArchivedChatlog {
+        ArchivedChatlog {
+            id: id.to_string(),
+            ip: "127.0.0.1".to_string(),
+            port: 7777,
+            started_at: 0,
+            ended_at: 0,
+            line_count: 0,
+        }
+    }
+
+    fn logs() -> Vec<(ArchivedChatlog, Vec<ChatLine>)> {
+        vec![
+            (
+                chatlog("newer"),
+                ["[10:00:00] {FF0000}Welcome to the server", "[10:00:01] bye"]
+                    .into_iter()
+                    .map(parse_line)
+                    .collect(),
+            ),
+            (
+                chatlog("older"),
+                ["welcome back", "Добро пожаловать, WELCOME"]
+                    .into_iter()
+                    .map(parse_line)
+                    .collect(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn search_ignores_case_and_colors() {
+        let matches = search_logs("  WELCOME ", logs().into_iter(), 10);
+        let found: Vec<(&str, usize)> = matches
+            .iter()
+            .map(|m| (m.chatlog.id.as_str(), m.line_number))
+            .collect();
+        assert_eq!(found, [("newer", 0), ("older", 0), ("older", 1)]);
+        assert_eq!(matches[0].line.text, "Welcome to the server");
+
+        // Color codes aren't part of the text
+        assert!(search_logs("FF0000", logs().into_iter(), 10).is_empty());
+        assert_eq!(search_logs("пожаловать", logs().into_iter(), 10).len(), 1);
+    }
+
+    #[test]
+    fn search_stops_at_limit() {
+        assert_eq!(search_logs("welcome", logs().into_iter(), 2).len(), 2);
+        assert!(search_logs("welcome", logs().into_iter(), 0).is_empty());
+        assert!(search_logs("   ", logs().into_iter(), 10).is_empty());
+    }
+}
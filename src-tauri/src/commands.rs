@@ -1,5 +1,9 @@
-use crate::{background_thread::check_for_new_instance_and_close, injector, samp};
-use log::info;
+use crate::{
+    background_thread::check_for_new_instance_and_close, errors::LauncherError, injector,
+    nativestorage, process, samp,
+};
+use log::{error, info};
+use std::path::Path;
 
 #[tauri::command]
 pub async fn inject(
@@ -11,8 +15,32 @@ pub async fn inject(
     omp_file: &str,
     password: &str,
     discord: bool,
-) -> Result<(), String> {
-    injector::run_samp(name, ip, port, exe, dll, omp_file, password, discord).await
+    launch_policy: Option<injector::LaunchPolicy>,
+) -> Result<injector::LaunchOutcome, LauncherError> {
+    injector::run_samp(
+        name,
+        ip,
+        port,
+        exe,
+        dll,
+        omp_file,
+        password,
+        discord,
+        launch_policy.unwrap_or_default(),
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn connect_running_game(
+    pid: u32,
+    ip: String,
+    port: i32,
+    password: String,
+) -> Result<(), LauncherError> {
+    tokio::task::spawn_blocking(move || injector::connect_running_game(pid, &ip, port, &password))
+        .await
+        .map_err(|e| LauncherError::Other(e.to_string()))?
 }
 
 #[tauri::command]
@@ -26,22 +54,19 @@ pub fn get_nickname_from_samp() -> String {
 }
 
 #[tauri::command]
-pub fn rerun_as_admin() -> Result<String, String> {
-    let res = std::env::current_exe();
-    match res {
-        Ok(p) => {
-            let path = p.into_os_string().into_string().unwrap();
-            runas::Command::new(path).arg("").status().unwrap();
-            check_for_new_instance_and_close();
-            Ok("SUCCESS".to_string())
-        }
-        Err(_) => Err("FAILED".to_string()),
-    }
+pub fn rerun_as_admin() -> Result<(), LauncherError> {
+    let path = std::env::current_exe()?;
+    runas::Command::new(&path)
+        .arg("")
+        .status()
+        .map_err(|e| LauncherError::from_io(e, &path))?;
+    check_for_new_instance_and_close();
+    Ok(())
 }
 
 #[tauri::command]
-pub fn get_samp_favorite_list() -> String {
-    samp::get_samp_favorite_list()
+pub fn get_samp_favorite_list(path: Option<String>) -> Result<samp::SAMPUserData, LauncherError> {
+    samp::get_samp_favorite_list(path.as_deref().map(Path::new))
 }
 
 #[tauri::command]
@@ -64,18 +89,35 @@ pub fn resolve_hostname(hostname: String) -> Result<String, String> {
 
 #[tauri::command]
 pub fn is_process_alive(pid: u32) -> bool {
-    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
-    use windows_sys::Win32::System::Threading::{OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+    process::is_process_alive(pid)
+}
 
-    unsafe {
-        let handle: HANDLE = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
-        if handle != 0 {
-            CloseHandle(handle);
-            true
-        } else {
-            false
-        }
+#[tauri::command]
+pub async fn get_game_processes() -> Vec<process::GameProcess> {
+    // Sampling CPU usage sleeps in between, keep that off the async workers
+    tokio::task::spawn_blocking(process::find_game_processes)
+        .await
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+pub fn terminate_game_process(pid: u32) -> Result<(), LauncherError> {
+    info!("Terminating game process {} on request", pid);
+    process::terminate_game_process(pid)
+}
+
+/// Writes pending storage changes to disk and exits the launcher. The
+/// frontend exits through this rather than on its own, which would skip the
+/// flush on `RunEvent::Exit`.
+#[tauri::command]
+pub fn exit_launcher(app: tauri::AppHandle) {
+    if let Err(e) = nativestorage::flush_storage() {
+        error!(
+            "[commands.rs] Flushing storage before exiting failed: {}",
+            e
+        );
     }
+    app.exit(0);
 }
 
 #[tauri::command]
@@ -0,0 +1,550 @@
+use log::info;
+use md5::Context;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::errors::LauncherError;
+
+const TEMP_FILE_SUFFIX: &str = ".omp-tmp";
+const BACKUP_DIR_PREFIX: &str = ".omp-backup-";
+
+/// What to do with files that already exist in the destination
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum OverwritePolicy {
+    #[default]
+    Always,
+    /// Only overwrite files whose content differs from the source
+    IfDifferent,
+    Never,
+}
+
+#[derive(Clone)]
+pub struct PlannedFile {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    /// Path relative to the copy destination
+    pub relative: String,
+    pub replaces_existing: bool,
+}
+
+/// Everything a copy is going to do, worked out before anything is touched
+#[derive(Default)]
+pub struct CopyPlan {
+    pub dirs: Vec<PathBuf>,
+    pub files: Vec<PlannedFile>,
+    pub skipped: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CopyReport {
+    /// Files that didn't exist in the destination before
+    pub created: Vec<String>,
+    /// Files that were overwritten, their originals are kept in the backup directory
+    pub replaced: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CopyProgress {
+    pub current: usize,
+    pub total: usize,
+    pub file: String,
+}
+
+/// Changes made so far, undone in reverse order on rollback
+enum JournalEntry {
+    CreatedDir(PathBuf),
+    CreatedFile(PathBuf),
+    ReplacedFile { target: PathBuf, backup: PathBuf },
+}
+
+fn map_io_error(e: io::Error, path: &Path) -> LauncherError {
+    info!("[copy_engine.rs] {}: {}", path.display(), e);
+    LauncherError::from_io(e, path)
+}
+
+/// Lowercases a path relative to the game directory and gives it forward
+/// slashes, for comparing paths the way Windows does
+pub fn normalize_relative_path(path: &str) -> String {
+    path.replace('\\', "/").to_lowercase()
+}
+
+/// Whether a path stays inside the directory it's relative to, i.e. it's not
+/// absolute, has no drive prefix and no `..` components
+pub fn is_safe_relative_path(path: &str) -> bool {
+    let path = path.replace('\\', "/");
+    !path.is_empty()
+        && !path.starts_with('/')
+        && !path.contains(':')
+        && path.split('/').all(|component| component != "..")
+}
+
+/// MD5 of a file, read in chunks so big .img archives aren't read into memory
+/// at once
+pub fn file_digest(path: &Path) -> io::Result<md5::Digest> {
+    let mut file = File::open(path)?;
+    let mut context = Context::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        context.consume(&buf[..read]);
+    }
+    Ok(context.compute())
+}
+
+fn files_are_equal(a: &Path, b: &Path) -> bool {
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a_meta), Ok(b_meta)) if a_meta.len() == b_meta.len() => {
+            match (file_digest(a), file_digest(b)) {
+                (Ok(a_digest), Ok(b_digest)) => a_digest == b_digest,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn plan_dir(
+    src: &Path,
+    dest: &Path,
+    relative: &Path,
+    policy: OverwritePolicy,
+    plan: &mut CopyPlan,
+) -> Result<(), LauncherError> {
+    let entries = fs::read_dir(src).map_err(|e| map_io_error(e, src))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| map_io_error(e, src))?;
+        let file_type = entry
+            .file_type()
+            .map_err(|e| map_io_error(e, &entry.path()))?;
+        let target = dest.join(entry.file_name());
+        let relative = relative.join(entry.file_name());
+
+        if file_type.is_dir() {
+            if !target.is_dir() {
+                plan.dirs.push(target.clone());
+            }
+            plan_dir(&entry.path(), &target, &relative, policy, plan)?;
+            continue;
+        }
+
+        let relative_str = relative.to_string_lossy().replace('\\', "/");
+        let exists = target.exists();
+        let skip = exists
+            && match policy {
+                OverwritePolicy::Always => false,
+                OverwritePolicy::IfDifferent => files_are_equal(&entry.path(), &target),
+                OverwritePolicy::Never => true,
+            };
+
+        if skip {
+            plan.skipped.push(relative_str);
+        } else {
+            plan.files.push(PlannedFile {
+                source: entry.path(),
+                target,
+                relative: relative_str,
+                replaces_existing: exists,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Walks `src` and works out which directories have to be created and which
+/// files will be copied, replaced or skipped according to `policy`
+pub fn plan_copy(
+    src: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    policy: OverwritePolicy,
+) -> Result<CopyPlan, LauncherError> {
+    let mut plan = CopyPlan::default();
+    if !dest.as_ref().is_dir() {
+        plan.dirs.push(dest.as_ref().to_path_buf());
+    }
+    plan_dir(
+        src.as_ref(),
+        dest.as_ref(),
+        Path::new(""),
+        policy,
+        &mut plan,
+    )?;
+    Ok(plan)
+}
+
+/// Undoes the journaled changes, returns whether all of them could be undone
+fn rollback(journal: Vec<JournalEntry>) -> bool {
+    let mut complete = true;
+    for entry in journal.into_iter().rev() {
+        let result = match &entry {
+            JournalEntry::CreatedFile(path) => fs::remove_file(path),
+            JournalEntry::CreatedDir(path) => fs::remove_dir(path),
+            JournalEntry::ReplacedFile { target, backup } => fs::copy(backup, target).map(|_| ()),
+        };
+
+        if let Err(e) = result {
+            let path = match &entry {
+                JournalEntry::CreatedFile(path) | JournalEntry::CreatedDir(path) => path,
+                JournalEntry::ReplacedFile { target, .. } => target,
+            };
+            info!(
+                "[copy_engine.rs] Rolling back {} failed: {}",
+                path.display(),
+                e
+            );
+            complete = false;
+        }
+    }
+    complete
+}
+
+/// Rolls back after `error` happened. If that leaves changes behind, the
+/// backups are the only intact copies of the overwritten files, so the error
+/// points there instead.
+fn fail(journal: Vec<JournalEntry>, backup_dir: &Path, error: LauncherError) -> LauncherError {
+    if rollback(journal) {
+        return error;
+    }
+
+    LauncherError::RollbackIncomplete(format!(
+        "{}, backups of the overwritten files are kept in {}",
+        error,
+        backup_dir.display()
+    ))
+}
+
+/// Copies the file next to its target first and then renames it into place,
+/// so the target is never left half written
+fn copy_atomically(source: &Path, target: &Path) -> io::Result<()> {
+    let mut temp_name = target.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(TEMP_FILE_SUFFIX);
+    let temp = target.with_file_name(temp_name);
+
+    let result = fs::copy(source, &temp).and_then(|_| fs::rename(&temp, target));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn apply_file(
+    file: &PlannedFile,
+    backup_dir: &Path,
+    journal: &mut Vec<JournalEntry>,
+) -> Result<(), LauncherError> {
+    if file.replaces_existing {
+        let backup = backup_dir.join(&file.relative);
+        if let Some(parent) = backup.parent() {
+            fs::create_dir_all(parent).map_err(|e| map_io_error(e, parent))?;
+        }
+        fs::copy(&file.target, &backup).map_err(|e| map_io_error(e, &file.target))?;
+        copy_atomically(&file.source, &file.target).map_err(|e| map_io_error(e, &file.target))?;
+        journal.push(JournalEntry::ReplacedFile {
+            target: file.target.clone(),
+            backup,
+        });
+    } else {
+        copy_atomically(&file.source, &file.target).map_err(|e| map_io_error(e, &file.target))?;
+        journal.push(JournalEntry::CreatedFile(file.target.clone()));
+    }
+    Ok(())
+}
+
+/// Runs a copy plan. Files about to be overwritten are backed up into
+/// `backup_dir` first; if anything fails, every change made so far is undone.
+/// The backups are left in place, it's up to the caller to keep or remove them,
+/// but they must be kept on `LauncherError::RollbackIncomplete`.
+pub fn execute_copy(
+    plan: &CopyPlan,
+    backup_dir: &Path,
+    on_progress: impl Fn(CopyProgress),
+) -> Result<CopyReport, LauncherError> {
+    let mut journal: Vec<JournalEntry> = Vec::new();
+    let mut report = CopyReport {
+        skipped: plan.skipped.clone(),
+        ..Default::default()
+    };
+
+    for dir in &plan.dirs {
+        if let Err(e) = fs::create_dir(dir) {
+            if e.kind() != io::ErrorKind::AlreadyExists {
+                return Err(fail(journal, backup_dir, map_io_error(e, dir)));
+            }
+        } else {
+            journal.push(JournalEntry::CreatedDir(dir.clone()));
+        }
+    }
+
+    let total = plan.files.len();
+    for (i, file) in plan.files.iter().enumerate() {
+        on_progress(CopyProgress {
+            current: i + 1,
+            total,
+            file: file.relative.clone(),
+        });
+
+        if let Err(e) = apply_file(file, backup_dir, &mut journal) {
+            return Err(fail(journal, backup_dir, e));
+        }
+
+        if file.replaces_existing {
+            report.replaced.push(file.relative.clone());
+        } else {
+            report.created.push(file.relative.clone());
+        }
+    }
+
+    Ok(report)
+}
+
+/// Copies a directory tree into `dest` as a single transaction, backups of
+/// overwritten files are only kept until the copy has succeeded or was rolled
+/// back. They go into a hidden directory inside `dest`, which is writable if
+/// the copy is possible at all and keeps restoring them on the same file system.
+pub fn copy_tree(
+    src: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    policy: OverwritePolicy,
+    on_progress: impl Fn(CopyProgress),
+) -> Result<CopyReport, LauncherError> {
+    let plan = plan_copy(&src, &dest, policy)?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let backup_dir = dest.as_ref().join(format!(
+        "{}{}-{}",
+        BACKUP_DIR_PREFIX,
+        std::process::id(),
+        timestamp
+    ));
+
+    let result = execute_copy(&plan, &backup_dir, on_progress);
+    if !matches!(result, Err(LauncherError::RollbackIncomplete(_))) {
+        let _ = fs::remove_dir_all(&backup_dir);
+    }
+    result
+}
+
+/// Copies a directory tree into the game directory, emitting `copy-progress` events
+#[tauri::command]
+pub async fn copy_files_to_game_dir(
+    window: tauri::Window,
+    src: String,
+    dest: String,
+    overwrite_policy: Option<OverwritePolicy>,
+) -> Result<CopyReport, LauncherError> {
+    tokio::task::spawn_blocking(move || {
+        copy_tree(
+            &src,
+            &dest,
+            overwrite_policy.unwrap_or_default(),
+            |progress| {
+                let _ = window.emit("copy-progress", progress);
+            },
+        )
+    })
+    .await
+    .map_err(|e| LauncherError::Other(e.to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "omp-copy-engine-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    fn no_progress(_: CopyProgress) {}
+
+    #[test]
+    fn merges_existing_subtrees() {
+        let dir = test_dir("merge");
+        let (src, dest) = (dir.join("src"), dir.join("dest"));
+        write(&src.join("models/new.txd"), "new");
+        write(&src.join("cleo/mod.cs"), "mod");
+        write(&dest.join("models/gta3.img"), "original");
+
+        let report = copy_tree(&src, &dest, OverwritePolicy::Always, no_progress).unwrap();
+        let mut created = report.created.clone();
+        created.sort();
+        assert_eq!(created, vec!["cleo/mod.cs", "models/new.txd"]);
+        assert_eq!(read(&dest.join("models/new.txd")), "new");
+        assert_eq!(read(&dest.join("models/gta3.img")), "original");
+        assert_eq!(read(&dest.join("cleo/mod.cs")), "mod");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn follows_the_overwrite_policy() {
+        let cases = [
+            (
+                OverwritePolicy::Always,
+                vec!["changed.txt", "same.txt"],
+                "new",
+            ),
+            (OverwritePolicy::IfDifferent, vec!["changed.txt"], "new"),
+            (OverwritePolicy::Never, vec![], "old"),
+        ];
+
+        for (policy, expected_replaced, expected_contents) in cases {
+            let dir = test_dir("policy");
+            let (src, dest) = (dir.join("src"), dir.join("dest"));
+            write(&src.join("changed.txt"), "new");
+            write(&src.join("same.txt"), "same");
+            write(&src.join("created.txt"), "created");
+            write(&dest.join("changed.txt"), "old");
+            write(&dest.join("same.txt"), "same");
+
+            let report = copy_tree(&src, &dest, policy, no_progress).unwrap();
+            let mut replaced = report.replaced.clone();
+            replaced.sort();
+            assert_eq!(replaced, expected_replaced, "{:?}", policy);
+            assert_eq!(report.created, vec!["created.txt"], "{:?}", policy);
+            assert_eq!(
+                report.replaced.len() + report.skipped.len(),
+                2,
+                "{:?}",
+                policy
+            );
+            assert_eq!(read(&dest.join("changed.txt")), expected_contents);
+            assert_eq!(read(&dest.join("same.txt")), "same");
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn keeps_backups_inside_dest_only_while_copying() {
+        let dir = test_dir("backups");
+        let (src, dest) = (dir.join("src"), dir.join("dest"));
+        write(&src.join("gta_sa.set"), "new");
+        write(&src.join("gta3.ini"), "new");
+        write(&dest.join("gta_sa.set"), "old");
+        write(&dest.join("gta3.ini"), "old");
+
+        let backup_dirs = |path: &Path| {
+            fs::read_dir(path)
+                .unwrap()
+                .filter(|entry| {
+                    entry
+                        .as_ref()
+                        .unwrap()
+                        .file_name()
+                        .to_string_lossy()
+                        .starts_with(BACKUP_DIR_PREFIX)
+                })
+                .count()
+        };
+
+        // By the second file, the first one has been backed up
+        let seen_during_copy = std::cell::Cell::new((0, 0));
+        copy_tree(&src, &dest, OverwritePolicy::Always, |progress| {
+            if progress.current == 2 {
+                seen_during_copy.set((backup_dirs(&dest), backup_dirs(&dir)));
+            }
+        })
+        .unwrap();
+
+        assert_eq!(seen_during_copy.get(), (1, 0));
+        assert_eq!((backup_dirs(&dest), backup_dirs(&dir)), (0, 0));
+        assert_eq!(read(&dest.join("gta_sa.set")), "new");
+        assert_eq!(read(&dest.join("gta3.ini")), "new");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// A plan replacing `a.txt`, creating `sub/b.txt` and then failing on a
+    /// source that doesn't exist
+    fn failing_plan(dir: &Path) -> (CopyPlan, PathBuf) {
+        let (src, dest) = (dir.join("src"), dir.join("dest"));
+        write(&src.join("a.txt"), "new a");
+        write(&src.join("sub/b.txt"), "new b");
+        write(&dest.join("a.txt"), "original a");
+
+        let file = |name: &str, replaces_existing: bool| PlannedFile {
+            source: src.join(name),
+            target: dest.join(name),
+            relative: name.to_string(),
+            replaces_existing,
+        };
+        let plan = CopyPlan {
+            dirs: vec![dest.join("sub")],
+            files: vec![
+                file("a.txt", true),
+                file("sub/b.txt", false),
+                file("missing.txt", false),
+            ],
+            skipped: Vec::new(),
+        };
+        (plan, dest)
+    }
+
+    #[test]
+    fn rolls_back_a_failed_copy() {
+        let dir = test_dir("rollback");
+        let (plan, dest) = failing_plan(&dir);
+        let backup_dir = dest.join(".backup");
+
+        let result = execute_copy(&plan, &backup_dir, no_progress);
+        assert!(matches!(result, Err(LauncherError::NotFound(_))));
+        assert_eq!(read(&dest.join("a.txt")), "original a");
+        assert!(!dest.join("sub").exists());
+        assert!(!dest.join("missing.txt").exists());
+        assert_eq!(read(&backup_dir.join("a.txt")), "original a");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_incomplete_rollbacks() {
+        let dir = test_dir("rollback-incomplete");
+        let (plan, dest) = failing_plan(&dir);
+        let backup_dir = dest.join(".backup");
+
+        // Something else puts a file into a directory the copy created, so it
+        // can't be removed again
+        let result = execute_copy(&plan, &backup_dir, |progress| {
+            if progress.file == "missing.txt" {
+                write(&dest.join("sub/foreign.txt"), "");
+            }
+        });
+        match result {
+            Err(LauncherError::RollbackIncomplete(details)) => {
+                assert!(details.contains(&backup_dir.display().to_string()))
+            }
+            _ => panic!("expected RollbackIncomplete"),
+        }
+        // Everything else is still undone
+        assert_eq!(read(&dest.join("a.txt")), "original a");
+        assert!(!dest.join("sub/b.txt").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
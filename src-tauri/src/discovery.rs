@@ -0,0 +1,502 @@
+use log::info;
+use rayon::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::gta_exe::{self, GtaExeVersion};
+use crate::process::{self, GAME_EXECUTABLE};
+
+/// Steam app id of GTA: San Andreas
+const GTA_SA_STEAM_APP_ID: &str = "12120";
+
+/// How deep to look for gta_sa.exe below a prefix's drive_c or a user root
+const MAX_SCAN_DEPTH: usize = 5;
+
+/// Directories that never contain a game but would take long to walk through
+const SKIPPED_DIRS: [&str; 6] = [
+    "windows",
+    "programdata",
+    "appdata",
+    "node_modules",
+    ".git",
+    "$recycle.bin",
+];
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CandidateSource {
+    /// Installed through Steam, in one of its library folders
+    Steam,
+    /// Inside a Proton prefix (`steamapps/compatdata/<app id>/pfx`)
+    Proton,
+    Wine,
+    Lutris,
+    Bottles,
+    /// Below a directory the user asked us to search
+    UserRoot,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InstallCandidate {
+    /// Directory containing gta_sa.exe
+    pub path: String,
+    pub source: CandidateSource,
+    /// Wine/Proton prefix the game lives in, if any
+    pub prefix: Option<String>,
+    pub exe: Option<GtaExeVersion>,
+}
+
+fn home_dir() -> Option<PathBuf> {
+    dirs_next::home_dir()
+}
+
+/// Looks for gta_sa.exe in `root` and the directories below it, up to
+/// `max_depth` levels deep
+fn find_game_dirs(root: &Path, max_depth: usize) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut pending = vec![(root.to_path_buf(), 0)];
+
+    while let Some((dir, depth)) = pending.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(_) => continue,
+            };
+            let name = entry.file_name().to_string_lossy().to_lowercase();
+
+            // Symlinks are skipped on purpose, Wine prefixes link the whole
+            // home directory and `dosdevices` back to `/`
+            if file_type.is_file() && name == GAME_EXECUTABLE {
+                found.push(dir.clone());
+            } else if file_type.is_dir()
+                && depth < max_depth
+                && !SKIPPED_DIRS.contains(&name.as_str())
+            {
+                pending.push((entry.path(), depth + 1));
+            }
+        }
+    }
+
+    found
+}
+
+/// Pulls the library paths out of Steam's `libraryfolders.vdf`. Both the
+/// current format (`"path" "..."` inside numbered blocks) and the old one
+/// (`"1" "..."` directly) are understood.
+pub fn parse_library_folders(vdf: &str) -> Vec<PathBuf> {
+    let re = Regex::new(r#"^\s*"(path|\d+)"\s+"((?:[^"\\]|\\.)*)""#).unwrap();
+    let unescape = Regex::new(r"\\(.)").unwrap();
+
+    vdf.lines()
+        .filter_map(|line| re.captures(line))
+        .map(|captures| unescape.replace_all(&captures[2], "$1").to_string())
+        // Numbered keys in the new format hold blocks, not paths
+        .filter(|value| value.contains('/') || value.contains('\\'))
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Default Steam directories of native, Flatpak and Snap installs
+fn steam_roots() -> Vec<PathBuf> {
+    if cfg!(target_os = "windows") {
+        return vec![PathBuf::from(r"C:\Program Files (x86)\Steam")];
+    }
+
+    let home = match home_dir() {
+        Some(home) => home,
+        None => return Vec::new(),
+    };
+
+    vec![
+        home.join(".steam/steam"),
+        home.join(".local/share/Steam"),
+        home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"),
+        home.join("snap/steam/common/.local/share/Steam"),
+    ]
+}
+
+fn steam_libraries() -> Vec<PathBuf> {
+    let mut libraries = Vec::new();
+    for root in steam_roots() {
+        let vdf = root.join("steamapps/libraryfolders.vdf");
+        if let Ok(content) = fs::read_to_string(&vdf) {
+            libraries.extend(parse_library_folders(&content));
+        }
+        libraries.push(root);
+    }
+    libraries
+}
+
+/// Prefixes listed in Lutris game configs (`prefix: /path/to/prefix`)
+fn parse_lutris_prefixes(config: &str) -> Vec<PathBuf> {
+    let re = Regex::new(r#"^\s*prefix:\s*['"]?([^'"]+?)['"]?\s*$"#).unwrap();
+    config
+        .lines()
+        .filter_map(|line| re.captures(line))
+        .map(|captures| PathBuf::from(&captures[1]))
+        .collect()
+}
+
+/// Wine prefixes in their usual places
+fn wine_prefixes() -> Vec<(PathBuf, CandidateSource)> {
+    let mut prefixes = Vec::new();
+
+    if let Ok(prefix) = std::env::var("WINEPREFIX") {
+        prefixes.push((PathBuf::from(prefix), CandidateSource::Wine));
+    }
+
+    if let Some(home) = home_dir() {
+        prefixes.extend(wine_prefixes_in(&home));
+    }
+    prefixes
+}
+
+/// Wine prefixes of plain Wine, Lutris and Bottles below a home directory
+fn wine_prefixes_in(home: &Path) -> Vec<(PathBuf, CandidateSource)> {
+    let mut prefixes = vec![(home.join(".wine"), CandidateSource::Wine)];
+
+    // Lutris installs each game in its own prefix under ~/Games by default,
+    // prefixes elsewhere are listed in the game configs
+    if let Ok(entries) = fs::read_dir(home.join("Games")) {
+        for entry in entries.flatten() {
+            prefixes.push((entry.path(), CandidateSource::Lutris));
+        }
+    }
+
+    if let Ok(entries) = fs::read_dir(home.join(".config/lutris/games")) {
+        for entry in entries.flatten() {
+            if let Ok(content) = fs::read_to_string(entry.path()) {
+                for prefix in parse_lutris_prefixes(&content) {
+                    prefixes.push((prefix, CandidateSource::Lutris));
+                }
+            }
+        }
+    }
+
+    for bottles in [
+        home.join(".local/share/bottles/bottles"),
+        home.join(".var/app/com.usebottles.bottles/data/bottles/bottles"),
+    ] {
+        if let Ok(entries) = fs::read_dir(bottles) {
+            for entry in entries.flatten() {
+                prefixes.push((entry.path(), CandidateSource::Bottles));
+            }
+        }
+    }
+
+    prefixes
+}
+
+/// A directory to search, how it was found, the prefix it belongs to and how
+/// deep to search it
+type SearchRoot = (PathBuf, CandidateSource, Option<PathBuf>, usize);
+
+/// Places to search in a Steam library: its games and its Proton prefixes
+fn library_roots(library: &Path) -> Vec<SearchRoot> {
+    let steamapps = library.join("steamapps");
+    let mut roots = vec![(steamapps.join("common"), CandidateSource::Steam, None, 2)];
+
+    // The game's own Proton prefix, plus prefixes of non-Steam shortcuts
+    // people commonly add a downgraded copy of the game as
+    if let Ok(entries) = fs::read_dir(steamapps.join("compatdata")) {
+        for entry in entries.flatten() {
+            let prefix = entry.path().join("pfx");
+            let is_game_prefix = entry.file_name() == GTA_SA_STEAM_APP_ID;
+            roots.push((
+                prefix.join("drive_c"),
+                CandidateSource::Proton,
+                Some(prefix),
+                if is_game_prefix { MAX_SCAN_DEPTH } else { 3 },
+            ));
+        }
+    }
+    roots
+}
+
+/// Every place to search, along with how deep to search it
+fn search_roots(user_roots: &[String]) -> Vec<SearchRoot> {
+    let mut roots = Vec::new();
+
+    for library in steam_libraries() {
+        roots.extend(library_roots(&library));
+    }
+
+    for (prefix, source) in wine_prefixes() {
+        roots.push((prefix.join("drive_c"), source, Some(prefix), MAX_SCAN_DEPTH));
+    }
+
+    for root in user_roots {
+        roots.push((
+            PathBuf::from(root),
+            CandidateSource::UserRoot,
+            None,
+            MAX_SCAN_DEPTH,
+        ));
+    }
+
+    roots
+}
+
+/// Wine and Proton prefixes that have a user registry (`user.reg`), the plain
+/// ones first since that's where a manually installed SA-MP usually lives
+pub fn find_wine_prefixes() -> Vec<PathBuf> {
+    let mut prefixes: Vec<PathBuf> = wine_prefixes()
+        .into_iter()
+        .map(|(prefix, _)| prefix)
+        .collect();
+
+    for library in steam_libraries() {
+        if let Ok(entries) = fs::read_dir(library.join("steamapps/compatdata")) {
+            prefixes.extend(entries.flatten().map(|entry| entry.path().join("pfx")));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    prefixes
+        .into_iter()
+        .filter(|prefix| prefix.join("user.reg").is_file())
+        .filter(|prefix| seen.insert(prefix.canonicalize().unwrap_or(prefix.clone())))
+        .collect()
+}
+
+/// Searches Steam libraries, Proton and Wine prefixes (plain, Lutris and
+/// Bottles) and the given directories for GTA: San Andreas installations
+pub fn discover_installations(user_roots: &[String]) -> Vec<InstallCandidate> {
+    let mut seen = HashSet::new();
+    let roots: Vec<_> = search_roots(user_roots)
+        .into_iter()
+        .filter(|(root, ..)| root.is_dir())
+        // The same directory is often reachable through several Steam roots
+        .filter(|(root, ..)| seen.insert(root.canonicalize().unwrap_or(root.clone())))
+        .collect();
+
+    let found: Vec<(PathBuf, CandidateSource, Option<PathBuf>)> = roots
+        .par_iter()
+        .flat_map(|(root, source, prefix, depth)| {
+            find_game_dirs(root, *depth)
+                .into_iter()
+                .map(|dir| (dir, *source, prefix.clone()))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let mut candidates: Vec<InstallCandidate> = Vec::new();
+    for (dir, source, prefix) in found {
+        let path = dir.to_string_lossy().to_string();
+        if candidates
+            .iter()
+            .any(|candidate| process::is_same_game_dir(&candidate.path, &path))
+        {
+            continue;
+        }
+
+        candidates.push(InstallCandidate {
+            exe: gta_exe::get_gta_exe_version(&path).ok(),
+            path,
+            source,
+            prefix: prefix.map(|prefix| prefix.to_string_lossy().to_string()),
+        });
+    }
+
+    info!(
+        "[discovery.rs] Found {} game installation(s)",
+        candidates.len()
+    );
+    candidates
+}
+
+/// Searches the usual install locations, plus `roots`, for GTA: San Andreas
+#[tauri::command]
+pub async fn discover_gta_installations(roots: Option<Vec<String>>) -> Vec<InstallCandidate> {
+    // Walking prefixes and hashing every executable found takes a while
+    tokio::task::spawn_blocking(move || discover_installations(&roots.unwrap_or_default()))
+        .await
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// libraryfolders.vdf as written by current Steam clients
+    const LIBRARY_FOLDERS: &str = r#""libraryfolders"
+{
+	"0"
+	{
+		"path"		"C:\\Program Files (x86)\\Steam"
+		"label"		""
+		"contentid"		"3000946524478306394"
+		"totalsize"		"0"
+		"update_clean_bytes_tally"		"1045416014"
+		"time_last_update_corruption"		"0"
+		"apps"
+		{
+			"228980"		"389338938"
+			"1493710"		"1181426483"
+		}
+	}
+	"1"
+	{
+		"path"		"/home/user/.local/share/Steam"
+		"label"		""
+		"contentid"		"7513469857391279401"
+		"totalsize"		"0"
+		"update_clean_bytes_tally"		"0"
+		"time_last_update_corruption"		"0"
+		"apps"
+		{
+			"12120"		"4836286238"
+		}
+	}
+	"2"
+	{
+		"path"		"D:\\Games\\Steam \"Library\""
+		"label"		"Games"
+		"contentid"		"1837419024912781004"
+		"totalsize"		"1000202039296"
+		"apps"
+		{
+		}
+	}
+}
+"#;
+
+    /// The format older Steam clients wrote
+    const OLD_LIBRARY_FOLDERS: &str = r#""LibraryFolders"
+{
+	"TimeNextStatsReport"		"1588927624"
+	"ContentStatsID"		"-5392507567283622880"
+	"1"		"D:\\SteamLibrary"
+	"2"		"/mnt/games/SteamLibrary"
+}
+"#;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "omp-discovery-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parses_library_folders() {
+        assert_eq!(
+            parse_library_folders(LIBRARY_FOLDERS),
+            vec![
+                PathBuf::from(r"C:\Program Files (x86)\Steam"),
+                PathBuf::from("/home/user/.local/share/Steam"),
+                PathBuf::from(r#"D:\Games\Steam "Library""#),
+            ]
+        );
+        assert_eq!(
+            parse_library_folders(OLD_LIBRARY_FOLDERS),
+            vec![
+                PathBuf::from(r"D:\SteamLibrary"),
+                PathBuf::from("/mnt/games/SteamLibrary"),
+            ]
+        );
+        assert!(parse_library_folders("").is_empty());
+        assert!(parse_library_folders("\"libraryfolders\"\n{\n}\n").is_empty());
+    }
+
+    #[test]
+    fn derives_proton_prefixes_from_libraries() {
+        let library = test_dir("library");
+        for app_id in [GTA_SA_STEAM_APP_ID, "2147483649"] {
+            fs::create_dir_all(
+                library
+                    .join("steamapps/compatdata")
+                    .join(app_id)
+                    .join("pfx"),
+            )
+            .unwrap();
+        }
+
+        let mut roots = library_roots(&library);
+        roots.sort_by_key(|(root, ..)| root.clone());
+        let game_prefix = library.join("steamapps/compatdata/12120/pfx");
+        let shortcut_prefix = library.join("steamapps/compatdata/2147483649/pfx");
+        assert_eq!(
+            roots,
+            vec![
+                (
+                    library.join("steamapps/common"),
+                    CandidateSource::Steam,
+                    None,
+                    2
+                ),
+                (
+                    game_prefix.join("drive_c"),
+                    CandidateSource::Proton,
+                    Some(game_prefix),
+                    MAX_SCAN_DEPTH
+                ),
+                (
+                    shortcut_prefix.join("drive_c"),
+                    CandidateSource::Proton,
+                    Some(shortcut_prefix),
+                    3
+                ),
+            ]
+        );
+
+        fs::remove_dir_all(&library).unwrap();
+    }
+
+    #[test]
+    fn derives_wine_lutris_and_bottles_prefixes() {
+        let home = test_dir("home");
+        fs::create_dir_all(home.join("Games/gta-san-andreas")).unwrap();
+        fs::create_dir_all(home.join(".local/share/bottles/bottles/GTA")).unwrap();
+        fs::create_dir_all(home.join(".config/lutris/games")).unwrap();
+        fs::write(
+            home.join(".config/lutris/games/gta-san-andreas-1700000000.yml"),
+            "game:\n  exe: /mnt/games/gta/gta_sa.exe\n  prefix: '/mnt/games/wine prefix'\nwine:\n  version: lutris-7.2\n",
+        )
+        .unwrap();
+
+        let mut prefixes = wine_prefixes_in(&home);
+        prefixes.sort_by_key(|(prefix, _)| prefix.clone());
+        let mut expected = vec![
+            (home.join(".wine"), CandidateSource::Wine),
+            (home.join("Games/gta-san-andreas"), CandidateSource::Lutris),
+            (
+                PathBuf::from("/mnt/games/wine prefix"),
+                CandidateSource::Lutris,
+            ),
+            (
+                home.join(".local/share/bottles/bottles/GTA"),
+                CandidateSource::Bottles,
+            ),
+        ];
+        expected.sort_by_key(|(prefix, _)| prefix.clone());
+        assert_eq!(prefixes, expected);
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn parses_lutris_prefixes() {
+        assert_eq!(
+            parse_lutris_prefixes("game:\n  prefix: /home/user/Games/gta\n"),
+            vec![PathBuf::from("/home/user/Games/gta")]
+        );
+        assert_eq!(
+            parse_lutris_prefixes("game:\n  prefix: \"/home/user/Games/gta sa\"\n"),
+            vec![PathBuf::from("/home/user/Games/gta sa")]
+        );
+        assert!(parse_lutris_prefixes("game:\n  exe: gta_sa.exe\n").is_empty());
+    }
+}
@@ -0,0 +1,755 @@
+use encoding_rs::{
+    Encoding, EUC_KR, GB18030, SHIFT_JIS, UTF_8, WINDOWS_1250, WINDOWS_1251, WINDOWS_1252,
+    WINDOWS_1253, WINDOWS_1254, WINDOWS_1255, WINDOWS_1256, WINDOWS_1257, WINDOWS_1258,
+    WINDOWS_874,
+};
+use log::info;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::helpers;
+
+/// Fewest non-ASCII bytes a sample needs for the encoding detected in it to
+/// be remembered for the server. The detectors easily mistake a short
+/// hostname for another code page.
+const MIN_REMEMBERED_SAMPLE: usize = 12;
+
+/// Most servers an encoding is remembered for, the oldest are forgotten first
+const MAX_SERVER_ENCODINGS: usize = 4096;
+
+/// Environment variable that makes the launcher log every non-ASCII server
+/// string it decodes as a `data/charset_corpus.json` entry
+const CAPTURE_SAMPLES_VAR: &str = "OMP_LAUNCHER_CAPTURE_CHARSETS";
+
+static CAPTURE_SAMPLES: Lazy<bool> = Lazy::new(|| std::env::var_os(CAPTURE_SAMPLES_VAR).is_some());
+
+/// Encodings detected in servers' rules and other strings, keyed by
+/// "ip:port", so the detectors don't have to run again for every refresh of
+/// the list
+static SERVER_ENCODINGS: Lazy<Mutex<ServerEncodings>> =
+    Lazy::new(|| Mutex::new(ServerEncodings::default()));
+
+#[derive(Default)]
+struct ServerEncodings {
+    /// Hints along with when they were remembered, counted in `remembered`
+    hints: HashMap<String, (EncodingHint, u64)>,
+    remembered: u64,
+}
+
+impl ServerEncodings {
+    fn get(&self, key: &str) -> Option<EncodingHint> {
+        self.hints.get(key).map(|(hint, _)| *hint)
+    }
+
+    /// Remembers `hint` for a server. One learned from the server's rules
+    /// isn't replaced by one detected in its other strings.
+    fn remember(&mut self, key: String, hint: EncodingHint) {
+        match self.get(&key) {
+            Some(existing)
+                if existing.source == EncodingSource::Rules
+                    && hint.source != EncodingSource::Rules =>
+            {
+                return
+            }
+            Some(_) => {}
+            None if self.hints.len() >= MAX_SERVER_ENCODINGS => {
+                let oldest = self
+                    .hints
+                    .iter()
+                    .min_by_key(|(_, (_, remembered))| *remembered)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    self.hints.remove(&oldest);
+                }
+            }
+            None => {}
+        }
+
+        self.remembered += 1;
+        self.hints.insert(key, (hint, self.remembered));
+    }
+
+    /// Forgets the hint remembered for a server if it came from `source`
+    fn forget(&mut self, key: &str, source: EncodingSource) {
+        if self.get(key).is_some_and(|hint| hint.source == source) {
+            self.hints.remove(key);
+        }
+    }
+}
+
+fn non_ascii_len(buf: &[u8]) -> usize {
+    buf.iter().filter(|byte| !byte.is_ascii()).count()
+}
+
+/// Words in a server's `language` field and the code page servers in that
+/// language usually send text in. Matched against the lower-cased field.
+const LANGUAGE_ENCODINGS: [(&[&str], &Encoding); 13] = [
+    (
+        &[
+            "russian",
+            "русский",
+            "ukrain",
+            "україн",
+            "belarus",
+            "bulgar",
+            "serbian",
+            "kazakh",
+            "cyrillic",
+        ],
+        WINDOWS_1251,
+    ),
+    (
+        &["chinese", "中文", "汉语", "漢語", "简体", "繁體"],
+        GB18030,
+    ),
+    (&["japanese", "日本語"], SHIFT_JIS),
+    (&["korean", "한국어"], EUC_KR),
+    (
+        &[
+            "polish", "polski", "czech", "česk", "slovak", "sloven", "hungar", "magyar", "romania",
+            "român", "croatia", "hrvatski", "bosnia",
+        ],
+        WINDOWS_1250,
+    ),
+    (&["turkish", "türk", "turk", "azer"], WINDOWS_1254),
+    (&["greek", "ελληνικ"], WINDOWS_1253),
+    (&["hebrew", "עברית"], WINDOWS_1255),
+    (&["arab", "عربي", "persian", "farsi"], WINDOWS_1256),
+    (
+        &["lithuan", "lietuv", "latvia", "latvie", "estonia", "eesti"],
+        WINDOWS_1257,
+    ),
+    (&["vietnam", "tiếng việt"], WINDOWS_1258),
+    (&["thai", "ไทย"], WINDOWS_874),
+    (
+        &[
+            "english",
+            "spanish",
+            "español",
+            "portug",
+            "brasil",
+            "brazil",
+            "french",
+            "français",
+            "german",
+            "deutsch",
+            "italian",
+            "italiano",
+            "dutch",
+            "nederlands",
+            "indonesia",
+            "bahasa",
+            "malay",
+            "filipino",
+            "tagalog",
+        ],
+        WINDOWS_1252,
+    ),
+];
+
+/// Where the encoding a string was decoded with came from
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum EncodingSource {
+    /// Set by the user for the server
+    Override,
+    /// Detected from the server's rules, which are longer than its hostname
+    /// and so detected more reliably
+    Rules,
+    /// Guessed from the server's `language` field
+    Language,
+    /// Detected from the string itself
+    #[default]
+    Detected,
+}
+
+/// An encoding to prefer for a server's strings, and where it came from
+#[derive(Clone, Copy)]
+pub struct EncodingHint {
+    pub encoding: &'static Encoding,
+    pub source: EncodingSource,
+}
+
+fn server_key(ip: &str, port: i32) -> String {
+    format!("{}:{}", ip, port)
+}
+
+/// The code page a `language` field suggests, if it names a language we know
+pub fn encoding_for_language(language: &str) -> Option<&'static Encoding> {
+    let language = language.to_lowercase();
+    LANGUAGE_ENCODINGS
+        .iter()
+        .find(|(words, _)| words.iter().any(|word| language.contains(word)))
+        .map(|(_, encoding)| *encoding)
+}
+
+/// Detects the encoding of a server's rule values and remembers it for the
+/// server. All non-ASCII values are detected together, which is a lot more
+/// reliable than a short hostname on its own. Only legacy code pages are
+/// remembered, text that's valid UTF-8 needs no hint, and nothing is when
+/// the values hold too little non-ASCII text to go on.
+pub fn learn_from_rules(ip: &str, port: i32, values: &[Vec<u8>]) -> Option<&'static Encoding> {
+    let mut sample = Vec::new();
+    for value in values.iter().filter(|value| !value.is_ascii()) {
+        sample.extend_from_slice(value);
+        sample.push(b' ');
+    }
+
+    if non_ascii_len(&sample) < MIN_REMEMBERED_SAMPLE || std::str::from_utf8(&sample).is_ok() {
+        return None;
+    }
+
+    let key = server_key(ip, port);
+    let learned = SERVER_ENCODINGS
+        .lock()
+        .ok()
+        .and_then(|encodings| encodings.get(&key))
+        .filter(|hint| hint.source == EncodingSource::Rules)
+        .map(|hint| hint.encoding);
+    // Rules rarely change, keep what was learned while it still fits
+    if let Some(encoding) = learned.filter(|encoding| !encoding.decode(&sample).2) {
+        return Some(encoding);
+    }
+
+    let (_, name) = helpers::decode_buffer(sample, None);
+    let encoding = Encoding::for_label(name.as_bytes())?;
+    if let Ok(mut encodings) = SERVER_ENCODINGS.lock() {
+        encodings.remember(
+            key,
+            EncodingHint {
+                encoding,
+                source: EncodingSource::Rules,
+            },
+        );
+    }
+    Some(encoding)
+}
+
+/// Picks the hint to decode a server's strings with: the user's override
+/// first, then the encoding learned from its rules or detected in its other
+/// strings, and last its language
+pub fn resolve_hint(
+    ip: &str,
+    port: i32,
+    encoding_override: Option<&str>,
+    language: Option<&str>,
+) -> Option<EncodingHint> {
+    if let Some(encoding) =
+        encoding_override.and_then(|label| Encoding::for_label(label.as_bytes()))
+    {
+        return Some(EncodingHint {
+            encoding,
+            source: EncodingSource::Override,
+        });
+    }
+
+    let remembered = SERVER_ENCODINGS
+        .lock()
+        .ok()
+        .and_then(|encodings| encodings.get(&server_key(ip, port)));
+    if remembered.is_some() {
+        return remembered;
+    }
+
+    language
+        .and_then(encoding_for_language)
+        .map(|encoding| EncodingHint {
+            encoding,
+            source: EncodingSource::Language,
+        })
+}
+
+/// Decodes a server string with a hint, reporting which encoding was used and
+/// whether it came from the hint or from detection. Valid UTF-8 always wins.
+/// Hints the user set or that were detected from a longer sample of the
+/// server's text are used as long as the string decodes without errors in
+/// them. A language hint only breaks ties between the detectors, since a
+/// string in a single-byte code page decodes without errors in any other.
+pub fn decode_with_hint(
+    buf: Vec<u8>,
+    hint: Option<EncodingHint>,
+) -> (String, String, EncodingSource) {
+    let trusted = hint.filter(|hint| hint.source != EncodingSource::Language);
+    if let Some(hint) = trusted {
+        if std::str::from_utf8(&buf).is_err() {
+            let (decoded, _, had_errors) = hint.encoding.decode(&buf);
+            if !had_errors {
+                return (
+                    decoded.into_owned(),
+                    hint.encoding.name().to_string(),
+                    hint.source,
+                );
+            }
+        }
+    }
+
+    let (text, name) = helpers::decode_buffer(buf, hint.map(|hint| hint.encoding));
+    let source = match hint {
+        Some(hint) if hint.encoding.name() == name => hint.source,
+        _ => EncodingSource::Detected,
+    };
+    (text, name, source)
+}
+
+/// Like `decode_with_hint`, for a string sent by the server at `ip:port`.
+/// Whatever the detectors settle on for a long enough string is remembered
+/// for the server and used as the hint next time, unless a better one is
+/// known. A remembered hint the string doesn't decode in is forgotten.
+pub fn decode_for_server(
+    ip: &str,
+    port: i32,
+    buf: Vec<u8>,
+    hint: Option<EncodingHint>,
+) -> (String, String, EncodingSource) {
+    let sample_len = non_ascii_len(&buf);
+    let (text, name, source) = decode_with_hint(buf, hint);
+
+    let encoding = Encoding::for_label(name.as_bytes()).unwrap_or(UTF_8);
+    // Whether the detectors ran, rather than the string being valid UTF-8 or
+    // decoded in a hint that's used as is
+    let detected = encoding != UTF_8
+        && hint.is_none_or(|hint| {
+            hint.source == EncodingSource::Language || hint.encoding != encoding
+        });
+
+    if detected {
+        if let Ok(mut encodings) = SERVER_ENCODINGS.lock() {
+            let key = server_key(ip, port);
+            if sample_len >= MIN_REMEMBERED_SAMPLE {
+                encodings.remember(
+                    key,
+                    EncodingHint {
+                        encoding,
+                        source: EncodingSource::Detected,
+                    },
+                );
+            } else if hint.is_some_and(|hint| hint.source == EncodingSource::Detected) {
+                encodings.forget(&key, EncodingSource::Detected);
+            }
+        }
+    }
+
+    (text, name, source)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Whether server strings are to be passed to `capture_sample`
+pub fn capturing_samples() -> bool {
+    *CAPTURE_SAMPLES
+}
+
+/// A string as sent by a live server, in the form `data/charset_corpus.json`
+/// holds it. `text` and `encoding` are what the launcher made of it, they
+/// have to be checked by someone who reads the language before the entry is
+/// added to the corpus.
+fn captured_corpus_entry(kind: &str, bytes: &[u8], text: &str, encoding: &str) -> String {
+    serde_json::json!({
+        "kind": kind,
+        "source": "captured",
+        "bytes": to_hex(bytes),
+        "text": text,
+        "encoding": encoding,
+    })
+    .to_string()
+}
+
+/// Logs a decoded server string for the charset corpus, see
+/// `CAPTURE_SAMPLES_VAR`. ASCII strings decode the same either way and are
+/// skipped.
+pub fn capture_sample(ip: &str, port: i32, kind: &str, bytes: &[u8], text: &str, encoding: &str) {
+    if non_ascii_len(bytes) == 0 {
+        return;
+    }
+
+    info!(
+        "[encoding.rs] Charset sample from {}: {}",
+        server_key(ip, port),
+        captured_corpus_entry(kind, bytes, text, encoding)
+    );
+}
+
+/// Debugging aid for misdecoded server strings: logs and returns what each
+/// detector makes of `bytes`. The detectors run even on ASCII and UTF-8,
+/// which `decode_buffer` itself never hands them. The bytes are logged hex
+/// encoded as well, the way `data/charset_corpus.json` holds them.
+#[tauri::command]
+pub fn debug_detect_encoding(bytes: Vec<u8>) -> helpers::DetectorVerdicts {
+    let verdicts = helpers::detector_verdicts(&bytes, None);
+    info!(
+        "[encoding.rs] {:?} ({}): chardetng {}, chardet {}, charset_normalizer {}, chosen {}",
+        String::from_utf8_lossy(&bytes),
+        to_hex(&bytes),
+        verdicts.chardetng,
+        verdicts.chardet,
+        verdicts.charset_normalizer,
+        verdicts.chosen
+    );
+    verdicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// A server string and how it should decode
+    #[derive(Deserialize)]
+    struct CorpusEntry {
+        kind: String,
+        /// `captured` from a live server, or `synthetic` when encoded by hand
+        /// to stand in for one. Running the launcher with
+        /// `OMP_LAUNCHER_CAPTURE_CHARSETS` set logs captured entries, and
+        /// `debug_detect_encoding` logs any string's bytes in the form used here.
+        source: String,
+        /// Raw bytes as sent by the server, hex encoded
+        bytes: String,
+        text: String,
+        encoding: String,
+        /// Hint to decode with, `None` for `decode_buffer` on its own
+        hint: Option<CorpusHint>,
+    }
+
+    #[derive(Deserialize)]
+    struct CorpusHint {
+        encoding: String,
+        source: EncodingSource,
+    }
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("invalid hex in corpus"))
+            .collect()
+    }
+
+    fn corpus() -> Vec<CorpusEntry> {
+        serde_json::from_str(include_str!("../data/charset_corpus.json"))
+            .expect("data/charset_corpus.json is invalid")
+    }
+
+    fn corpus_hint(entry: &CorpusEntry) -> Option<EncodingHint> {
+        entry.hint.as_ref().map(|hint| EncodingHint {
+            encoding: Encoding::for_label(hint.encoding.as_bytes())
+                .expect("unknown hint encoding in corpus"),
+            source: hint.source,
+        })
+    }
+
+    /// Decodes every corpus entry with `decode`, listing those that come out
+    /// wrong
+    fn corpus_failures(
+        mut decode: impl FnMut(usize, &CorpusEntry, Vec<u8>) -> (String, String),
+    ) -> Vec<String> {
+        corpus()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| {
+                let bytes = from_hex(&entry.bytes);
+                let (text, encoding) = decode(i, entry, bytes.clone());
+                if text == entry.text && encoding == entry.encoding {
+                    return None;
+                }
+
+                let verdicts = helpers::detector_verdicts(&bytes, None);
+                Some(format!(
+                    "{} {} {:?}: got {:?} as {}, expected {}\n\t{:?}",
+                    entry.source, entry.kind, entry.text, text, encoding, entry.encoding, verdicts
+                ))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn charset_corpus() {
+        let failures = corpus_failures(|_, entry, bytes| match corpus_hint(entry) {
+            None => helpers::decode_buffer(bytes, None),
+            Some(hint) => {
+                let (text, name, _) = decode_with_hint(bytes, Some(hint));
+                (text, name)
+            }
+        });
+        assert!(
+            failures.is_empty(),
+            "{} of {} corpus entries decoded wrong:\n{}",
+            failures.len(),
+            corpus().len(),
+            failures.join("\n")
+        );
+    }
+
+    /// The corpus decodes the same through the per-server cache, on the first
+    /// refresh and on the next one that may use what the first remembered
+    #[test]
+    fn charset_corpus_for_server() {
+        for refresh in 0..2 {
+            let failures = corpus_failures(|i, entry, bytes| {
+                let ip = format!("10.3.{}.{}", i / 256, i % 256);
+                let hint = match refresh {
+                    0 => corpus_hint(entry),
+                    _ => resolve_hint(&ip, 7777, None, None).or(corpus_hint(entry)),
+                };
+                let (text, name, _) = decode_for_server(&ip, 7777, bytes, hint);
+                (text, name)
+            });
+            assert!(
+                failures.is_empty(),
+                "refresh {}: {} corpus entries decoded wrong:\n{}",
+                refresh,
+                failures.len(),
+                failures.join("\n")
+            );
+        }
+    }
+
+    /// What capturing logs can be pasted into the corpus as it is
+    #[test]
+    fn captured_entry_fits_corpus() {
+        let bytes = [0xcf, 0xf0, 0xe8, 0xe2, 0xe5, 0xf2];
+        let entry: CorpusEntry = serde_json::from_str(&captured_corpus_entry(
+            "hostname",
+            &bytes,
+            "Привет",
+            "windows-1251",
+        ))
+        .unwrap();
+        assert_eq!(entry.kind, "hostname");
+        assert_eq!(entry.source, "captured");
+        assert_eq!(from_hex(&entry.bytes), bytes);
+        assert_eq!(entry.text, "Привет");
+        assert_eq!(entry.encoding, "windows-1251");
+        assert!(entry.hint.is_none());
+    }
+
+    /// chardet and charset normalizer used to be trusted as meaning UTF-8
+    /// when both called a buffer "ascii". They do that for valid UTF-8, which
+    /// no longer reaches the detectors, but also for most short strings in
+    /// legacy code pages, which UTF-8 turns into replacement characters.
+    #[test]
+    fn ascii_verdicts_are_not_utf8() {
+        let misjudged: Vec<CorpusEntry> = corpus()
+            .into_iter()
+            .filter(|entry| {
+                let bytes = from_hex(&entry.bytes);
+                let verdicts = helpers::detector_verdicts(&bytes, None);
+                std::str::from_utf8(&bytes).is_err()
+                    && verdicts.chardet == "ascii"
+                    && verdicts.charset_normalizer == "ascii"
+            })
+            .collect();
+
+        assert!(!misjudged.is_empty());
+        for entry in misjudged {
+            let bytes = from_hex(&entry.bytes);
+            assert_ne!(entry.encoding, "UTF-8", "{:?}", entry.text);
+            assert_ne!(UTF_8.decode(&bytes).0, entry.text);
+        }
+    }
+
+    fn hint(encoding: &'static Encoding, source: EncodingSource) -> Option<EncodingHint> {
+        Some(EncodingHint { encoding, source })
+    }
+
+    fn decode(
+        text: &str,
+        encoding: &'static Encoding,
+        hint: Option<EncodingHint>,
+    ) -> (String, String, EncodingSource) {
+        decode_with_hint(encoding.encode(text).0.to_vec(), hint)
+    }
+
+    #[test]
+    fn wrong_language_hint_is_ignored() {
+        let english = hint(WINDOWS_1252, EncodingSource::Language);
+        for (text, encoding) in [
+            ("Русский сервер", WINDOWS_1251),
+            ("Привет", WINDOWS_1251),
+            ("中文服务器", GB18030),
+            ("Zażółć gęślą", WINDOWS_1250),
+            ("İstanbul", WINDOWS_1254),
+        ] {
+            let (decoded, name, source) = decode(text, encoding, english);
+            assert_eq!(decoded, text);
+            assert_eq!(name, encoding.name());
+            assert_eq!(source, EncodingSource::Detected);
+        }
+    }
+
+    #[test]
+    fn language_hint_breaks_ties() {
+        // Too short for the detectors, which settle for windows-1252
+        assert_eq!(decode("Мир", WINDOWS_1251, None).1, "windows-1252");
+
+        let russian = hint(WINDOWS_1251, EncodingSource::Language);
+        assert_eq!(
+            decode("Мир", WINDOWS_1251, russian),
+            (
+                "Мир".to_string(),
+                "windows-1251".to_string(),
+                EncodingSource::Language
+            )
+        );
+
+        let turkish = hint(WINDOWS_1254, EncodingSource::Language);
+        assert_eq!(
+            decode("Türkçe sunucu", WINDOWS_1254, turkish).2,
+            EncodingSource::Language
+        );
+    }
+
+    #[test]
+    fn trusted_hints_win_when_they_decode() {
+        // Detected as windows-1251 on its own, but the user knows better
+        let greek = hint(WINDOWS_1253, EncodingSource::Override);
+        let bytes = WINDOWS_1251.encode("Русский сервер").0.to_vec();
+        let (_, name, source) = decode_with_hint(bytes, greek);
+        assert_eq!(name, "windows-1253");
+        assert_eq!(source, EncodingSource::Override);
+
+        // Not valid Shift_JIS, so the detectors decide
+        let japanese = hint(SHIFT_JIS, EncodingSource::Rules);
+        let (decoded, _, source) = decode_with_hint(vec![0x81, 0x20, 0x41], japanese);
+        assert_ne!(source, EncodingSource::Rules);
+        assert!(!decoded.is_empty());
+
+        // Valid UTF-8 wins over any hint
+        let (decoded, name, source) = decode("Ärger", UTF_8, greek);
+        assert_eq!((decoded.as_str(), name.as_str()), ("Ärger", "UTF-8"));
+        assert_eq!(source, EncodingSource::Detected);
+    }
+
+    #[test]
+    fn short_samples_are_not_remembered() {
+        let buf = WINDOWS_1251.encode("Привет").0.to_vec();
+        decode_for_server("10.1.0.1", 7777, buf, None);
+        assert!(resolve_hint("10.1.0.1", 7777, None, None).is_none());
+
+        let buf = WINDOWS_1251.encode("Русский сервер | Новый мир").0.to_vec();
+        decode_for_server("10.1.0.1", 7777, buf, None);
+        let hint = resolve_hint("10.1.0.1", 7777, None, None).unwrap();
+        assert_eq!(hint.encoding, WINDOWS_1251);
+        assert_eq!(hint.source, EncodingSource::Detected);
+    }
+
+    #[test]
+    fn remembered_hint_is_dropped_when_detection_disagrees() {
+        SERVER_ENCODINGS.lock().unwrap().remember(
+            server_key("10.1.0.2", 7777),
+            EncodingHint {
+                encoding: SHIFT_JIS,
+                source: EncodingSource::Detected,
+            },
+        );
+
+        // "и в д" isn't valid Shift_JIS and too short to be remembered itself
+        let buf = WINDOWS_1251.encode("и в д").0.to_vec();
+        let hint = resolve_hint("10.1.0.2", 7777, None, None);
+        let (_, name, _) = decode_for_server("10.1.0.2", 7777, buf, hint);
+        assert_ne!(name, "Shift_JIS");
+        assert!(resolve_hint("10.1.0.2", 7777, None, None).is_none());
+    }
+
+    #[test]
+    fn rules_are_not_replaced_by_other_strings() {
+        let rules = [WINDOWS_1251.encode("Добро пожаловать на сервер").0.to_vec()];
+        assert_eq!(
+            learn_from_rules("10.1.0.3", 7777, &rules),
+            Some(WINDOWS_1251)
+        );
+
+        let buf = GB18030.encode("中文服务器 欢迎来到我们的服务器").0.to_vec();
+        decode_for_server("10.1.0.3", 7777, buf, None);
+        let hint = resolve_hint("10.1.0.3", 7777, None, None).unwrap();
+        assert_eq!(hint.encoding, WINDOWS_1251);
+        assert_eq!(hint.source, EncodingSource::Rules);
+    }
+
+    #[test]
+    fn server_encodings_are_bounded() {
+        let mut encodings = ServerEncodings::default();
+        let hint = EncodingHint {
+            encoding: WINDOWS_1251,
+            source: EncodingSource::Detected,
+        };
+        for server in 0..MAX_SERVER_ENCODINGS + 10 {
+            encodings.remember(format!("10.0.0.{}:7777", server), hint);
+        }
+
+        assert_eq!(encodings.hints.len(), MAX_SERVER_ENCODINGS);
+        assert!(encodings.get("10.0.0.9:7777").is_none());
+        assert!(encodings.get("10.0.0.10:7777").is_some());
+    }
+
+    /// A list refresh worth of strings: mostly ASCII, some UTF-8 and some in
+    /// legacy code pages, for a few hundred servers
+    fn sample_strings() -> Vec<(String, Vec<u8>)> {
+        let texts: [(&str, &'static Encoding); 5] = [
+            ("[0.3.7] Grand Roleplay | Join us!", UTF_8),
+            ("Freeroam DM Stunt Race", UTF_8),
+            ("Ärger im Paradies – Deutsch", UTF_8),
+            ("Русский сервер | Новый мир", WINDOWS_1251),
+            ("中文服务器 欢迎来到我们的服务器", GB18030),
+        ];
+
+        (0..100)
+            .flat_map(|server| {
+                texts
+                    .iter()
+                    .enumerate()
+                    .map(move |(kind, (text, encoding))| {
+                        (
+                            format!("10.2.{}.{}:7777", kind, server),
+                            encoding.encode(text).0.to_vec(),
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    fn time(runs: u32, mut f: impl FnMut()) -> Duration {
+        let start = Instant::now();
+        for _ in 0..runs {
+            f();
+        }
+        start.elapsed() / runs
+    }
+
+    #[test]
+    #[ignore = "timing dependent, run with --ignored"]
+    fn fast_paths_and_cache_beat_detecting_everything() {
+        let strings = sample_strings();
+        let runs = 3;
+
+        let detect_all = time(runs, || {
+            for (_, buf) in &strings {
+                let encoding = helpers::detect_encoding(buf, None);
+                std::hint::black_box(encoding.decode(buf));
+            }
+        });
+
+        let decode_all = || {
+            for (server, buf) in &strings {
+                let (ip, port) = server.split_once(':').unwrap();
+                let port = port.parse().unwrap();
+                let hint = resolve_hint(ip, port, None, None);
+                std::hint::black_box(decode_for_server(ip, port, buf.clone(), hint));
+            }
+        };
+        // The first refresh of the list fills the cache
+        decode_all();
+        let fast_path = time(runs, decode_all);
+
+        // Around 10x even in debug builds, the margin is for noisy machines
+        assert!(
+            fast_path * 5 < detect_all,
+            "{} strings: detectors on everything {:?}, fast paths and cache {:?}",
+            strings.len(),
+            detect_all,
+            fast_path
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// Windows system error codes we care about, `io::ErrorKind` doesn't cover
+/// all of them (e.g. elevation) on every toolchain
+#[cfg(target_os = "windows")]
+mod win32 {
+    pub const ERROR_FILE_NOT_FOUND: i32 = 2;
+    pub const ERROR_PATH_NOT_FOUND: i32 = 3;
+    pub const ERROR_ACCESS_DENIED: i32 = 5;
+    pub const ERROR_SHARING_VIOLATION: i32 = 32;
+    pub const ERROR_ALREADY_EXISTS: i32 = 183;
+    pub const ERROR_ELEVATION_REQUIRED: i32 = 740;
+}
+
+/// Errors returned to the frontend. They serialize as
+/// `{ "code": "permission_denied", "details": "..." }`, the code is stable and
+/// meant to be matched on, the details are for logs and error messages only.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "code", content = "details", rename_all = "snake_case")]
+pub enum LauncherError {
+    /// The file or directory isn't accessible to the current user
+    PermissionDenied(String),
+    /// The operation has to be run with administrator rights (Windows UAC)
+    ElevationRequired(String),
+    AlreadyExists(String),
+    NotFound(String),
+    /// The file is in use by another process
+    Busy(String),
+    /// gta_sa.exe is a release neither SA-MP nor open.mp can hook into
+    UnsupportedGtaExe(String),
+    /// The given process isn't a running game instance
+    NotAGameProcess(String),
+    /// The file exists but its contents can't be parsed
+    CorruptFile(String),
+    /// A setting was given a value outside of what it accepts
+    InvalidSetting(String),
+    /// A failed operation couldn't undo all of its changes, the details name
+    /// where backups of the original files were kept
+    RollbackIncomplete(String),
+    Io(String),
+    Other(String),
+}
+
+impl LauncherError {
+    /// Builds an error from an I/O error, mentioning the path it happened on
+    pub fn from_io(e: io::Error, path: impl AsRef<Path>) -> Self {
+        let details = format!("{}: {}", path.as_ref().display(), e);
+        Self::from_io_with_details(&e, details)
+    }
+
+    fn from_io_with_details(e: &io::Error, details: String) -> Self {
+        #[cfg(target_os = "windows")]
+        if let Some(code) = e.raw_os_error() {
+            match code {
+                win32::ERROR_ACCESS_DENIED => return Self::PermissionDenied(details),
+                win32::ERROR_ELEVATION_REQUIRED => return Self::ElevationRequired(details),
+                win32::ERROR_ALREADY_EXISTS => return Self::AlreadyExists(details),
+                win32::ERROR_FILE_NOT_FOUND | win32::ERROR_PATH_NOT_FOUND => {
+                    return Self::NotFound(details)
+                }
+                win32::ERROR_SHARING_VIOLATION => return Self::Busy(details),
+                _ => {}
+            }
+        }
+
+        // EROFS, the game directory is on a read-only mount
+        #[cfg(unix)]
+        if e.raw_os_error() == Some(30) {
+            return Self::PermissionDenied(details);
+        }
+
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied(details),
+            io::ErrorKind::AlreadyExists => Self::AlreadyExists(details),
+            io::ErrorKind::NotFound => Self::NotFound(details),
+            _ => Self::Io(details),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::PermissionDenied(_) => "permission_denied",
+            Self::ElevationRequired(_) => "elevation_required",
+            Self::AlreadyExists(_) => "already_exists",
+            Self::NotFound(_) => "not_found",
+            Self::Busy(_) => "busy",
+            Self::UnsupportedGtaExe(_) => "unsupported_gta_exe",
+            Self::NotAGameProcess(_) => "not_a_game_process",
+            Self::CorruptFile(_) => "corrupt_file",
+            Self::InvalidSetting(_) => "invalid_setting",
+            Self::RollbackIncomplete(_) => "rollback_incomplete",
+            Self::Io(_) => "io",
+            Self::Other(_) => "other",
+        }
+    }
+
+    pub fn details(&self) -> &str {
+        match self {
+            Self::PermissionDenied(details)
+            | Self::ElevationRequired(details)
+            | Self::AlreadyExists(details)
+            | Self::NotFound(details)
+            | Self::Busy(details)
+            | Self::UnsupportedGtaExe(details)
+            | Self::NotAGameProcess(details)
+            | Self::CorruptFile(details)
+            | Self::InvalidSetting(details)
+            | Self::RollbackIncomplete(details)
+            | Self::Io(details)
+            | Self::Other(details) => details,
+        }
+    }
+}
+
+impl fmt::Display for LauncherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code(), self.details())
+    }
+}
+
+impl std::error::Error for LauncherError {}
+
+impl From<io::Error> for LauncherError {
+    fn from(e: io::Error) -> Self {
+        let details = e.to_string();
+        Self::from_io_with_details(&e, details)
+    }
+}
+
+impl From<String> for LauncherError {
+    fn from(details: String) -> Self {
+        Self::Other(details)
+    }
+}
+
+impl From<&str> for LauncherError {
+    fn from(details: &str) -> Self {
+        Self::Other(details.to_string())
+    }
+}
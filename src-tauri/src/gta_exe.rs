@@ -0,0 +1,393 @@
+use byteorder::{ByteOrder, LittleEndian};
+use md5::compute;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::process::GAME_EXECUTABLE;
+
+/// MD5 hashes of unmodified release executables. Executables matching one of
+/// these are identified right away, which also covers packed ones the version
+/// signatures below can't see into. Only the 1.0 US executable is on record so
+/// far, so other versions are never reported as modified; add their hashes here
+/// once they've been taken from verified copies.
+const KNOWN_RELEASES: [(&str, GtaVersion); 1] = [
+    // The widely used (no-CD) 1.0 US executable, 14,383,616 bytes
+    ("170b3a9108687b26da2d8901c6948a18", GtaVersion::V1_0Us),
+];
+
+/// Every known release has `mov edi, 94h` (bf 94 00 00 00) at a fixed address
+/// in its startup code, which makes for a reliable version fingerprint. Packed
+/// (SecuROM protected) retail executables won't match any of these.
+const VERSION_SIGNATURES: [(u32, GtaVersion); 5] = [
+    (0x0082457C, GtaVersion::V1_0Us),
+    (0x008245BC, GtaVersion::V1_0Eu),
+    (0x008252FC, GtaVersion::V1_01Us),
+    (0x0082533C, GtaVersion::V1_01Eu),
+    (0x0085EC4A, GtaVersion::Steam3_0),
+];
+const VERSION_SIGNATURE_VALUE: u32 = 0x94BF;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum GtaVersion {
+    #[serde(rename = "1.0_us")]
+    V1_0Us,
+    #[serde(rename = "1.0_eu")]
+    V1_0Eu,
+    #[serde(rename = "1.01_us")]
+    V1_01Us,
+    #[serde(rename = "1.01_eu")]
+    V1_01Eu,
+    #[serde(rename = "steam_3.0")]
+    Steam3_0,
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Compatibility {
+    Compatible,
+    Incompatible,
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PeInfo {
+    pub timestamp: u32,
+    pub image_base: u32,
+    pub entry_point: u32,
+    pub size_of_image: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GtaExeVersion {
+    pub version: GtaVersion,
+    pub size: u64,
+    pub md5: String,
+    /// Recognized as a release whose hash is known, but the file differs from it.
+    /// Always false for releases without a hash in `KNOWN_RELEASES`.
+    pub modified: bool,
+    pub pe: Option<PeInfo>,
+    pub samp: Compatibility,
+    pub omp: Compatibility,
+}
+
+struct Section {
+    virtual_address: u32,
+    virtual_size: u32,
+    raw_pointer: u32,
+    raw_size: u32,
+}
+
+struct PeHeader {
+    info: PeInfo,
+    sections: Vec<Section>,
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> Option<u16> {
+    buf.get(offset..offset + 2).map(LittleEndian::read_u16)
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    buf.get(offset..offset + 4).map(LittleEndian::read_u32)
+}
+
+fn parse_pe_header(buf: &[u8]) -> Option<PeHeader> {
+    if buf.get(0..2)? != b"MZ" {
+        return None;
+    }
+
+    let pe_offset = read_u32(buf, 0x3C)? as usize;
+    if buf.get(pe_offset..pe_offset + 4)? != b"PE\0\0" {
+        return None;
+    }
+
+    let coff = pe_offset + 4;
+    let section_count = read_u16(buf, coff + 2)? as usize;
+    let timestamp = read_u32(buf, coff + 4)?;
+    let optional_header_size = read_u16(buf, coff + 16)? as usize;
+
+    // Only 32-bit (PE32) images are of any interest here
+    let optional = coff + 20;
+    if read_u16(buf, optional)? != 0x10B {
+        return None;
+    }
+
+    let info = PeInfo {
+        timestamp,
+        entry_point: read_u32(buf, optional + 16)?,
+        image_base: read_u32(buf, optional + 28)?,
+        size_of_image: read_u32(buf, optional + 56)?,
+    };
+
+    let mut sections = Vec::with_capacity(section_count);
+    let section_table = optional + optional_header_size;
+    for i in 0..section_count {
+        let header = section_table + i * 40;
+        sections.push(Section {
+            virtual_size: read_u32(buf, header + 8)?,
+            virtual_address: read_u32(buf, header + 12)?,
+            raw_size: read_u32(buf, header + 16)?,
+            raw_pointer: read_u32(buf, header + 20)?,
+        });
+    }
+
+    Some(PeHeader { info, sections })
+}
+
+/// Translates a virtual address into an offset in the executable file
+fn va_to_file_offset(header: &PeHeader, va: u32) -> Option<usize> {
+    let rva = va.checked_sub(header.info.image_base)?;
+    header.sections.iter().find_map(|section| {
+        let size = section.virtual_size.max(section.raw_size);
+        let end = section.virtual_address.checked_add(size)?;
+        if rva >= section.virtual_address && rva < end {
+            let offset = rva - section.virtual_address;
+            if offset < section.raw_size {
+                return section
+                    .raw_pointer
+                    .checked_add(offset)
+                    .map(|pointer| pointer as usize);
+            }
+        }
+        None
+    })
+}
+
+fn detect_version(buf: &[u8], header: &PeHeader) -> GtaVersion {
+    VERSION_SIGNATURES
+        .iter()
+        .find(|(va, _)| {
+            va_to_file_offset(header, *va)
+                .and_then(|offset| read_u32(buf, offset))
+                .map(|value| value == VERSION_SIGNATURE_VALUE)
+                .unwrap_or(false)
+        })
+        .map(|(_, version)| *version)
+        .unwrap_or(GtaVersion::Unknown)
+}
+
+/// Both SA-MP and open.mp clients only hook into the 1.0 executables
+fn get_compatibility(version: GtaVersion) -> (Compatibility, Compatibility) {
+    match version {
+        GtaVersion::V1_0Us | GtaVersion::V1_0Eu => {
+            (Compatibility::Compatible, Compatibility::Compatible)
+        }
+        GtaVersion::V1_01Us | GtaVersion::V1_01Eu | GtaVersion::Steam3_0 => {
+            (Compatibility::Incompatible, Compatibility::Incompatible)
+        }
+        GtaVersion::Unknown => (Compatibility::Unknown, Compatibility::Unknown),
+    }
+}
+
+/// Size and modification time of an executable, along with what it was
+/// identified as
+type CachedVersion = (u64, SystemTime, GtaExeVersion);
+
+/// Results of `get_gta_exe_version`, keyed by executable path and only valid
+/// while the file keeps its size and modification time
+static VERSION_CACHE: Lazy<Mutex<HashMap<PathBuf, CachedVersion>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn identify_gta_exe(buf: &[u8]) -> GtaExeVersion {
+    let md5 = format!("{:x}", compute(buf));
+    let header = parse_pe_header(buf);
+    let known = KNOWN_RELEASES
+        .iter()
+        .find(|(hash, _)| *hash == md5)
+        .map(|(_, version)| *version);
+    let version = match (known, &header) {
+        (Some(version), _) => version,
+        (None, Some(header)) => detect_version(buf, header),
+        (None, None) => GtaVersion::Unknown,
+    };
+    let modified = known.is_none()
+        && KNOWN_RELEASES
+            .iter()
+            .any(|(_, release)| *release == version);
+    let (samp, omp) = get_compatibility(version);
+
+    GtaExeVersion {
+        version,
+        size: buf.len() as u64,
+        md5,
+        modified,
+        pe: header.map(|h| h.info),
+        samp,
+        omp,
+    }
+}
+
+/// Identifies the `gta_sa.exe` found in the given game directory. Hashing the
+/// whole executable is slow, so results are reused until the file changes.
+pub fn get_gta_exe_version(game_dir: &str) -> Result<GtaExeVersion, String> {
+    let exe_path = Path::new(game_dir).join(GAME_EXECUTABLE);
+    let metadata = fs::metadata(&exe_path).map_err(|e| format!("{}: {}", exe_path.display(), e))?;
+    let size = metadata.len();
+    let modified_at = metadata.modified().ok();
+
+    if let Some(modified_at) = modified_at {
+        if let Ok(cache) = VERSION_CACHE.lock() {
+            if let Some((cached_size, cached_at, version)) = cache.get(&exe_path) {
+                if *cached_size == size && *cached_at == modified_at {
+                    return Ok(version.clone());
+                }
+            }
+        }
+    }
+
+    let buf = fs::read(&exe_path).map_err(|e| format!("{}: {}", exe_path.display(), e))?;
+    let version = identify_gta_exe(&buf);
+
+    if let Some(modified_at) = modified_at {
+        if let Ok(mut cache) = VERSION_CACHE.lock() {
+            cache.insert(exe_path, (size, modified_at, version.clone()));
+        }
+    }
+    Ok(version)
+}
+
+#[tauri::command]
+pub async fn detect_gta_exe_version(game_dir: String) -> Result<GtaExeVersion, String> {
+    tokio::task::spawn_blocking(move || get_gta_exe_version(&game_dir))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMAGE_BASE: u32 = 0x0040_0000;
+    const SECTION_RVA: u32 = 0x0042_0000;
+    const SECTION_RAW_POINTER: u32 = 0x400;
+    const SECTION_SIZE: u32 = 0x0004_0000;
+
+    /// Builds a minimal PE32 image with a single section that covers every
+    /// version signature address, with `mov edi, 94h` placed at `signature_va`
+    fn synthetic_exe(signature_va: Option<u32>) -> Vec<u8> {
+        let mut buf = vec![0u8; (SECTION_RAW_POINTER + SECTION_SIZE) as usize];
+        buf[0..2].copy_from_slice(b"MZ");
+        LittleEndian::write_u32(&mut buf[0x3C..], 0x80);
+        buf[0x80..0x84].copy_from_slice(b"PE\0\0");
+
+        let coff = 0x84;
+        LittleEndian::write_u16(&mut buf[coff + 2..], 1);
+        LittleEndian::write_u32(&mut buf[coff + 4..], 0x4274_2D4E);
+        LittleEndian::write_u16(&mut buf[coff + 16..], 0xE0);
+
+        let optional = coff + 20;
+        LittleEndian::write_u16(&mut buf[optional..], 0x10B);
+        LittleEndian::write_u32(&mut buf[optional + 16..], SECTION_RVA);
+        LittleEndian::write_u32(&mut buf[optional + 28..], IMAGE_BASE);
+        LittleEndian::write_u32(&mut buf[optional + 56..], SECTION_RVA + SECTION_SIZE);
+
+        let section = optional + 0xE0;
+        buf[section..section + 5].copy_from_slice(b".text");
+        LittleEndian::write_u32(&mut buf[section + 8..], SECTION_SIZE);
+        LittleEndian::write_u32(&mut buf[section + 12..], SECTION_RVA);
+        LittleEndian::write_u32(&mut buf[section + 16..], SECTION_SIZE);
+        LittleEndian::write_u32(&mut buf[section + 20..], SECTION_RAW_POINTER);
+
+        if let Some(va) = signature_va {
+            let offset = (va - IMAGE_BASE - SECTION_RVA + SECTION_RAW_POINTER) as usize;
+            LittleEndian::write_u32(&mut buf[offset..], VERSION_SIGNATURE_VALUE);
+        }
+        buf
+    }
+
+    #[test]
+    fn detects_every_signature() {
+        for (va, version) in VERSION_SIGNATURES {
+            let buf = synthetic_exe(Some(va));
+            let header = parse_pe_header(&buf).expect("synthetic image should parse");
+            assert_eq!(detect_version(&buf, &header), version, "at {:#x}", va);
+        }
+    }
+
+    #[test]
+    fn unknown_without_signature() {
+        let buf = synthetic_exe(None);
+        let header = parse_pe_header(&buf).expect("synthetic image should parse");
+        assert_eq!(detect_version(&buf, &header), GtaVersion::Unknown);
+    }
+
+    #[test]
+    fn identifies_synthetic_exe() {
+        let exe = identify_gta_exe(&synthetic_exe(Some(0x0085EC4A)));
+        assert_eq!(exe.version, GtaVersion::Steam3_0);
+        assert_eq!(exe.samp, Compatibility::Incompatible);
+        assert_eq!(exe.pe.map(|pe| pe.image_base), Some(IMAGE_BASE));
+        // No hash of the Steam release is on record to compare against
+        assert!(!exe.modified);
+
+        // Looks like the 1.0 US release, but isn't the known executable
+        let exe = identify_gta_exe(&synthetic_exe(Some(0x0082457C)));
+        assert_eq!(exe.version, GtaVersion::V1_0Us);
+        assert!(exe.modified);
+    }
+
+    #[test]
+    fn rejects_non_pe_buffers() {
+        assert!(parse_pe_header(b"").is_none());
+        assert!(parse_pe_header(b"MZ").is_none());
+
+        let mut buf = synthetic_exe(None);
+        buf[0x80] = b'X';
+        assert!(parse_pe_header(&buf).is_none());
+        assert_eq!(identify_gta_exe(&buf).version, GtaVersion::Unknown);
+    }
+
+    #[test]
+    fn rejects_overflowing_sections() {
+        let buf = synthetic_exe(None);
+        let mut header = parse_pe_header(&buf).expect("synthetic image should parse");
+        // The section would end past the 4 GiB address space
+        header.sections[0].virtual_address = u32::MAX - IMAGE_BASE - 0x10;
+        header.sections[0].virtual_size = 2 * IMAGE_BASE;
+        header.sections[0].raw_pointer = u32::MAX - 0x10;
+        assert_eq!(va_to_file_offset(&header, u32::MAX - 0x8), None);
+
+        // Inside the section, but its file offset wouldn't fit in 32 bits
+        header.sections[0].virtual_address = SECTION_RVA;
+        assert_eq!(
+            va_to_file_offset(&header, IMAGE_BASE + SECTION_RVA + 0x20),
+            None
+        );
+        assert_eq!(
+            va_to_file_offset(&header, IMAGE_BASE + SECTION_RVA + 0x8),
+            Some((u32::MAX - 0x8) as usize)
+        );
+    }
+
+    #[test]
+    fn caches_until_the_file_changes() {
+        let dir = std::env::temp_dir().join(format!("omp-gta-exe-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let exe_path = dir.join(GAME_EXECUTABLE);
+        let game_dir = dir.to_string_lossy().to_string();
+
+        fs::write(&exe_path, synthetic_exe(Some(0x008245BC))).unwrap();
+        assert_eq!(
+            get_gta_exe_version(&game_dir).unwrap().version,
+            GtaVersion::V1_0Eu
+        );
+
+        // Same size, but a different modification time
+        fs::write(&exe_path, synthetic_exe(Some(0x008252FC))).unwrap();
+        let file = fs::File::options().write(true).open(&exe_path).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(
+            get_gta_exe_version(&game_dir).unwrap().version,
+            GtaVersion::V1_01Us
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,166 +1,286 @@
 use std::fs;
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use chardet::{charset2encoding, detect};
 use chardetng::EncodingDetector;
 use charset_normalizer_rs::from_bytes;
-use encoding_rs::{Encoding, UTF_8};
+use encoding_rs::{
+    Encoding, EUC_KR, GB18030, GBK, SHIFT_JIS, UTF_8, WINDOWS_1250, WINDOWS_1251, WINDOWS_1252,
+    WINDOWS_1253, WINDOWS_1254, WINDOWS_1255, WINDOWS_1256, WINDOWS_1257, WINDOWS_1258,
+    WINDOWS_874,
+};
 use log::info;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 
-/// Decodes a buffer of bytes into a string, detecting the encoding
-pub fn decode_buffer(buf: Vec<u8>) -> (String, String) {
+use crate::copy_engine::{self, OverwritePolicy};
+use crate::errors::LauncherError;
+
+/// Returns the launcher's own data directory (`<local data dir>/mp.open.launcher`),
+/// creating it if it doesn't exist yet
+pub fn get_launcher_data_dir() -> Result<PathBuf, String> {
+    let data_dir = dirs_next::data_local_dir()
+        .ok_or_else(|| "Could not determine local data directory".to_string())?
+        .join("mp.open.launcher");
+
+    if !data_dir.exists() {
+        fs::create_dir_all(&data_dir).map_err(|e| e.to_string())?;
+    }
+
+    Ok(data_dir)
+}
+
+/// Current time in seconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Replaces `path` with `data` without ever leaving a partly written file
+/// behind: the data goes to a temp file next to it that's synced to disk and
+/// then renamed over the old one
+pub fn write_file_atomic(path: &Path, data: &[u8]) -> Result<(), LauncherError> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".omp-tmp");
+    let temp = path.with_file_name(temp_name);
+
+    let write_temp = || -> std::io::Result<()> {
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(data)?;
+        file.sync_all()
+    };
+
+    if let Err(e) = write_temp() {
+        let _ = fs::remove_file(&temp);
+        return Err(LauncherError::from_io(e, &temp));
+    }
+
+    fs::rename(&temp, path).map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        LauncherError::from_io(e, path)
+    })
+}
+
+/// A JSON file in the launcher's data directory, along with the lock that
+/// serializes read-modify-write cycles on it
+pub struct JsonStore {
+    /// Path relative to the data directory
+    path: &'static str,
+    lock: Mutex<()>,
+}
+
+impl JsonStore {
+    pub const fn new(path: &'static str) -> Self {
+        Self {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn file(&self) -> Result<PathBuf, LauncherError> {
+        Ok(get_launcher_data_dir()?.join(self.path))
+    }
+
+    pub fn lock(&self) -> Result<MutexGuard<'_, ()>, LauncherError> {
+        self.lock
+            .lock()
+            .map_err(|e| LauncherError::Other(e.to_string()))
+    }
+
+    /// Reads the file, a missing one reads as the default value
+    pub fn load<T: DeserializeOwned + Default>(&self) -> Result<T, LauncherError> {
+        let file = self.file()?;
+        if !file.exists() {
+            return Ok(T::default());
+        }
+
+        let data = fs::read_to_string(&file).map_err(|e| LauncherError::from_io(e, &file))?;
+        serde_json::from_str(&data)
+            .map_err(|e| LauncherError::CorruptFile(format!("{}: {}", file.display(), e)))
+    }
+
+    pub fn save<T: Serialize>(&self, data: &T) -> Result<(), LauncherError> {
+        let file = self.file()?;
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir).map_err(|e| LauncherError::from_io(e, dir))?;
+        }
+
+        let json = serde_json::to_string(data).map_err(|e| LauncherError::Other(e.to_string()))?;
+        write_file_atomic(&file, json.as_bytes())
+    }
+}
+
+/// Decodes a buffer of bytes into a string, returning it along with the name
+/// of the encoding used. ASCII and valid UTF-8 are taken as they are, other
+/// text goes through the detectors. A `hint` (e.g. the code page a server's
+/// language suggests) only breaks ties: it's taken over the detectors' choice
+/// when one of them agrees with it, see `detector_verdicts`.
+pub fn decode_buffer(buf: Vec<u8>, hint: Option<&'static Encoding>) -> (String, String) {
+    // Most strings are plain ASCII or UTF-8, which needs no detection at all
+    let buf = match String::from_utf8(buf) {
+        Ok(text) => return (text, UTF_8.name().to_string()),
+        Err(e) => e.into_bytes(),
+    };
+
+    let actual_encoding = detect_encoding(&buf, hint);
+
+    // Decode the buffer using the determined encoding
+    // Note: Error handling for decoding errors is intentionally omitted.
+    // In cases where there are minor errors in the text (like a few corrupted characters),
+    // this approach ensures that the text is still usable, albeit with some minor imperfections.
+    let (decoded, _, _had_errors) = actual_encoding.decode(&buf);
+    let buff_output = decoded.into_owned();
+
+    // Return the decoded string and the encoding name
+    (buff_output, actual_encoding.name().to_string())
+}
+
+/// What each charset detector made of a buffer, and the encoding picked from
+/// their verdicts
+#[derive(Serialize, Debug)]
+pub struct DetectorVerdicts {
+    pub chardetng: String,
+    pub chardet: String,
+    pub charset_normalizer: String,
+    pub chosen: String,
+}
+
+/// Runs the charset detectors over a buffer that isn't valid UTF-8 and picks
+/// the most likely encoding from their verdicts
+pub fn detect_encoding(buf: &[u8], hint: Option<&'static Encoding>) -> &'static Encoding {
+    Encoding::for_label(detector_verdicts(buf, hint).chosen.as_bytes()).unwrap_or(UTF_8)
+}
+
+/// Code pages and a top-level domain chardetng favors each of them for
+const ENCODING_TLDS: [(&Encoding, &[u8]); 13] = [
+    (WINDOWS_1251, b"ru"),
+    (GB18030, b"cn"),
+    (SHIFT_JIS, b"jp"),
+    (EUC_KR, b"kr"),
+    (WINDOWS_1250, b"cz"),
+    (WINDOWS_1252, b"de"),
+    (WINDOWS_1253, b"gr"),
+    (WINDOWS_1254, b"tr"),
+    (WINDOWS_1255, b"il"),
+    (WINDOWS_1256, b"sa"),
+    (WINDOWS_1257, b"lt"),
+    (WINDOWS_1258, b"vn"),
+    (WINDOWS_874, b"th"),
+];
+
+/// Whether chardetng's verdict names `encoding`. GBK counts as GB18030,
+/// which decodes everything it does.
+fn is_encoding(detected: &'static Encoding, encoding: &'static Encoding) -> bool {
+    detected == encoding || (detected == GBK && encoding == GB18030)
+}
+
+/// Runs the charset detectors over a buffer, see `detect_encoding`.
+///
+/// A `hint` only wins over the picked encoding when the detectors back it:
+/// when one of them named it, or when chardetng settled for windows-1252,
+/// which is what it falls back to for text too short to place, and picks the
+/// hint once told the text comes from a country using it. That the buffer
+/// decodes in the hint without errors says little on its own, nearly any byte
+/// is valid in a single-byte code page.
+pub fn detector_verdicts(buf: &[u8], hint: Option<&'static Encoding>) -> DetectorVerdicts {
     // Using chardetng for encoding detection
     let mut detector = EncodingDetector::new();
-    detector.feed(&buf, true);
-    let chardetng_encoding = detector.guess(None, true).name().to_lowercase();
+    detector.feed(buf, true);
+    let chardetng_encoding = detector.guess(None, true);
+    let chardetng = chardetng_encoding.name().to_lowercase();
 
     // Using chardet for encoding detection
-    let chardet_encoding = charset2encoding(&detect(&buf).0).to_string().to_lowercase();
+    let chardet = charset2encoding(&detect(buf).0).to_string().to_lowercase();
 
     // Using charset_normalizer_rs for encoding detection
-    let charset_normalizer_encoding = from_bytes(&buf, None)
+    let charset_normalizer = from_bytes(buf, None)
         .get_best()
         .map(|cd| cd.encoding().to_string().to_lowercase())
         .unwrap_or_else(|| "not_found".to_string());
 
-    // Collect encoding results for debug
-    // let mut messages: Vec<String> = Vec::new();
-    // messages.push(format!("Input: {}", String::from_utf8_lossy(&buf)));
-    // messages.push(format!("\tchardetng: {}", chardetng_encoding));
-    // messages.push(format!("\tchardet: {}", chardet_encoding));
-    // messages.push(format!(
-    //     "\tcharset_normalizer: {}",
-    //     charset_normalizer_encoding
-    // ));
-
-    // Determine the most likely actual encoding
-    let actual_encoding = if chardet_encoding == "ascii" && charset_normalizer_encoding == "ascii" {
-        // Default to UTF-8 if both chardet and charset normalizer detect ASCII
-        Encoding::for_label("UTF_8".as_bytes()).unwrap_or(UTF_8)
-    } else if ((chardet_encoding == "koi8-r" && charset_normalizer_encoding == "koi8-r")
-        || chardetng_encoding == "gbk"
-            && (chardet_encoding == "windows-1255" || charset_normalizer_encoding == "ibm866"))
-        || chardet_encoding == "x-mac-cyrillic"
-        || charset_normalizer_encoding == "macintosh"
-        || (chardetng_encoding == "iso-8859-2" && chardet_encoding == "iso-8859-1")
+    let mut verdicts = DetectorVerdicts {
+        chardetng,
+        chardet,
+        charset_normalizer,
+        chosen: String::new(),
+    };
+
+    let mut chosen = choose_encoding(&verdicts);
+    if let Some(hint) = hint.filter(|hint| !is_encoding(chosen, hint)) {
+        let name = hint.name().to_lowercase();
+        let named = is_encoding(chardetng_encoding, hint)
+            || verdicts.chardet == name
+            || verdicts.charset_normalizer == name;
+        let placed = chosen == WINDOWS_1252
+            && ENCODING_TLDS
+                .iter()
+                .find(|(encoding, _)| *encoding == hint)
+                .is_some_and(|(_, tld)| is_encoding(detector.guess(Some(tld), true), hint));
+
+        if (named || placed) && !hint.decode_without_bom_handling(buf).1 {
+            chosen = hint;
+        }
+    }
+    verdicts.chosen = chosen.name().to_string();
+    verdicts
+}
+
+/// Determines the most likely actual encoding. chardet and charset normalizer
+/// report "ascii" for some legacy code pages (windows-1251 Cyrillic among
+/// them), those are left to chardetng.
+fn choose_encoding(verdicts: &DetectorVerdicts) -> &'static Encoding {
+    if ((verdicts.chardet == "koi8-r" && verdicts.charset_normalizer == "koi8-r")
+        || verdicts.chardetng == "gbk"
+            && (verdicts.chardet == "windows-1255" || verdicts.charset_normalizer == "ibm866"))
+        || verdicts.chardet == "x-mac-cyrillic"
+        || verdicts.charset_normalizer == "macintosh"
+        || (verdicts.chardetng == "iso-8859-2" && verdicts.chardet == "iso-8859-1")
     {
         // Use windows-1251 for various combinations
         Encoding::for_label("windows-1251".as_bytes()).unwrap_or(UTF_8)
-    } else if chardetng_encoding == "windows-1250"
-        && (chardet_encoding == "iso-8859-1" || charset_normalizer_encoding == "windows-1251")
+    } else if verdicts.chardetng == "windows-1250"
+        && (verdicts.chardet == "iso-8859-1" || verdicts.charset_normalizer == "windows-1251")
     {
         // Use windows-1250 for various combinations
         Encoding::for_label("windows-1250".as_bytes()).unwrap_or(UTF_8)
-    } else if (chardetng_encoding == "windows-1252" && chardet_encoding == "windows-1251")
-        || (chardet_encoding == "iso-8859-1"
-            && (charset_normalizer_encoding == "iso-8859-2"
-                || charset_normalizer_encoding == "windows-874"
-                || charset_normalizer_encoding == "iso-8859-1"
-                || charset_normalizer_encoding == "ibm866"
-                || charset_normalizer_encoding == "euc-kr"))
-        || (chardetng_encoding == "shift_jis" && chardet_encoding == "iso-8859-1")
+    } else if (verdicts.chardetng == "windows-1252" && verdicts.chardet == "windows-1251")
+        || (verdicts.chardet == "iso-8859-1"
+            && (verdicts.charset_normalizer == "iso-8859-2"
+                || verdicts.charset_normalizer == "windows-874"
+                || verdicts.charset_normalizer == "iso-8859-1"
+                || verdicts.charset_normalizer == "ibm866"
+                || verdicts.charset_normalizer == "euc-kr"))
+        || (verdicts.chardetng == "shift_jis" && verdicts.chardet == "iso-8859-1")
     {
         // Use windows-1252 for various combinations
         Encoding::for_label("windows-1252".as_bytes()).unwrap_or(UTF_8)
-    } else if chardetng_encoding == "gbk" || chardet_encoding == "gb2312" {
+    } else if verdicts.chardetng == "gbk" || verdicts.chardet == "gb2312" {
         // Use GB18030 when chardetng detects GBK or chardet detects GB2312
         Encoding::for_label("GB18030".as_bytes()).unwrap_or(UTF_8)
     } else {
         // Default to the encoding detected by chardetng
-        Encoding::for_label(chardetng_encoding.as_bytes()).unwrap_or(UTF_8)
-    };
-
-    // Decode the buffer using the determined encoding
-    // Note: Error handling for decoding errors is intentionally omitted.
-    // In cases where there are minor errors in the text (like a few corrupted characters),
-    // this approach ensures that the text is still usable, albeit with some minor imperfections.
-    let (decoded, _, _had_errors) = actual_encoding.decode(&buf);
-    let buff_output = decoded.into_owned();
-
-    // Print debug information
-    // messages.push(format!("\tfinal: {}", actual_encoding.name().to_string()));
-    // for message in messages {
-    //     print!("{}", message);
-    //     print!("\t");
-    // }
-    // println!();
-
-    // Return the decoded string and the encoding name
-    (buff_output, actual_encoding.name().to_string())
+        Encoding::for_label(verdicts.chardetng.as_bytes()).unwrap_or(UTF_8)
+    }
 }
 
-pub fn copy_files(src: impl AsRef<Path>, dest: impl AsRef<Path>) -> Result<(), String> {
-    let read_results = fs::read_dir(src);
-    match read_results {
-        Ok(files) => {
-            for entry in files {
-                match entry {
-                    Ok(entry) => {
-                        let ty = entry.file_type().unwrap();
-                        if ty.is_dir() {
-                            let dir_path = dest.as_ref().join(entry.file_name());
-                            let dir_path_str = dir_path.to_str().unwrap();
-                            let dir_creation_results = fs::create_dir(&dir_path);
-                            match dir_creation_results {
-                                Ok(_) => {}
-                                Err(e) => {
-                                    println!("[helpers.rs] copy_files 1: {}", e);
-                                    info!("[helpers.rs] copy_files 1: {}", e);
-
-                                    if e.raw_os_error().is_some() {
-                                        if e.raw_os_error().unwrap() == 183 {
-                                            println!("Directory {} already exists", dir_path_str);
-                                            return Ok(());
-                                        }
-
-                                        if e.raw_os_error().unwrap() == 5 {
-                                            return Err("need_admin".to_string());
-                                        }
-                                    }
-                                    return Err(e.to_string());
-                                }
-                            }
-
-                            match copy_files(entry.path(), dest.as_ref().join(entry.file_name())) {
-                                Ok(_) => {}
-                                Err(e) => {
-                                    println!("[helpers.rs] copy_files 2: {}", e);
-                                    info!("[helpers.rs] copy_files 2: {}", e);
-                                    return Err(e);
-                                }
-                            }
-                        } else {
-                            let copy_results =
-                                fs::copy(entry.path(), dest.as_ref().join(entry.file_name()));
-                            match copy_results {
-                                Ok(_) => {}
-                                Err(e) => {
-                                    println!("[helpers.rs] copy_files 3: {}", e);
-                                    info!("[helpers.rs] copy_files 3: {}", e);
-
-                                    if e.raw_os_error().is_some() {
-                                        if e.raw_os_error().unwrap() == 5 {
-                                            return Err("need_admin".to_string());
-                                        }
-                                    }
-                                    return Err(e.to_string());
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        println!("[helpers.rs] copy_files 4: {}", e);
-                        info!("[helpers.rs] copy_files: {}", e);
-                        return Err(e.to_string());
-                    }
-                }
-            }
-            Ok(())
-        }
-        Err(e) => {
-            println!("[helpers.rs] copy_files 5: {}", e);
-            info!("[helpers.rs] copy_files: {}", e);
-            Err(e.to_string())
-        }
-    }
+/// Copies a directory tree into `dest`. Either everything is copied or, on
+/// failure, the destination is left as it was.
+pub fn copy_files(
+    src: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    policy: OverwritePolicy,
+) -> Result<(), LauncherError> {
+    copy_engine::copy_tree(src, dest, policy, |_| {}).map(|report| {
+        info!(
+            "[helpers.rs] copy_files: {} created, {} replaced, {} skipped",
+            report.created.len(),
+            report.replaced.len(),
+            report.skipped.len()
+        );
+    })
 }
@@ -1,22 +1,117 @@
 #[cfg(target_os = "windows")]
-use dll_syringe::{process::OwnedProcess, Syringe};
+use crate::gta_exe;
+use crate::{errors::LauncherError, ipc, playtime, process};
 #[cfg(target_os = "windows")]
+use dll_syringe::{process::OwnedProcess, Syringe};
 use log::info;
+use serde::{Deserialize, Serialize};
 #[cfg(target_os = "windows")]
 use std::process::Command;
 
+/// What to do when the game is already running from the target game directory
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LaunchPolicy {
+    /// Tell the running client to connect to the new server instead
+    Connect,
+    /// Don't launch, let the user decide what to do with the running game
+    #[default]
+    Ask,
+    /// Don't launch at all
+    Refuse,
+}
+
+/// What `run_samp` ended up doing
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum LaunchOutcome {
+    Launched,
+    ConnectedToRunningGame { pid: u32 },
+    AlreadyRunning { pid: u32 },
+    Refused { pid: u32 },
+}
+
+/// Tells a running game to connect to another server. The password goes last
+/// since it may contain colons itself. The session the game runs is moved
+/// over to the new server, so playtime, chat logs and screenshots from now on
+/// count for it.
+pub fn connect_running_game(
+    pid: u32,
+    ip: &str,
+    port: i32,
+    password: &str,
+) -> Result<(), LauncherError> {
+    // Messages are newline delimited
+    let password = password.replace(['\r', '\n'], "");
+    ipc::send_message_to_game(pid as i32, &format!("connect:{}:{}:{}", ip, port, password))
+        .map_err(|e| match e.as_str() {
+            "no_stream_found" => LauncherError::NotFound(format!(
+                "Game process {} isn't connected to the launcher",
+                pid
+            )),
+            _ => LauncherError::Other(e),
+        })?;
+
+    playtime::switch_session_server(pid, ip, port);
+    Ok(())
+}
+
+/// Looks for a game instance already running from `executable_dir` and applies
+/// the launch policy to it. Returns `None` when it's fine to launch a new one.
+fn check_running_game(
+    executable_dir: &str,
+    ip: &str,
+    port: i32,
+    password: &str,
+    policy: LaunchPolicy,
+) -> Option<LaunchOutcome> {
+    let running = process::find_game_processes_in_dir(executable_dir);
+    let pid = running.first()?.pid;
+
+    info!(
+        "[injector.rs] Game is already running from {} (pid: {})",
+        executable_dir, pid
+    );
+
+    match policy {
+        LaunchPolicy::Connect => match connect_running_game(pid, ip, port, password) {
+            Ok(_) => Some(LaunchOutcome::ConnectedToRunningGame { pid }),
+            // The running client isn't connected to our IPC (e.g. it was started
+            // outside of the launcher), so we have to ask the user instead
+            Err(_) => Some(LaunchOutcome::AlreadyRunning { pid }),
+        },
+        LaunchPolicy::Ask => Some(LaunchOutcome::AlreadyRunning { pid }),
+        LaunchPolicy::Refuse => Some(LaunchOutcome::Refused { pid }),
+    }
+}
+
 #[cfg(not(target_os = "windows"))]
 pub async fn run_samp(
     _name: &str,
-    _ip: &str,
-    _port: i32,
-    _executable_dir: &str,
+    ip: &str,
+    port: i32,
+    executable_dir: &str,
     _dll_path: &str,
     _omp_file: &str,
-    _password: &str,
+    password: &str,
     _discord: bool,
-) -> Result<(), String> {
-    Ok(())
+    policy: LaunchPolicy,
+) -> Result<LaunchOutcome, LauncherError> {
+    let (ip, executable_dir, password) = (
+        ip.to_string(),
+        executable_dir.to_string(),
+        password.to_string(),
+    );
+
+    // Scanning processes and talking to a running game block
+    tokio::task::spawn_blocking(move || {
+        match check_running_game(&executable_dir, &ip, port, &password, policy) {
+            Some(outcome) => Ok(outcome),
+            None => Ok(LaunchOutcome::Launched),
+        }
+    })
+    .await
+    .map_err(|e| LauncherError::Other(e.to_string()))?
 }
 
 #[cfg(target_os = "windows")]
@@ -29,57 +124,82 @@ pub async fn run_samp(
     omp_file: &str,
     password: &str,
     discord: bool,
-) -> Result<(), String> {
-    // Prepare the command to spawn the executable
-    let mut cmd = Command::new(format!("{}/gta_sa.exe", executable_dir));
-
-    let mut ready_for_exec = cmd
-        .arg("-c")
-        .arg("-n")
-        .arg(name)
-        .arg("-h")
-        .arg(ip)
-        .arg("-p")
-        .arg(format!("{}", port));
-
-    if !password.is_empty() {
-        ready_for_exec = ready_for_exec.arg("-z").arg(password);
-    }
+    policy: LaunchPolicy,
+) -> Result<LaunchOutcome, LauncherError> {
+    let (name, ip, executable_dir, dll_path, omp_file, password) = (
+        name.to_string(),
+        ip.to_string(),
+        executable_dir.to_string(),
+        dll_path.to_string(),
+        omp_file.to_string(),
+        password.to_string(),
+    );
 
-    if discord {
-        ready_for_exec = ready_for_exec.arg("--discord");
-    }
-
-    let process = ready_for_exec.current_dir(executable_dir).spawn();
+    // Scanning processes, hashing gta_sa.exe and waiting for the injected DLLs
+    // all block
+    tokio::task::spawn_blocking(move || {
+        if let Some(outcome) = check_running_game(&executable_dir, &ip, port, &password, policy) {
+            return Ok(outcome);
+        }
 
-    match process {
-        Ok(p) => {
-            // let target_process = .unwrap();
-            match inject_dll(p.id(), dll_path, 0, false) {
-                Ok(_) => inject_dll(p.id(), omp_file, 0, false),
-                Err(e) => {
-                    return Err(e);
-                }
+        // Executables we can't recognize are still given a chance, only refuse the
+        // ones we know the client can't hook into
+        if let Ok(exe) = gta_exe::get_gta_exe_version(&executable_dir) {
+            if exe.samp == gta_exe::Compatibility::Incompatible
+                || exe.omp == gta_exe::Compatibility::Incompatible
+            {
+                info!(
+                    "[injector.rs] Unsupported gta_sa.exe in {} (md5: {})",
+                    executable_dir, exe.md5
+                );
+                return Err(LauncherError::UnsupportedGtaExe(format!(
+                    "{:?} (md5: {})",
+                    exe.version, exe.md5
+                )));
             }
         }
-        Err(e) => {
-            info!("[injector.rs] Process creation failed: {}", e.to_string());
 
-            let mut raw_os_err = 0;
-            if e.raw_os_error().is_some() {
-                raw_os_err = e.raw_os_error().get_or_insert(0).to_owned();
-            }
+        // Prepare the command to spawn the executable
+        let mut cmd = Command::new(format!("{}/gta_sa.exe", executable_dir));
 
-            if raw_os_err == 740 {
-                return Err("need_admin".to_string());
-            }
+        let mut ready_for_exec = cmd
+            .arg("-c")
+            .arg("-n")
+            .arg(&name)
+            .arg("-h")
+            .arg(&ip)
+            .arg("-p")
+            .arg(format!("{}", port));
 
-            return Err(format!(
-                "Spawning process failed (error code: {})",
-                raw_os_err
-            ));
+        if !password.is_empty() {
+            ready_for_exec = ready_for_exec.arg("-z").arg(&password);
         }
-    }
+
+        if discord {
+            ready_for_exec = ready_for_exec.arg("--discord");
+        }
+
+        let process = ready_for_exec.current_dir(&executable_dir).spawn();
+
+        match process {
+            Ok(p) => {
+                // let target_process = .unwrap();
+                inject_dll(p.id(), &dll_path, 0, false)?;
+                inject_dll(p.id(), &omp_file, 0, false)?;
+                playtime::track_session(p, &ip, port, &executable_dir);
+                Ok(LaunchOutcome::Launched)
+            }
+            Err(e) => {
+                info!("[injector.rs] Process creation failed: {}", e.to_string());
+                Err(LauncherError::from_io(
+                    e,
+                    format!("{}/gta_sa.exe", executable_dir),
+                ))
+            }
+        }
+    })
+    .await
+    .map_err(|e| LauncherError::Other(e.to_string()))?
 }
 
 #[cfg(target_os = "windows")]
@@ -88,7 +208,7 @@ pub fn inject_dll(
     dll_path: &str,
     times: u32,
     waiting_for_vorbis: bool,
-) -> Result<(), String> {
+) -> Result<(), LauncherError> {
     use winapi::{
         shared::minwindef::{FALSE, HMODULE},
         um::{
@@ -166,7 +286,10 @@ pub fn inject_dll(
                         if !waiting_for_vorbis {
                             return inject_dll(child, dll_path, 0, true);
                         }
-                        Err(format!("Injecting dll failed: {}", e.to_string()))
+                        Err(LauncherError::Other(format!(
+                            "Injecting dll failed: {}",
+                            e.to_string()
+                        )))
                     } else {
                         if !waiting_for_vorbis {
                             inject_dll(child, dll_path, times + 1, false)
@@ -179,7 +302,10 @@ pub fn inject_dll(
         }
         Err(e) => {
             info!("[injector.rs] Process creation failed: {}", e.to_string());
-            Err(format!("Finding GTASA process failed: {}", e.to_string()))
+            Err(LauncherError::NotFound(format!(
+                "Finding GTASA process failed: {}",
+                e.to_string()
+            )))
         }
     }
 }
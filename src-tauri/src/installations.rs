@@ -0,0 +1,335 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::errors::LauncherError;
+use crate::gta_exe::{self, Compatibility, GtaExeVersion};
+use crate::helpers::{self, JsonStore};
+use crate::mods;
+use crate::process::{self, GAME_EXECUTABLE};
+use crate::samp_version::{self, SampClientInfo};
+
+static INSTALLATIONS_STORE: JsonStore = JsonStore::new("installations.json");
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Installation {
+    pub id: String,
+    pub name: String,
+    pub path: String,
+    pub exe: Option<GtaExeVersion>,
+    /// samp.dll found in the installation, `None` if there's none
+    pub samp: Option<SampClientInfo>,
+    /// Ids of the mods installed through the mod manager
+    pub mods: Vec<String>,
+    pub added_at: u64,
+    /// Last time the installation was scanned
+    pub scanned_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct InstallationsData {
+    installations: Vec<Installation>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallationStatus {
+    Ok,
+    /// The directory doesn't exist anymore
+    Missing,
+    /// The directory exists but there's no gta_sa.exe in it
+    NoExecutable,
+    /// gta_sa.exe is a release the clients can't hook into
+    Unsupported,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InstallationValidation {
+    pub id: String,
+    pub status: InstallationStatus,
+    /// The installation changed since it was last scanned (different exe or
+    /// samp.dll)
+    pub changed: bool,
+}
+
+fn load_installations_data() -> Result<InstallationsData, LauncherError> {
+    INSTALLATIONS_STORE.load()
+}
+
+fn save_installations_data(data: &InstallationsData) -> Result<(), LauncherError> {
+    INSTALLATIONS_STORE.save(data)
+}
+
+fn lock() -> Result<std::sync::MutexGuard<'static, ()>, LauncherError> {
+    INSTALLATIONS_STORE.lock()
+}
+
+/// Detects everything we keep about an installation. Detection failures are
+/// stored as `None` rather than failing, `validate` reports what's wrong.
+fn scan(installation: &mut Installation) {
+    installation.exe = gta_exe::get_gta_exe_version(&installation.path).ok();
+    installation.samp = samp_version::get_installed_samp_version(&installation.path)
+        .ok()
+        .flatten();
+    installation.mods = mods::list_mods(Some(&installation.path))
+        .map(|list| list.into_iter().map(|m| m.id).collect())
+        .unwrap_or_default();
+    installation.scanned_at = helpers::now();
+}
+
+fn validate(installation: &Installation) -> InstallationValidation {
+    let dir = Path::new(&installation.path);
+    let exe = gta_exe::get_gta_exe_version(&installation.path).ok();
+    let samp = samp_version::get_installed_samp_version(&installation.path)
+        .ok()
+        .flatten();
+
+    let status = if !dir.is_dir() {
+        InstallationStatus::Missing
+    } else if !dir.join(GAME_EXECUTABLE).is_file() {
+        InstallationStatus::NoExecutable
+    } else {
+        match &exe {
+            Some(exe)
+                if exe.samp == Compatibility::Incompatible
+                    || exe.omp == Compatibility::Incompatible =>
+            {
+                InstallationStatus::Unsupported
+            }
+            _ => InstallationStatus::Ok,
+        }
+    };
+
+    let md5_of_exe = |exe: &Option<GtaExeVersion>| exe.as_ref().map(|exe| exe.md5.clone());
+    let md5_of_samp = |samp: &Option<SampClientInfo>| samp.as_ref().map(|samp| samp.md5.clone());
+
+    InstallationValidation {
+        id: installation.id.clone(),
+        status,
+        changed: status != InstallationStatus::Missing
+            && (md5_of_exe(&exe) != md5_of_exe(&installation.exe)
+                || md5_of_samp(&samp) != md5_of_samp(&installation.samp)),
+    }
+}
+
+/// Registers a game directory as an installation. Every directory can only be
+/// registered once.
+pub fn add_installation(path: &str, name: Option<String>) -> Result<Installation, LauncherError> {
+    let _guard = lock()?;
+    let mut data = load_installations_data()?;
+
+    if !Path::new(path).join(GAME_EXECUTABLE).is_file() {
+        return Err(LauncherError::NotFound(format!(
+            "No {} in {}",
+            GAME_EXECUTABLE, path
+        )));
+    }
+
+    if let Some(existing) = data
+        .installations
+        .iter()
+        .find(|installation| process::is_same_game_dir(&installation.path, path))
+    {
+        return Err(LauncherError::AlreadyExists(format!(
+            "{} is already registered as {}",
+            path, existing.name
+        )));
+    }
+
+    let added_at = helpers::now();
+    let mut installation = Installation {
+        id: format!(
+            "{:x}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0)
+        ),
+        name: name.unwrap_or_else(|| {
+            Path::new(path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.to_string())
+        }),
+        path: path.to_string(),
+        exe: None,
+        samp: None,
+        mods: Vec::new(),
+        added_at,
+        scanned_at: added_at,
+    };
+    scan(&mut installation);
+
+    info!(
+        "[installations.rs] Added installation {} ({})",
+        installation.name, installation.path
+    );
+
+    data.installations.push(installation.clone());
+    save_installations_data(&data)?;
+    Ok(installation)
+}
+
+/// Detects the exe version, client version and mods of an installation again
+pub fn scan_installation(id: &str) -> Result<Installation, LauncherError> {
+    let _guard = lock()?;
+    let mut data = load_installations_data()?;
+
+    let installation = data
+        .installations
+        .iter_mut()
+        .find(|installation| installation.id == id)
+        .ok_or_else(|| LauncherError::NotFound(format!("No installation with id {}", id)))?;
+    scan(installation);
+    let installation = installation.clone();
+
+    save_installations_data(&data)?;
+    Ok(installation)
+}
+
+pub fn validate_installations() -> Result<Vec<InstallationValidation>, LauncherError> {
+    let _guard = lock()?;
+    Ok(load_installations_data()?
+        .installations
+        .iter()
+        .map(validate)
+        .collect())
+}
+
+/// Forgets an installation, the game directory itself is left alone
+pub fn remove_installation(id: &str) -> Result<(), LauncherError> {
+    let _guard = lock()?;
+    let mut data = load_installations_data()?;
+
+    let count = data.installations.len();
+    data.installations
+        .retain(|installation| installation.id != id);
+    if data.installations.len() == count {
+        return Err(LauncherError::NotFound(format!(
+            "No installation with id {}",
+            id
+        )));
+    }
+
+    save_installations_data(&data)
+}
+
+pub fn get_installation(id: &str) -> Result<Installation, LauncherError> {
+    let _guard = lock()?;
+    load_installations_data()?
+        .installations
+        .into_iter()
+        .find(|installation| installation.id == id)
+        .ok_or_else(|| LauncherError::NotFound(format!("No installation with id {}", id)))
+}
+
+pub fn list_installations() -> Result<Vec<Installation>, LauncherError> {
+    let _guard = lock()?;
+    Ok(load_installations_data()?.installations)
+}
+
+#[tauri::command]
+pub fn get_installations() -> Result<Vec<Installation>, LauncherError> {
+    list_installations()
+}
+
+#[tauri::command]
+pub fn get_installation_by_id(id: String) -> Result<Installation, LauncherError> {
+    get_installation(&id)
+}
+
+#[tauri::command]
+pub async fn add_game_installation(
+    path: String,
+    name: Option<String>,
+) -> Result<Installation, LauncherError> {
+    tokio::task::spawn_blocking(move || add_installation(&path, name))
+        .await
+        .map_err(|e| LauncherError::Other(e.to_string()))?
+}
+
+#[tauri::command]
+pub async fn scan_game_installation(id: String) -> Result<Installation, LauncherError> {
+    tokio::task::spawn_blocking(move || scan_installation(&id))
+        .await
+        .map_err(|e| LauncherError::Other(e.to_string()))?
+}
+
+#[tauri::command]
+pub async fn validate_game_installations() -> Result<Vec<InstallationValidation>, LauncherError> {
+    tokio::task::spawn_blocking(validate_installations)
+        .await
+        .map_err(|e| LauncherError::Other(e.to_string()))?
+}
+
+#[tauri::command]
+pub fn remove_game_installation(id: String) -> Result<(), LauncherError> {
+    remove_installation(&id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn game_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "omp-installations-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn installation(path: &Path) -> Installation {
+        Installation {
+            id: "test".to_string(),
+            name: "Test".to_string(),
+            path: path.to_string_lossy().to_string(),
+            exe: None,
+            samp: None,
+            mods: Vec::new(),
+            added_at: 0,
+            scanned_at: 0,
+        }
+    }
+
+    #[test]
+    fn reports_missing_directories_and_executables() {
+        let dir = game_dir("missing");
+        let validation = validate(&installation(&dir));
+        assert_eq!(validation.status, InstallationStatus::NoExecutable);
+        assert!(!validation.changed);
+
+        fs::remove_dir_all(&dir).unwrap();
+        let validation = validate(&installation(&dir));
+        assert_eq!(validation.status, InstallationStatus::Missing);
+        assert!(!validation.changed);
+    }
+
+    #[test]
+    fn detects_changed_files() {
+        let dir = game_dir("changed");
+        fs::write(dir.join(GAME_EXECUTABLE), "not really an exe").unwrap();
+        let mut installation = installation(&dir);
+
+        // Unrecognized executables get the benefit of the doubt
+        let validation = validate(&installation);
+        assert_eq!(validation.status, InstallationStatus::Ok);
+        assert!(validation.changed);
+
+        installation.exe = gta_exe::get_gta_exe_version(&installation.path).ok();
+        assert!(!validate(&installation).changed);
+
+        fs::write(dir.join("samp.dll"), "not really a dll").unwrap();
+        assert!(validate(&installation).changed);
+
+        installation.samp = samp_version::get_installed_samp_version(&installation.path).unwrap();
+        assert!(!validate(&installation).changed);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
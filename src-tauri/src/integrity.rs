@@ -0,0 +1,377 @@
+use log::info;
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sevenz_rust::decompress_file;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::copy_engine;
+use crate::errors::LauncherError;
+use crate::helpers;
+
+/// Files the launcher puts into the game directory, taken from samp_clients.7z
+static DEFAULT_MANIFEST: Lazy<IntegrityManifest> = Lazy::new(|| {
+    serde_json::from_str(include_str!("../data/integrity_manifest.json"))
+        .expect("data/integrity_manifest.json is invalid")
+});
+
+fn default_required() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ManifestEntry {
+    /// Path relative to the game directory
+    pub path: String,
+    /// Where to restore the file from, relative to the launcher data directory
+    pub source: Option<String>,
+    pub size: Option<u64>,
+    pub md5: Option<String>,
+    pub sha256: Option<String>,
+    #[serde(default = "default_required")]
+    pub required: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct IntegrityManifest {
+    pub files: Vec<ManifestEntry>,
+    /// Directories (relative to the game directory) that only contain files from
+    /// this manifest, anything else found in them is reported as extra
+    #[serde(default)]
+    pub owned_dirs: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    Ok,
+    Missing,
+    Modified,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FileReport {
+    pub path: String,
+    pub status: FileStatus,
+    pub required: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IntegrityReport {
+    pub game_dir: String,
+    pub files: Vec<FileReport>,
+    pub missing: Vec<String>,
+    pub modified: Vec<String>,
+    pub extra: Vec<String>,
+    /// No required file is missing or modified
+    pub healthy: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum RepairStage {
+    Scanning,
+    Extracting,
+    Restoring,
+    Done,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RepairProgress {
+    pub stage: RepairStage,
+    pub current: usize,
+    pub total: usize,
+    pub file: Option<String>,
+}
+
+/// SHA-256 counterpart of `copy_engine::file_digest`
+fn sha256_digest(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn hash_matches(
+    expected: &Option<String>,
+    digest: impl FnOnce() -> io::Result<String>,
+) -> io::Result<bool> {
+    match expected {
+        Some(expected) => Ok(expected.eq_ignore_ascii_case(&digest()?)),
+        None => Ok(true),
+    }
+}
+
+/// Checks a single file against its manifest entry. The size is compared first
+/// so obviously broken files don't need to be hashed.
+pub fn check_file(path: &Path, entry: &ManifestEntry) -> FileStatus {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => return FileStatus::Missing,
+    };
+
+    if let Some(size) = entry.size {
+        if metadata.len() != size {
+            return FileStatus::Modified;
+        }
+    }
+
+    if entry.md5.is_none() && entry.sha256.is_none() {
+        return FileStatus::Ok;
+    }
+
+    let md5_matches = hash_matches(&entry.md5, || {
+        copy_engine::file_digest(path).map(|digest| format!("{:x}", digest))
+    })
+    .unwrap_or(false);
+    // No need to hash the file again once it's known to differ
+    let sha256_matches =
+        md5_matches && hash_matches(&entry.sha256, || sha256_digest(path)).unwrap_or(false);
+
+    if sha256_matches {
+        FileStatus::Ok
+    } else {
+        FileStatus::Modified
+    }
+}
+
+/// Manifests can come from the frontend, so make sure none of their paths
+/// point outside of the game or launcher data directory
+fn validate_manifest(manifest: &IntegrityManifest) -> Result<(), LauncherError> {
+    let paths = manifest
+        .files
+        .iter()
+        .flat_map(|entry| std::iter::once(&entry.path).chain(entry.source.as_ref()))
+        .chain(manifest.owned_dirs.iter());
+
+    for path in paths {
+        if !copy_engine::is_safe_relative_path(path) {
+            return Err(LauncherError::InvalidSetting(format!(
+                "Manifest path {} leaves its directory",
+                path
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Lists files in the manifest's owned directories that the manifest doesn't know
+fn find_extra_files(game_dir: &Path, manifest: &IntegrityManifest) -> Vec<String> {
+    let known: HashSet<String> = manifest
+        .files
+        .iter()
+        .map(|entry| copy_engine::normalize_relative_path(&entry.path))
+        .collect();
+
+    let mut extra = Vec::new();
+    let mut pending: Vec<PathBuf> = manifest
+        .owned_dirs
+        .iter()
+        .map(|dir| game_dir.join(dir))
+        .collect();
+
+    while let Some(dir) = pending.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if let Ok(relative) = path.strip_prefix(game_dir) {
+                let relative = relative.to_string_lossy().replace('\\', "/");
+                if !known.contains(&relative.to_lowercase()) {
+                    extra.push(relative);
+                }
+            }
+        }
+    }
+
+    extra.sort();
+    extra
+}
+
+/// Scans a game directory against the manifest, hashing files in parallel
+pub fn scan_game_dir(game_dir: &str, manifest: &IntegrityManifest) -> IntegrityReport {
+    let root = Path::new(game_dir);
+
+    let files: Vec<FileReport> = manifest
+        .files
+        .par_iter()
+        .map(|entry| FileReport {
+            path: entry.path.clone(),
+            status: check_file(&root.join(&entry.path), entry),
+            required: entry.required,
+        })
+        .collect();
+
+    let paths_with_status = |status: FileStatus| -> Vec<String> {
+        files
+            .iter()
+            .filter(|file| file.status == status)
+            .map(|file| file.path.clone())
+            .collect()
+    };
+
+    IntegrityReport {
+        game_dir: game_dir.to_string(),
+        missing: paths_with_status(FileStatus::Missing),
+        modified: paths_with_status(FileStatus::Modified),
+        extra: find_extra_files(root, manifest),
+        healthy: files
+            .iter()
+            .all(|file| !file.required || file.status == FileStatus::Ok),
+        files,
+    }
+}
+
+fn copy_into_game_dir(source: &Path, target: &Path) -> Result<(), LauncherError> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|e| LauncherError::from_io(e, parent))?;
+    }
+
+    match fs::copy(source, target) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            info!(
+                "[integrity.rs] Restoring {} failed: {}",
+                target.display(),
+                e
+            );
+            Err(LauncherError::from_io(e, target))
+        }
+    }
+}
+
+/// Restores missing and modified files from the launcher's own copies. If those
+/// are broken as well, samp_clients.7z is extracted again first.
+pub fn repair_game_dir(
+    game_dir: &str,
+    manifest: &IntegrityManifest,
+    on_progress: impl Fn(RepairProgress),
+) -> Result<IntegrityReport, LauncherError> {
+    let total = manifest.files.len();
+    on_progress(RepairProgress {
+        stage: RepairStage::Scanning,
+        current: 0,
+        total,
+        file: None,
+    });
+
+    let report = scan_game_dir(game_dir, manifest);
+    let broken: Vec<&ManifestEntry> = manifest
+        .files
+        .iter()
+        .filter(|entry| {
+            report.missing.contains(&entry.path) || report.modified.contains(&entry.path)
+        })
+        .collect();
+
+    if broken.is_empty() {
+        on_progress(RepairProgress {
+            stage: RepairStage::Done,
+            current: 0,
+            total: 0,
+            file: None,
+        });
+        return Ok(report);
+    }
+
+    let data_dir = helpers::get_launcher_data_dir()?;
+    let sources_are_valid = broken.iter().all(|entry| match &entry.source {
+        Some(source) => check_file(&data_dir.join(source), entry) == FileStatus::Ok,
+        None => true,
+    });
+
+    if !sources_are_valid {
+        on_progress(RepairProgress {
+            stage: RepairStage::Extracting,
+            current: 0,
+            total: broken.len(),
+            file: Some("samp_clients.7z".to_string()),
+        });
+
+        let samp_dir = data_dir.join("samp");
+        decompress_file(samp_dir.join("samp_clients.7z"), &samp_dir)
+            .map_err(|e| LauncherError::Io(format!("Extracting samp_clients.7z failed: {}", e)))?;
+    }
+
+    for (i, entry) in broken.iter().enumerate() {
+        on_progress(RepairProgress {
+            stage: RepairStage::Restoring,
+            current: i + 1,
+            total: broken.len(),
+            file: Some(entry.path.clone()),
+        });
+
+        let source = match &entry.source {
+            Some(source) => data_dir.join(source),
+            None => continue,
+        };
+
+        if check_file(&source, entry) != FileStatus::Ok {
+            return Err(LauncherError::NotFound(format!(
+                "No valid copy of {} to restore from",
+                entry.path
+            )));
+        }
+
+        copy_into_game_dir(&source, &Path::new(game_dir).join(&entry.path))?;
+    }
+
+    on_progress(RepairProgress {
+        stage: RepairStage::Done,
+        current: broken.len(),
+        total: broken.len(),
+        file: None,
+    });
+
+    Ok(scan_game_dir(game_dir, manifest))
+}
+
+#[tauri::command]
+pub async fn check_game_dir_integrity(
+    game_dir: String,
+    manifest: Option<IntegrityManifest>,
+) -> Result<IntegrityReport, LauncherError> {
+    let manifest = manifest.unwrap_or_else(|| DEFAULT_MANIFEST.clone());
+    validate_manifest(&manifest)?;
+
+    tokio::task::spawn_blocking(move || scan_game_dir(&game_dir, &manifest))
+        .await
+        .map_err(|e| LauncherError::Other(e.to_string()))
+}
+
+/// Scans and repairs the game directory, emitting `integrity-progress` events
+#[tauri::command]
+pub async fn repair_game_dir_integrity(
+    window: tauri::Window,
+    game_dir: String,
+    manifest: Option<IntegrityManifest>,
+) -> Result<IntegrityReport, LauncherError> {
+    let manifest = manifest.unwrap_or_else(|| DEFAULT_MANIFEST.clone());
+    validate_manifest(&manifest)?;
+
+    tokio::task::spawn_blocking(move || {
+        repair_game_dir(&game_dir, &manifest, |progress| {
+            let _ = window.emit("integrity-progress", progress);
+        })
+    })
+    .await
+    .map_err(|e| LauncherError::Other(e.to_string()))?
+}
@@ -2,13 +2,30 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 // use serde_json::json;
 mod background_thread;
+mod chatlog;
 mod commands;
+mod copy_engine;
+mod discovery;
+mod encoding;
+mod errors;
+mod gta_exe;
 mod helpers;
 mod injector;
+mod installations;
+mod integrity;
 mod ipc;
+mod mods;
+mod playtime;
+mod process;
 mod query;
 mod rpcs;
 mod samp;
+mod samp_client_cfg;
+mod samp_config;
+mod samp_sync;
+mod samp_version;
+mod screenshots;
+mod text;
 
 #[path = "nativestorage/lib.rs"]
 mod nativestorage;
@@ -23,7 +40,7 @@ use std::sync::Mutex;
 
 use background_thread::initialize_background_thread;
 use gumdrop::Options;
-use injector::run_samp;
+use injector::{run_samp, LaunchOutcome, LaunchPolicy};
 use log::{error, info, LevelFilter};
 // use std::io::Read;
 use std::fs;
@@ -140,7 +157,7 @@ Options:
                         
                         let omp_client_path = format!("{}/mp.open.launcher/omp/omp-client.dll", data_dir_str);
                         
-                        let _ = run_samp(
+                        match run_samp(
                             name,
                             host,
                             port,
@@ -149,8 +166,32 @@ Options:
                             &omp_client_path,
                             &password,
                             true,
+                            LaunchPolicy::Connect,
                         )
-                        .await;
+                        .await
+                        {
+                            Ok(LaunchOutcome::Launched) => {
+                                // Stay around until the game exits so the
+                                // session gets recorded
+                                playtime::wait_for_sessions();
+                            }
+                            Ok(LaunchOutcome::ConnectedToRunningGame { pid }) => {
+                                println!(
+                                    "Game is already running (pid: {}), connected it to {}:{} instead",
+                                    pid, host, port
+                                );
+                            }
+                            Ok(LaunchOutcome::AlreadyRunning { pid })
+                            | Ok(LaunchOutcome::Refused { pid }) => {
+                                println!(
+                                    "Game is already running from {} (pid: {})",
+                                    gamepath_str, pid
+                                );
+                            }
+                            Err(e) => {
+                                println!("Launching the game failed: {}", e);
+                            }
+                        }
                         info!("Attempted to run the game from command line");
                         exit(0)
                     } else {
@@ -258,19 +299,64 @@ Options:
         .invoke_handler(tauri::generate_handler![
             get_uri_scheme_value,
             commands::inject,
+            commands::connect_running_game,
             commands::get_gtasa_path_from_samp,
             commands::get_nickname_from_samp,
             commands::get_samp_favorite_list,
+            samp::export_samp_favorite_list,
             commands::rerun_as_admin,
             commands::resolve_hostname,
             commands::is_process_alive,
+            commands::get_game_processes,
+            commands::terminate_game_process,
             commands::log,
+            commands::exit_launcher,
+            copy_engine::copy_files_to_game_dir,
+            discovery::discover_gta_installations,
+            gta_exe::detect_gta_exe_version,
+            installations::get_installations,
+            installations::get_installation_by_id,
+            installations::add_game_installation,
+            installations::scan_game_installation,
+            installations::validate_game_installations,
+            installations::remove_game_installation,
+            integrity::check_game_dir_integrity,
+            integrity::repair_game_dir_integrity,
+            mods::install_mod_archive,
+            mods::get_installed_mods,
+            mods::uninstall_installed_mod,
+            playtime::get_playtime_summary,
+            playtime::get_server_playtime,
             query::query_server,
+            encoding::debug_detect_encoding,
+            samp_config::get_samp_config,
+            samp_config::write_samp_config,
+            samp_sync::sync_samp_config,
+            samp_client_cfg::get_samp_client_settings,
+            samp_client_cfg::set_samp_client_settings,
+            chatlog::get_archived_chatlogs,
+            chatlog::get_archived_chatlog,
+            chatlog::search_chatlogs,
+            chatlog::delete_archived_chatlog,
+            screenshots::get_screenshots,
+            screenshots::delete_screenshot,
+            screenshots::export_screenshots,
+            samp_version::detect_samp_version,
+            samp_version::get_known_samp_versions,
+            samp_version::select_samp_client,
             ipc::send_message_to_game
         ])
-        .run(tauri::generate_context!())
+        .build(tauri::generate_context!())
     {
-        Ok(_) => {}
+        Ok(app) => app.run(|_, event| {
+            // Exit isn't sent when the process is ended some other way, so
+            // flush as soon as an exit is requested too
+            if let tauri::RunEvent::Exit | tauri::RunEvent::ExitRequested { .. } = event {
+                if let Err(e) = nativestorage::flush_storage() {
+                    error!("[main.rs] Flushing storage on exit failed: {}", e);
+                }
+            }
+        }),
         Err(e) => {
             error!("[main.rs] Running tauri instance failed: {}", e.to_string());
         }
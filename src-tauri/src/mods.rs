@@ -0,0 +1,545 @@
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use sevenz_rust::decompress_file;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::copy_engine::{self, CopyProgress, OverwritePolicy};
+use crate::errors::LauncherError;
+use crate::helpers::{self, JsonStore};
+use crate::process;
+
+static MODS_STORE: JsonStore = JsonStore::new("mods/mods.json");
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ModFile {
+    /// Path relative to the game directory
+    pub path: String,
+    /// The file existed before the mod was installed, its original is kept in
+    /// the mod's backup directory
+    pub replaced: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InstalledMod {
+    pub id: String,
+    pub name: String,
+    /// Archive the mod was installed from
+    pub archive: String,
+    pub game_dir: String,
+    pub installed_at: u64,
+    pub files: Vec<ModFile>,
+    /// Directories the mod created, relative to the game directory, removed
+    /// again on uninstall if they're empty
+    pub created_dirs: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ModsData {
+    mods: Vec<InstalledMod>,
+}
+
+fn get_mods_dir() -> Result<PathBuf, String> {
+    Ok(helpers::get_launcher_data_dir()?.join("mods"))
+}
+
+fn get_backup_dir(id: &str) -> Result<PathBuf, String> {
+    Ok(get_mods_dir()?.join("backups").join(id))
+}
+
+fn load_mods_data() -> Result<ModsData, LauncherError> {
+    MODS_STORE.load()
+}
+
+fn save_mods_data(data: &ModsData) -> Result<(), LauncherError> {
+    MODS_STORE.save(data)
+}
+
+/// Directories found directly in the game directory. An archive whose only
+/// top level entry is one of these is already laid out like the game directory.
+const GAME_DIRS: [&str; 12] = [
+    "anim",
+    "audio",
+    "cleo",
+    "data",
+    "models",
+    "modloader",
+    "movies",
+    "samp",
+    "scripts",
+    "text",
+    "moonloader",
+    "reshade-shaders",
+];
+
+/// Files and directories only found in the root of a game directory
+const GAME_ROOT_MARKERS: [&str; 4] = ["gta_sa.exe", "models", "samp", "samp.dll"];
+
+fn contains_game_root_marker(dir: &Path) -> bool {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries.flatten().any(|entry| {
+            let name = entry.file_name().to_string_lossy().to_lowercase();
+            GAME_ROOT_MARKERS.contains(&name.as_str())
+        }),
+        Err(_) => false,
+    }
+}
+
+/// Archives often wrap everything in a single top level folder named after the
+/// mod, in that case the folder's content is what belongs in the game directory.
+/// A single folder that's a game directory itself (e.g. `cleo/`) is kept,
+/// unless it looks like a whole game directory wrapped in a folder.
+fn find_content_root(extracted: &Path) -> PathBuf {
+    let entries: Vec<_> = match fs::read_dir(extracted) {
+        Ok(entries) => entries.flatten().collect(),
+        Err(_) => return extracted.to_path_buf(),
+    };
+
+    match entries.as_slice() {
+        [entry] if entry.path().is_dir() => {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_lowercase();
+            if contains_game_root_marker(&path) || !GAME_DIRS.contains(&name.as_str()) {
+                path
+            } else {
+                extracted.to_path_buf()
+            }
+        }
+        _ => extracted.to_path_buf(),
+    }
+}
+
+/// Files and directories a mod added to the game directory
+struct InstalledFiles {
+    files: Vec<ModFile>,
+    created_dirs: Vec<String>,
+}
+
+fn ensure_game_not_running(game_dir: &str) -> Result<(), LauncherError> {
+    if process::find_game_processes_in_dir(game_dir).is_empty() {
+        Ok(())
+    } else {
+        Err(LauncherError::Busy(format!(
+            "The game is running from {}",
+            game_dir
+        )))
+    }
+}
+
+fn install_extracted(
+    data: &ModsData,
+    extract_dir: &Path,
+    game_dir: &str,
+    backup_dir: &Path,
+    on_progress: impl Fn(CopyProgress),
+) -> Result<InstalledFiles, LauncherError> {
+    let plan = copy_engine::plan_copy(
+        find_content_root(extract_dir),
+        game_dir,
+        OverwritePolicy::Always,
+    )?;
+
+    let conflicts: Vec<String> = data
+        .mods
+        .iter()
+        .filter(|m| process::is_same_game_dir(&m.game_dir, game_dir))
+        .flat_map(|m| {
+            m.files
+                .iter()
+                .filter(|file| {
+                    plan.files.iter().any(|planned| {
+                        copy_engine::normalize_relative_path(&planned.relative)
+                            == copy_engine::normalize_relative_path(&file.path)
+                    })
+                })
+                .map(move |file| format!("{} ({})", file.path, m.name))
+        })
+        .collect();
+
+    if !conflicts.is_empty() {
+        return Err(LauncherError::AlreadyExists(format!(
+            "Files are owned by other mods: {}",
+            conflicts.join(", ")
+        )));
+    }
+
+    let report = copy_engine::execute_copy(&plan, backup_dir, on_progress)?;
+
+    let mut files: Vec<ModFile> = report
+        .created
+        .into_iter()
+        .map(|path| ModFile {
+            path,
+            replaced: false,
+        })
+        .collect();
+    files.extend(report.replaced.into_iter().map(|path| ModFile {
+        path,
+        replaced: true,
+    }));
+
+    let created_dirs = plan
+        .dirs
+        .iter()
+        .filter_map(|dir| dir.strip_prefix(game_dir).ok())
+        .map(|dir| dir.to_string_lossy().replace('\\', "/"))
+        .filter(|dir| !dir.is_empty())
+        .collect();
+
+    Ok(InstalledFiles {
+        files,
+        created_dirs,
+    })
+}
+
+/// Extracts a mod archive and copies its content into the game directory. Files
+/// it overwrites are backed up so uninstalling restores them. Installing over
+/// files another mod owns is refused, uninstalling either of them would break
+/// the other one.
+pub fn install_mod(
+    archive: &str,
+    game_dir: &str,
+    name: Option<String>,
+    on_progress: impl Fn(CopyProgress),
+) -> Result<InstalledMod, LauncherError> {
+    ensure_game_not_running(game_dir)?;
+
+    let _guard = MODS_STORE.lock()?;
+    let mut data = load_mods_data()?;
+
+    let id = format!(
+        "{:x}",
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0)
+    );
+    let name = name.unwrap_or_else(|| {
+        Path::new(archive)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| id.clone())
+    });
+
+    let backup_dir = get_backup_dir(&id)?;
+    let extract_dir = std::env::temp_dir().join(format!("omp-launcher-mod-{}", id));
+    decompress_file(archive, &extract_dir).map_err(|e| {
+        let _ = fs::remove_dir_all(&extract_dir);
+        LauncherError::Io(format!("Extracting {} failed: {}", archive, e))
+    })?;
+
+    let result = install_extracted(&data, &extract_dir, game_dir, &backup_dir, on_progress);
+
+    let _ = fs::remove_dir_all(&extract_dir);
+    let installed_files = match result {
+        Ok(installed_files) => installed_files,
+        Err(e) => {
+            // Backups of files that couldn't be restored are all that's left
+            // of them
+            if !matches!(e, LauncherError::RollbackIncomplete(_)) {
+                let _ = fs::remove_dir_all(&backup_dir);
+            }
+            return Err(e);
+        }
+    };
+    let installed = InstalledMod {
+        id,
+        name,
+        archive: archive.to_string(),
+        game_dir: game_dir.to_string(),
+        installed_at: helpers::now(),
+        files: installed_files.files,
+        created_dirs: installed_files.created_dirs,
+    };
+
+    info!(
+        "[mods.rs] Installed {} into {} ({} files)",
+        installed.name,
+        game_dir,
+        installed.files.len()
+    );
+
+    data.mods.push(installed.clone());
+    save_mods_data(&data)?;
+    Ok(installed)
+}
+
+/// Removes the files a mod added and puts back the ones it replaced. The mod
+/// stays registered if anything goes wrong, so uninstalling can be retried.
+pub fn uninstall_mod(id: &str) -> Result<(), LauncherError> {
+    let _guard = MODS_STORE.lock()?;
+    let mut data = load_mods_data()?;
+
+    let index = data
+        .mods
+        .iter()
+        .position(|m| m.id == id)
+        .ok_or_else(|| LauncherError::NotFound(format!("No installed mod with id {}", id)))?;
+    let installed = &data.mods[index];
+
+    ensure_game_not_running(&installed.game_dir)?;
+
+    let backup_dir = get_backup_dir(id)?;
+    restore_game_dir(installed, &backup_dir)?;
+
+    info!(
+        "[mods.rs] Uninstalled {} from {}",
+        installed.name, installed.game_dir
+    );
+
+    if let Err(e) = fs::remove_dir_all(&backup_dir) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            error!(
+                "[mods.rs] Removing backups of {} failed: {}",
+                installed.name, e
+            );
+        }
+    }
+
+    data.mods.remove(index);
+    save_mods_data(&data)
+}
+
+/// Removes the files a mod added and puts back the originals of the ones it
+/// replaced from `backup_dir`
+fn restore_game_dir(installed: &InstalledMod, backup_dir: &Path) -> Result<(), LauncherError> {
+    let game_dir = Path::new(&installed.game_dir);
+
+    for file in &installed.files {
+        let target = game_dir.join(&file.path);
+        if file.replaced {
+            let backup = backup_dir.join(&file.path);
+            fs::copy(&backup, &target).map_err(|e| LauncherError::from_io(e, &target))?;
+        } else {
+            match fs::remove_file(&target) {
+                Ok(_) => {}
+                // Already deleted by hand, that's what we wanted anyway
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(LauncherError::from_io(e, &target)),
+            }
+        }
+    }
+
+    // Deepest first, so nested directories are emptied before their parents
+    let mut created_dirs = installed.created_dirs.clone();
+    created_dirs.sort_by_key(|dir| std::cmp::Reverse(dir.len()));
+    for dir in created_dirs {
+        // Fails if the user put something else in there, which is fine
+        let _ = fs::remove_dir(game_dir.join(dir));
+    }
+    Ok(())
+}
+
+/// Lists installed mods, optionally only the ones in the given game directory
+pub fn list_mods(game_dir: Option<&str>) -> Result<Vec<InstalledMod>, LauncherError> {
+    let _guard = MODS_STORE.lock()?;
+    let data = load_mods_data()?;
+
+    Ok(data
+        .mods
+        .into_iter()
+        .filter(|m| match game_dir {
+            Some(game_dir) => process::is_same_game_dir(&m.game_dir, game_dir),
+            None => true,
+        })
+        .collect())
+}
+
+/// Installs a mod archive (.7z), emitting `mod-install-progress` events
+#[tauri::command]
+pub async fn install_mod_archive(
+    window: tauri::Window,
+    archive: String,
+    game_dir: String,
+    name: Option<String>,
+) -> Result<InstalledMod, LauncherError> {
+    tokio::task::spawn_blocking(move || {
+        install_mod(&archive, &game_dir, name, |progress| {
+            let _ = window.emit("mod-install-progress", progress);
+        })
+    })
+    .await
+    .map_err(|e| LauncherError::Other(e.to_string()))?
+}
+
+#[tauri::command]
+pub fn get_installed_mods(game_dir: Option<String>) -> Result<Vec<InstalledMod>, LauncherError> {
+    list_mods(game_dir.as_deref())
+}
+
+#[tauri::command]
+pub async fn uninstall_installed_mod(id: String) -> Result<(), LauncherError> {
+    tokio::task::spawn_blocking(move || uninstall_mod(&id))
+        .await
+        .map_err(|e| LauncherError::Other(e.to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extracted_dir(name: &str, files: &[&str]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("omp-mods-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn unwraps_mod_folder() {
+        let dir = extracted_dir("wrapped", &["Cool Mod/cleo/mod.cs", "Cool Mod/readme.txt"]);
+        assert_eq!(find_content_root(&dir), dir.join("Cool Mod"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_game_dir_folder() {
+        let dir = extracted_dir("cleo", &["cleo/mod.cs", "cleo/mod.ini"]);
+        assert_eq!(find_content_root(&dir), dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let dir = extracted_dir("models", &["Models/generic.txd"]);
+        assert_eq!(find_content_root(&dir), dir);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unwraps_wrapped_game_dir() {
+        let dir = extracted_dir(
+            "game",
+            &[
+                "GTA San Andreas/gta_sa.exe",
+                "GTA San Andreas/models/gta3.img",
+            ],
+        );
+        assert_eq!(find_content_root(&dir), dir.join("GTA San Andreas"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Named like a game directory folder, but holds a whole game directory
+        let dir = extracted_dir(
+            "samp",
+            &["samp/gta_sa.exe", "samp/samp.dll", "samp/cleo/mod.cs"],
+        );
+        assert_eq!(find_content_root(&dir), dir.join("samp"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_multiple_entries() {
+        let dir = extracted_dir("flat", &["cleo/mod.cs", "modloader/mod/file.dff"]);
+        assert_eq!(find_content_root(&dir), dir);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn installed_mod(game_dir: &Path, installed_files: InstalledFiles) -> InstalledMod {
+        InstalledMod {
+            id: "test".to_string(),
+            name: "Test".to_string(),
+            archive: "test.7z".to_string(),
+            game_dir: game_dir.to_string_lossy().to_string(),
+            installed_at: 0,
+            files: installed_files.files,
+            created_dirs: installed_files.created_dirs,
+        }
+    }
+
+    #[test]
+    fn uninstall_restores_the_game_dir() {
+        let extracted = extracted_dir("round-trip-mod", &[]);
+        write(&extracted.join("Cool Mod/models/gta3.img"), "modded");
+        write(&extracted.join("Cool Mod/cleo/new/mod.cs"), "mod");
+        let game_dir = extracted_dir("round-trip-game", &[]);
+        write(&game_dir.join("models/gta3.img"), "original");
+        let backup_dir = game_dir.with_extension("backups");
+        let _ = fs::remove_dir_all(&backup_dir);
+
+        let installed_files = install_extracted(
+            &ModsData::default(),
+            &extracted,
+            &game_dir.to_string_lossy(),
+            &backup_dir,
+            |_| {},
+        )
+        .unwrap();
+        let installed = installed_mod(&game_dir, installed_files);
+
+        assert_eq!(
+            fs::read_to_string(game_dir.join("models/gta3.img")).unwrap(),
+            "modded"
+        );
+        assert!(game_dir.join("cleo/new/mod.cs").is_file());
+        let mut created_dirs = installed.created_dirs.clone();
+        created_dirs.sort();
+        assert_eq!(created_dirs, vec!["cleo", "cleo/new"]);
+
+        restore_game_dir(&installed, &backup_dir).unwrap();
+        assert_eq!(
+            fs::read_to_string(game_dir.join("models/gta3.img")).unwrap(),
+            "original"
+        );
+        assert!(!game_dir.join("cleo").exists());
+
+        for dir in [extracted, game_dir, backup_dir] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn refuses_files_owned_by_other_mods() {
+        let extracted = extracted_dir("conflict-mod", &["cleo/mod.cs", "cleo/other.cs"]);
+        let game_dir = extracted_dir("conflict-game", &[]);
+        let backup_dir = game_dir.with_extension("backups");
+
+        let owner = installed_mod(
+            &game_dir,
+            InstalledFiles {
+                files: vec![ModFile {
+                    path: "CLEO\\Mod.cs".to_string(),
+                    replaced: false,
+                }],
+                created_dirs: Vec::new(),
+            },
+        );
+        let data = ModsData { mods: vec![owner] };
+
+        let game_dir_str = game_dir.to_string_lossy().to_string();
+        match install_extracted(&data, &extracted, &game_dir_str, &backup_dir, |_| {}) {
+            Err(LauncherError::AlreadyExists(details)) => {
+                assert!(details.contains("Test"));
+                assert!(!details.contains("other.cs"));
+            }
+            _ => panic!("expected a conflict"),
+        }
+        // Nothing was copied
+        assert!(!game_dir.join("cleo").exists());
+
+        // The same files in another game directory don't conflict
+        let other_game_dir = extracted_dir("conflict-other-game", &[]);
+        install_extracted(
+            &data,
+            &extracted,
+            &other_game_dir.to_string_lossy(),
+            &backup_dir,
+            |_| {},
+        )
+        .unwrap();
+        assert!(other_game_dir.join("cleo/mod.cs").is_file());
+
+        for dir in [extracted, game_dir, other_game_dir] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+        let _ = fs::remove_dir_all(backup_dir);
+    }
+}
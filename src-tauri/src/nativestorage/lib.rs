@@ -1,16 +1,46 @@
 use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{path::PathBuf, sync::Mutex};
 use std::error::Error;
 use actix_web::{web, HttpResponse, Responder};
-use lazy_static::lazy_static;
+use log::{error, info};
 use serde::Deserialize;
 use serde_json::{json, Value};
-use tokio::sync::Semaphore;
 
 static STORAGE_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
 
-lazy_static! {
-    static ref SEMAPHORE: Semaphore = Semaphore::new(1);
+/// storage.json as it's kept in memory, loaded on first use
+static STORAGE: Mutex<Option<Storage>> = Mutex::new(None);
+/// Set while a flush thread is waiting for changes to settle
+static FLUSH_SCHEDULED: AtomicBool = AtomicBool::new(false);
+/// Keeps flushes from overtaking each other on disk
+static FLUSH_LOCK: Mutex<()> = Mutex::new(());
+
+/// How many backups of storage.json are kept (storage.json.bak.1 being the newest)
+const BACKUP_COUNT: usize = 3;
+/// Minimum age of the newest backup before a new one is taken
+const BACKUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// How long changes have to settle before they're written to disk
+const FLUSH_DEBOUNCE: Duration = Duration::from_millis(500);
+/// Longest changes wait for a flush while they keep coming in
+const FLUSH_MAX_DELAY: Duration = Duration::from_secs(5);
+
+struct Storage {
+    data: Value,
+    /// When the oldest change not yet on disk was made
+    dirty_since: Option<Instant>,
+    last_change: Instant,
+}
+
+impl Storage {
+    fn mark_changed(&mut self) {
+        let now = Instant::now();
+        self.last_change = now;
+        self.dirty_since.get_or_insert(now);
+    }
 }
 
 #[derive(Deserialize)]
@@ -35,69 +65,123 @@ struct StorageSetItemParams {
 }
 
 fn storage_get_item(key: String) -> Result<Option<String>, String> {
-    init_storage_file();
-    let storage_file = STORAGE_FILE.lock().unwrap().to_owned().unwrap();
-    ensure_storage_file(&storage_file)?;
-
-    let data = fs::read_to_string(&storage_file).map_err(|e| e.to_string())?;
-    let json_data: Value = serde_json::from_str(&data).map_err(|e| e.to_string())?;
-
-    Ok(json_data
-        .get(&key)
-        .and_then(|v| v.as_str().map(|s| s.to_string())))
+    with_storage(|storage| {
+        storage
+            .data
+            .get(&key)
+            .and_then(|v| v.as_str().map(|s| s.to_string()))
+    })
 }
 
 fn storage_set_item(key: String, value: String) -> Result<(), String> {
-    init_storage_file();
-    let storage_file = STORAGE_FILE.lock().unwrap().to_owned().unwrap();
-    ensure_storage_file(&storage_file)?;
+    with_storage(|storage| {
+        storage.data[key] = json!(value);
+        storage.mark_changed();
+    })?;
 
-    let data = fs::read_to_string(&storage_file).map_err(|e| e.to_string())?;
-    let mut json_data: Value = serde_json::from_str(&data).map_err(|e| e.to_string())?;
-
-    json_data[key] = json!(value);
-
-    fs::write(storage_file, json_data.to_string()).map_err(|e| e.to_string())?;
+    schedule_flush();
     Ok(())
 }
 
 fn storage_remove_item(key: String) -> Result<(), String> {
-    init_storage_file();
-    let storage_file = STORAGE_FILE.lock().unwrap().to_owned().unwrap();
-    ensure_storage_file(&storage_file)?;
-
-    let data = fs::read_to_string(&storage_file).map_err(|e| e.to_string())?;
-    let mut json_data: Value = serde_json::from_str(&data).map_err(|e| e.to_string())?;
-
-    json_data.as_object_mut().map(|map| map.remove(&key));
+    with_storage(|storage| {
+        if let Some(map) = storage.data.as_object_mut() {
+            if map.remove(&key).is_some() {
+                storage.mark_changed();
+            }
+        }
+    })?;
 
-    fs::write(storage_file, json_data.to_string()).map_err(|e| e.to_string())?;
+    schedule_flush();
     Ok(())
 }
 
 fn storage_get_all_items() -> Result<String, String> {
-    init_storage_file();
-    let storage_file = STORAGE_FILE.lock().unwrap().to_owned().unwrap();
-    ensure_storage_file(&storage_file)?;
-
-    let data = fs::read_to_string(&storage_file).unwrap_or_else(|_| "{}".to_string());
-    Ok(data)
+    with_storage(|storage| storage.data.to_string())
 }
 
 fn storage_clear() -> Result<(), String> {
-    init_storage_file();
-    let storage_file = STORAGE_FILE.lock().unwrap().to_owned().unwrap();
-    ensure_storage_file(&storage_file)?;
+    with_storage(|storage| {
+        storage.data = json!({});
+        storage.mark_changed();
+    })?;
 
-    fs::write(storage_file, "{}").map_err(|e| e.to_string())?;
+    schedule_flush();
     Ok(())
 }
 
+/// Runs `f` on the in-memory storage, loading it from disk first if needed
+fn with_storage<T>(f: impl FnOnce(&mut Storage) -> T) -> Result<T, String> {
+    let mut storage = STORAGE.lock().map_err(|e| e.to_string())?;
+    if storage.is_none() {
+        *storage = Some(Storage {
+            data: read_storage_file(&storage_file())?,
+            dirty_since: None,
+            last_change: Instant::now(),
+        });
+    }
+
+    Ok(f(storage.as_mut().unwrap()))
+}
+
+/// Starts a thread that flushes the storage once changes have settled, unless
+/// one is already waiting
+fn schedule_flush() {
+    if FLUSH_SCHEDULED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    std::thread::spawn(|| {
+        while let Some(wait) = time_until_flush() {
+            std::thread::sleep(wait);
+        }
+
+        FLUSH_SCHEDULED.store(false, Ordering::SeqCst);
+        if let Err(e) = flush_storage() {
+            error!("[nativestorage] Flushing storage failed: {}", e);
+        }
+    });
+}
+
+/// How much longer pending changes should wait for more, `None` once they're due
+fn time_until_flush() -> Option<Duration> {
+    let storage = STORAGE.lock().ok()?;
+    let storage = storage.as_ref()?;
+    let due = (storage.last_change + FLUSH_DEBOUNCE).min(storage.dirty_since? + FLUSH_MAX_DELAY);
+    let wait = due.saturating_duration_since(Instant::now());
+    (!wait.is_zero()).then_some(wait)
+}
+
+/// Writes pending storage changes to disk. Runs on its own shortly after
+/// changes, and has to be called before the launcher exits.
+pub fn flush_storage() -> Result<(), String> {
+    let _flush = FLUSH_LOCK.lock().map_err(|e| e.to_string())?;
+
+    let data = {
+        let mut storage = STORAGE.lock().map_err(|e| e.to_string())?;
+        match storage.as_mut() {
+            Some(storage) if storage.dirty_since.is_some() => {
+                storage.dirty_since = None;
+                storage.data.to_string()
+            }
+            _ => return Ok(()),
+        }
+    };
+
+    write_storage_file(&storage_file(), &data).inspect_err(|_| {
+        // Keep the changes pending, the next flush will try again
+        if let Ok(mut storage) = STORAGE.lock() {
+            if let Some(storage) = storage.as_mut() {
+                storage.dirty_since.get_or_insert_with(Instant::now);
+            }
+        }
+    })
+}
+
 pub async fn sync_storage_rpc_handler(
     path: web::Path<RpcMethod>,
     payload: web::Json<RpcParams>,
 ) -> Result<impl Responder, Box<dyn Error>> {
-    let _permit = SEMAPHORE.acquire().await.unwrap(); // Acquire a permit to ensure only one request is processed at a time
     let params_str = serde_json::to_string(&payload.params)?;
 
     /*
@@ -176,14 +260,139 @@ pub async fn sync_storage_rpc_handler(
     Ok(HttpResponse::Ok().body(response))
 }
 
-fn ensure_storage_file(storage_file: &PathBuf) -> Result<(), String> {
-    if !storage_file.exists() {
-        // Create an empty JSON file
-        fs::write(storage_file, "{}").map_err(|e| e.to_string())?;
+fn backup_path(storage_file: &Path, index: usize) -> PathBuf {
+    storage_file.with_extension(format!("json.bak.{}", index))
+}
+
+/// Reads and parses a storage file. `Ok(None)` means it's missing, or empty
+/// or otherwise not a JSON object, errors reading it are passed on.
+fn parse_storage_file(path: &Path) -> std::io::Result<Option<Value>> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    Ok(serde_json::from_slice::<Value>(&data)
+        .ok()
+        .filter(|value| value.is_object()))
+}
+
+/// Reads and parses storage.json. When it's missing or corrupt (e.g. a write
+/// was cut short by a crash), it's restored from the newest backup that parses,
+/// and the corrupt file is kept next to it as storage.json.corrupt.<timestamp>.
+/// Errors reading it are returned as they are, the file may be fine.
+fn read_storage_file(storage_file: &Path) -> Result<Value, String> {
+    match parse_storage_file(storage_file) {
+        Ok(Some(value)) => return Ok(value),
+        Ok(None) => {}
+        Err(e) => return Err(format!("{}: {}", storage_file.display(), e)),
+    }
+
+    if storage_file.exists() {
+        error!(
+            "[nativestorage] {} is corrupt, restoring it from a backup",
+            storage_file.display()
+        );
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let corrupt = storage_file.with_extension(format!("json.corrupt.{}", timestamp));
+        fs::rename(storage_file, &corrupt)
+            .map_err(|e| format!("{}: {}", storage_file.display(), e))?;
+    }
+
+    let restored = (1..=BACKUP_COUNT).find_map(|index| {
+        let backup = backup_path(storage_file, index);
+        match parse_storage_file(&backup) {
+            Ok(value) => value.map(|value| (backup, value)),
+            Err(e) => {
+                error!("[nativestorage] Reading {} failed: {}", backup.display(), e);
+                None
+            }
+        }
+    });
+
+    let value = match restored {
+        Some((backup, value)) => {
+            info!("[nativestorage] Restored storage from {}", backup.display());
+            value
+        }
+        // A first run, or nothing left to restore from
+        None => json!({}),
+    };
+
+    write_storage_file(storage_file, &value.to_string())?;
+    Ok(value)
+}
+
+/// Replaces storage.json with `data` without ever leaving a partly written
+/// file behind: the data goes to a temp file that's synced to disk and then
+/// renamed over the old one. A backup of the old file is taken first if the
+/// newest one is older than `BACKUP_INTERVAL`.
+fn write_storage_file(storage_file: &Path, data: &str) -> Result<(), String> {
+    if let Some(parent) = storage_file.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+
+    rotate_backups(storage_file);
+
+    let tmp_file = storage_file.with_extension("json.tmp");
+    let write_tmp = || -> std::io::Result<()> {
+        let mut file = fs::File::create(&tmp_file)?;
+        file.write_all(data.as_bytes())?;
+        file.sync_all()
+    };
+
+    if let Err(e) = write_tmp().and_then(|_| fs::rename(&tmp_file, storage_file)) {
+        let _ = fs::remove_file(&tmp_file);
+        return Err(e.to_string());
     }
+
+    // Make the rename itself durable, not possible on Windows and not needed there
+    #[cfg(unix)]
+    if let Some(parent) = storage_file.parent() {
+        let _ = fs::File::open(parent).and_then(|dir| dir.sync_all());
+    }
+
     Ok(())
 }
 
+/// Shifts storage.json.bak.N up by one and copies the current storage.json to
+/// storage.json.bak.1, as long as it's valid and the newest backup is due
+fn rotate_backups(storage_file: &Path) {
+    let newest = backup_path(storage_file, 1);
+    let due = fs::metadata(&newest)
+        .and_then(|meta| meta.modified())
+        .map(|modified| {
+            SystemTime::now()
+                .duration_since(modified)
+                .unwrap_or_default()
+                >= BACKUP_INTERVAL
+        })
+        .unwrap_or(true);
+
+    if !due || !matches!(parse_storage_file(storage_file), Ok(Some(_))) {
+        return;
+    }
+
+    for index in (1..BACKUP_COUNT).rev() {
+        let from = backup_path(storage_file, index);
+        if from.exists() {
+            let _ = fs::rename(&from, backup_path(storage_file, index + 1));
+        }
+    }
+
+    if let Err(e) = fs::copy(storage_file, &newest) {
+        error!("[nativestorage] Backing up storage failed: {}", e);
+    }
+}
+
+fn storage_file() -> PathBuf {
+    init_storage_file();
+    STORAGE_FILE.lock().unwrap().to_owned().unwrap()
+}
+
 fn init_storage_file() {
     let mut storage_file_guard = STORAGE_FILE.lock().unwrap();
     if storage_file_guard.is_none() {
@@ -202,3 +411,195 @@ fn resolve_option_for_http_response(
         None => Ok(HttpResponse::Ok().body("null")),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::MutexGuard;
+
+    /// Held by tests that use the global storage, they'd see each other's
+    /// changes otherwise
+    static GLOBAL_STORAGE: Mutex<()> = Mutex::new(());
+
+    /// Points the global storage at `storage_file` and unloads it, for as
+    /// long as the returned guard is held
+    fn use_global_storage(storage_file: &Path) -> MutexGuard<'static, ()> {
+        let guard = GLOBAL_STORAGE.lock().unwrap_or_else(|e| e.into_inner());
+        *STORAGE_FILE.lock().unwrap() = Some(storage_file.to_path_buf());
+        *STORAGE.lock().unwrap() = None;
+        guard
+    }
+
+    /// Changes the storage like `storage_set_item`, without a flush thread
+    /// that could write it out behind the test's back
+    fn set_item(key: &str, value: &str) {
+        with_storage(|storage| {
+            storage.data[key] = json!(value);
+            storage.mark_changed();
+        })
+        .unwrap();
+    }
+
+    fn has_pending_changes() -> bool {
+        with_storage(|storage| storage.dirty_since.is_some()).unwrap()
+    }
+
+    fn temp_storage_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "omp-nativestorage-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("storage.json")
+    }
+
+    fn corrupt_files(storage_file: &Path) -> Vec<PathBuf> {
+        fs::read_dir(storage_file.parent().unwrap())
+            .unwrap()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .starts_with("storage.json.corrupt.")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn missing_file_starts_empty() {
+        let storage_file = temp_storage_file("missing");
+        assert_eq!(read_storage_file(&storage_file).unwrap(), json!({}));
+        assert_eq!(fs::read_to_string(&storage_file).unwrap(), "{}");
+        assert!(corrupt_files(&storage_file).is_empty());
+
+        fs::remove_dir_all(storage_file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn valid_file_is_read() {
+        let storage_file = temp_storage_file("valid");
+        write_storage_file(&storage_file, r#"{"nickname":"John"}"#).unwrap();
+        assert_eq!(
+            read_storage_file(&storage_file).unwrap(),
+            json!({ "nickname": "John" })
+        );
+
+        fs::remove_dir_all(storage_file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn corrupt_file_is_restored_from_backup() {
+        let cases: [(&str, &[u8]); 3] = [
+            ("garbage", b"{\"nickname\":\"Jo"),
+            ("empty", b""),
+            ("not-utf8", b"\xff\xfe"),
+        ];
+        for (name, corrupt) in cases {
+            let storage_file = temp_storage_file(name);
+            fs::write(backup_path(&storage_file, 1), r#"{"nickname":"John"}"#).unwrap();
+            fs::write(&storage_file, corrupt).unwrap();
+
+            assert_eq!(
+                read_storage_file(&storage_file).unwrap(),
+                json!({ "nickname": "John" }),
+                "{}",
+                name
+            );
+            assert_eq!(
+                parse_storage_file(&storage_file).unwrap(),
+                Some(json!({ "nickname": "John" }))
+            );
+
+            let kept = corrupt_files(&storage_file);
+            assert_eq!(kept.len(), 1, "{}", name);
+            assert_eq!(fs::read(&kept[0]).unwrap(), corrupt);
+
+            fs::remove_dir_all(storage_file.parent().unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn unreadable_file_is_left_alone() {
+        // A directory in its place can't be read, which says nothing about
+        // whether the storage is corrupt
+        let storage_file = temp_storage_file("unreadable");
+        fs::create_dir(&storage_file).unwrap();
+        fs::write(backup_path(&storage_file, 1), r#"{"nickname":"John"}"#).unwrap();
+
+        assert!(read_storage_file(&storage_file).is_err());
+        assert!(storage_file.is_dir());
+        assert!(corrupt_files(&storage_file).is_empty());
+
+        fs::remove_dir_all(storage_file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn changes_are_written_on_flush() {
+        let storage_file = temp_storage_file("flush");
+        let _guard = use_global_storage(&storage_file);
+
+        set_item("nickname", "John");
+        assert!(has_pending_changes());
+        assert_eq!(parse_storage_file(&storage_file).unwrap(), Some(json!({})));
+
+        flush_storage().unwrap();
+        assert!(!has_pending_changes());
+        assert_eq!(
+            parse_storage_file(&storage_file).unwrap(),
+            Some(json!({ "nickname": "John" }))
+        );
+
+        fs::remove_dir_all(storage_file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn reads_are_served_from_memory() {
+        let storage_file = temp_storage_file("memory");
+        let _guard = use_global_storage(&storage_file);
+        write_storage_file(&storage_file, r#"{"nickname":"John"}"#).unwrap();
+        assert_eq!(
+            storage_get_item("nickname".to_string()).unwrap(),
+            Some("John".to_string())
+        );
+
+        // Gone from disk, still there in memory and not written back on a read
+        fs::remove_dir_all(storage_file.parent().unwrap()).unwrap();
+        assert_eq!(
+            storage_get_item("nickname".to_string()).unwrap(),
+            Some("John".to_string())
+        );
+        assert_eq!(
+            storage_get_all_items().unwrap(),
+            json!({ "nickname": "John" }).to_string()
+        );
+        assert!(!storage_file.parent().unwrap().exists());
+    }
+
+    #[test]
+    fn failed_flush_keeps_changes_pending() {
+        let storage_file = temp_storage_file("failed-flush");
+        let _guard = use_global_storage(&storage_file);
+        set_item("nickname", "John");
+
+        // A directory in place of the temp file makes the write fail
+        let tmp_file = storage_file.with_extension("json.tmp");
+        fs::create_dir(&tmp_file).unwrap();
+        assert!(flush_storage().is_err());
+        assert!(has_pending_changes());
+        assert_eq!(parse_storage_file(&storage_file).unwrap(), Some(json!({})));
+
+        fs::remove_dir(&tmp_file).unwrap();
+        flush_storage().unwrap();
+        assert!(!has_pending_changes());
+        assert_eq!(
+            parse_storage_file(&storage_file).unwrap(),
+            Some(json!({ "nickname": "John" }))
+        );
+
+        fs::remove_dir_all(storage_file.parent().unwrap()).unwrap();
+    }
+}
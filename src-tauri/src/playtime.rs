@@ -0,0 +1,404 @@
+use log::{error, info};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::process::Child;
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+
+use crate::chatlog;
+use crate::errors::LauncherError;
+use crate::helpers::{self, JsonStore};
+use crate::screenshots;
+
+const SECONDS_PER_DAY: u64 = 86400;
+
+/// Game sessions that are currently running, keyed by game process id
+static ACTIVE_SESSIONS: Lazy<Mutex<HashMap<u32, ActiveSession>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Threads waiting for game sessions to end, see `wait_for_sessions`
+static SESSION_THREADS: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+
+static PLAYTIME_STORE: JsonStore = JsonStore::new("playtime.json");
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ActiveSession {
+    pub pid: u32,
+    pub ip: String,
+    pub port: i32,
+    /// Directory of the game the session runs in
+    pub game_dir: String,
+    pub started_at: u64,
+    /// Chat log lines already archived for servers the game was connected to
+    /// earlier in this session
+    #[serde(default)]
+    pub chatlog_lines: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PlaySession {
+    pub started_at: u64,
+    pub ended_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct PlaytimeData {
+    /// Finished sessions, keyed by "ip:port"
+    servers: HashMap<String, Vec<PlaySession>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ServerPlaytime {
+    pub ip: String,
+    pub port: i32,
+    pub total_seconds: u64,
+    pub session_count: usize,
+    pub last_played: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DailyPlaytime {
+    /// UTC date in YYYY-MM-DD format
+    pub date: String,
+    pub seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ServerPlaytimeDetails {
+    pub summary: ServerPlaytime,
+    pub daily: Vec<DailyPlaytime>,
+}
+
+fn server_key(ip: &str, port: i32) -> String {
+    format!("{}:{}", ip, port)
+}
+
+fn load_playtime_data() -> Result<PlaytimeData, LauncherError> {
+    PLAYTIME_STORE.load()
+}
+
+fn save_playtime_data(data: &PlaytimeData) -> Result<(), LauncherError> {
+    PLAYTIME_STORE.save(data)
+}
+
+fn lock() -> Result<std::sync::MutexGuard<'static, ()>, LauncherError> {
+    PLAYTIME_STORE.lock()
+}
+
+fn record_session(ip: &str, port: i32, session: PlaySession) -> Result<(), LauncherError> {
+    let _guard = lock()?;
+    let mut data = load_playtime_data()?;
+    data.servers
+        .entry(server_key(ip, port))
+        .or_default()
+        .push(session);
+    save_playtime_data(&data)
+}
+
+/// Records the time spent on the session's current server and archives the
+/// chat log written meanwhile. Returns the number of chat log lines archived.
+fn finish_session(session: &ActiveSession) -> usize {
+    let ended_at = helpers::now();
+    if ended_at <= session.started_at {
+        return 0;
+    }
+
+    info!(
+        "[playtime.rs] Session on {}:{} ended after {} seconds",
+        session.ip,
+        session.port,
+        ended_at - session.started_at
+    );
+
+    if let Err(e) = record_session(
+        &session.ip,
+        session.port,
+        PlaySession {
+            started_at: session.started_at,
+            ended_at,
+        },
+    ) {
+        error!("[playtime.rs] Saving play session failed: {}", e);
+    }
+
+    chatlog::archive_session_chatlog(
+        &session.game_dir,
+        &session.ip,
+        session.port,
+        session.started_at,
+        session.chatlog_lines,
+    )
+}
+
+/// Starts tracking a freshly launched game process. Screenshots taken while it
+/// runs are linked to the given server. Once the process exits, the session is
+/// recorded against that server and its chat log archived.
+pub fn track_session(mut child: Child, ip: &str, port: i32, game_dir: &str) {
+    let session = ActiveSession {
+        pid: child.id(),
+        ip: ip.to_string(),
+        port,
+        game_dir: game_dir.to_string(),
+        started_at: helpers::now(),
+        chatlog_lines: 0,
+    };
+
+    if let Ok(mut sessions) = ACTIVE_SESSIONS.lock() {
+        sessions.insert(session.pid, session.clone());
+    }
+    screenshots::watch_session(session.clone());
+
+    let handle = thread::spawn(move || {
+        if let Err(e) = child.wait() {
+            error!(
+                "[playtime.rs] Waiting for game process {} failed: {}",
+                session.pid, e
+            );
+        }
+
+        // The game may have been moved to another server since it started
+        let session = match ACTIVE_SESSIONS.lock() {
+            Ok(mut sessions) => sessions.remove(&session.pid).unwrap_or(session),
+            Err(_) => session,
+        };
+        finish_session(&session);
+    });
+
+    if let Ok(mut threads) = SESSION_THREADS.lock() {
+        threads.retain(|thread| !thread.is_finished());
+        threads.push(handle);
+    }
+}
+
+/// Moves a running session to another server after the game was told to
+/// connect there. Time played and chat written so far are credited to the
+/// previous server, anything after that to the new one.
+pub fn switch_session_server(pid: u32, ip: &str, port: i32) {
+    let previous = match ACTIVE_SESSIONS.lock() {
+        Ok(sessions) => match sessions.get(&pid) {
+            Some(session) if session.ip != ip || session.port != port => session.clone(),
+            _ => return,
+        },
+        Err(_) => return,
+    };
+
+    // Link screenshots taken so far before the session points elsewhere
+    screenshots::index_session(&previous);
+    let archived_lines = finish_session(&previous);
+
+    if let Ok(mut sessions) = ACTIVE_SESSIONS.lock() {
+        if let Some(session) = sessions.get_mut(&pid) {
+            session.ip = ip.to_string();
+            session.port = port;
+            session.started_at = helpers::now();
+            session.chatlog_lines = previous.chatlog_lines + archived_lines;
+        }
+    }
+}
+
+/// Blocks until every tracked game session has ended and been recorded. For
+/// launches that exit the launcher right away (the command line), which would
+/// otherwise lose the session.
+pub fn wait_for_sessions() {
+    let threads = match SESSION_THREADS.lock() {
+        Ok(mut threads) => std::mem::take(&mut *threads),
+        Err(_) => return,
+    };
+
+    for thread in threads {
+        let _ = thread.join();
+    }
+}
+
+/// Returns the game sessions that are currently running
+pub fn get_active_sessions() -> Vec<ActiveSession> {
+    match ACTIVE_SESSIONS.lock() {
+        Ok(sessions) => sessions.values().cloned().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn summarize(key: &str, sessions: &[PlaySession]) -> ServerPlaytime {
+    let (ip, port) = match key.rsplit_once(':') {
+        Some((ip, port)) => (ip.to_string(), port.parse::<i32>().unwrap_or(0)),
+        None => (key.to_string(), 0),
+    };
+
+    ServerPlaytime {
+        ip,
+        port,
+        total_seconds: sessions
+            .iter()
+            .map(|s| s.ended_at.saturating_sub(s.started_at))
+            .sum(),
+        session_count: sessions.len(),
+        last_played: sessions.iter().map(|s| s.ended_at).max().unwrap_or(0),
+    }
+}
+
+/// Converts days since 1970-01-01 into a YYYY-MM-DD string
+fn format_date(days_since_epoch: u64) -> String {
+    // Howard Hinnant's civil_from_days algorithm
+    let z = days_since_epoch as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Splits sessions at UTC midnight and sums up the time played on each day
+fn daily_breakdown(sessions: &[PlaySession]) -> Vec<DailyPlaytime> {
+    let mut days: BTreeMap<u64, u64> = BTreeMap::new();
+
+    for session in sessions {
+        let mut start = session.started_at;
+        while start < session.ended_at {
+            let day = start / SECONDS_PER_DAY;
+            let end = session.ended_at.min((day + 1) * SECONDS_PER_DAY);
+            *days.entry(day).or_insert(0) += end - start;
+            start = end;
+        }
+    }
+
+    days.into_iter()
+        .map(|(day, seconds)| DailyPlaytime {
+            date: format_date(day),
+            seconds,
+        })
+        .collect()
+}
+
+/// Returns total playtime of every server played on, most played first
+#[tauri::command]
+pub fn get_playtime_summary() -> Result<Vec<ServerPlaytime>, LauncherError> {
+    let _guard = lock()?;
+    let data = load_playtime_data()?;
+
+    let mut summary: Vec<ServerPlaytime> = data
+        .servers
+        .iter()
+        .map(|(key, sessions)| summarize(key, sessions))
+        .collect();
+    summary.sort_by_key(|s| std::cmp::Reverse(s.total_seconds));
+    Ok(summary)
+}
+
+/// Returns playtime details of a single server, including a per-day breakdown
+#[tauri::command]
+pub fn get_server_playtime(ip: &str, port: i32) -> Result<ServerPlaytimeDetails, LauncherError> {
+    let _guard = lock()?;
+    let data = load_playtime_data()?;
+
+    let key = server_key(ip, port);
+    let sessions = data.servers.get(&key).cloned().unwrap_or_default();
+
+    Ok(ServerPlaytimeDetails {
+        summary: summarize(&key, &sessions),
+        daily: daily_breakdown(&sessions),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-02-28 00:00:00 UTC
+    const FEB_28_2024: u64 = 1709078400;
+
+    fn session(started_at: u64, ended_at: u64) -> PlaySession {
+        PlaySession {
+            started_at,
+            ended_at,
+        }
+    }
+
+    fn days(daily: &[DailyPlaytime]) -> Vec<(&str, u64)> {
+        daily.iter().map(|d| (d.date.as_str(), d.seconds)).collect()
+    }
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(FEB_28_2024 / SECONDS_PER_DAY), "2024-02-28");
+        assert_eq!(format_date(FEB_28_2024 / SECONDS_PER_DAY + 1), "2024-02-29");
+        assert_eq!(format_date(FEB_28_2024 / SECONDS_PER_DAY + 2), "2024-03-01");
+        // 2000 is a leap year, 2100 isn't
+        assert_eq!(format_date(11016), "2000-02-29");
+        assert_eq!(format_date(47540), "2100-02-28");
+        assert_eq!(format_date(47541), "2100-03-01");
+        assert_eq!(format_date(10956), "1999-12-31");
+        assert_eq!(format_date(10957), "2000-01-01");
+    }
+
+    #[test]
+    fn splits_sessions_at_utc_midnight() {
+        let midnight = FEB_28_2024 + SECONDS_PER_DAY;
+        let daily = daily_breakdown(&[session(midnight - 600, midnight + 1800)]);
+        assert_eq!(
+            days(&daily),
+            vec![("2024-02-28", 600), ("2024-02-29", 1800)]
+        );
+
+        // Ending exactly at midnight doesn't add an empty day
+        let daily = daily_breakdown(&[session(midnight - 600, midnight)]);
+        assert_eq!(days(&daily), vec![("2024-02-28", 600)]);
+    }
+
+    #[test]
+    fn splits_multi_day_sessions() {
+        // From 22:00 on February 28th to 02:00 on March 1st, across the leap day
+        let daily = daily_breakdown(&[session(
+            FEB_28_2024 + 22 * 3600,
+            FEB_28_2024 + 2 * SECONDS_PER_DAY + 2 * 3600,
+        )]);
+        assert_eq!(
+            days(&daily),
+            vec![
+                ("2024-02-28", 2 * 3600),
+                ("2024-02-29", SECONDS_PER_DAY),
+                ("2024-03-01", 2 * 3600),
+            ]
+        );
+    }
+
+    #[test]
+    fn sums_sessions_on_the_same_day() {
+        let daily = daily_breakdown(&[
+            session(FEB_28_2024 + 3600, FEB_28_2024 + 7200),
+            session(FEB_28_2024 + 10000, FEB_28_2024 + 10060),
+            // Sessions with their clock going backwards count as nothing
+            session(FEB_28_2024 + 500, FEB_28_2024 + 100),
+        ]);
+        assert_eq!(days(&daily), vec![("2024-02-28", 3660)]);
+    }
+
+    #[test]
+    fn summarizes_sessions() {
+        let summary = summarize(
+            "127.0.0.1:7777",
+            &[
+                session(FEB_28_2024, FEB_28_2024 + 3600),
+                session(
+                    FEB_28_2024 + 2 * SECONDS_PER_DAY,
+                    FEB_28_2024 + 2 * SECONDS_PER_DAY + 60,
+                ),
+                session(FEB_28_2024 + 500, FEB_28_2024 + 100),
+            ],
+        );
+        assert_eq!(summary.ip, "127.0.0.1");
+        assert_eq!(summary.port, 7777);
+        assert_eq!(summary.total_seconds, 3660);
+        assert_eq!(summary.session_count, 3);
+        assert_eq!(summary.last_played, FEB_28_2024 + 2 * SECONDS_PER_DAY + 60);
+
+        let empty = summarize("example.com", &[]);
+        assert_eq!((empty.ip.as_str(), empty.port), ("example.com", 0));
+        assert_eq!((empty.total_seconds, empty.last_played), (0, 0));
+    }
+}
@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use sysinfo::{Pid, Process, ProcessRefreshKind, System, UpdateKind};
+
+use crate::errors::LauncherError;
+
+pub const GAME_EXECUTABLE: &str = "gta_sa.exe";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GameProcess {
+    pub pid: u32,
+    pub name: String,
+    /// Directory the game was started from, if it could be determined
+    pub game_dir: Option<String>,
+    /// Whether the game is running through Wine (or Proton)
+    pub wine: bool,
+    /// CPU usage in percent, where 100% is one fully used core
+    pub cpu_usage: f32,
+    /// Resident memory in bytes
+    pub memory: u64,
+    /// Seconds since the process was started
+    pub run_time: u64,
+}
+
+fn refresh_kind() -> ProcessRefreshKind {
+    ProcessRefreshKind::new()
+        .with_cpu()
+        .with_memory()
+        .with_exe(UpdateKind::OnlyIfNotSet)
+        .with_cmd(UpdateKind::OnlyIfNotSet)
+        .with_cwd(UpdateKind::OnlyIfNotSet)
+}
+
+fn ends_with_game_executable(path: &str) -> bool {
+    path.rsplit(['/', '\\'])
+        .next()
+        .map(|file_name| file_name.eq_ignore_ascii_case(GAME_EXECUTABLE))
+        .unwrap_or(false)
+}
+
+/// Checks whether the given process is a GTA: San Andreas instance, either
+/// running natively or as a Wine process (where the host executable is the
+/// Wine loader and the game path only shows up in the command line)
+fn is_game_process(process: &Process) -> bool {
+    process.name().eq_ignore_ascii_case(GAME_EXECUTABLE)
+        || process
+            .cmd()
+            .first()
+            .map(|arg| ends_with_game_executable(arg))
+            .unwrap_or(false)
+}
+
+fn is_wine_process(process: &Process) -> bool {
+    if cfg!(target_os = "windows") {
+        return false;
+    }
+
+    match process.exe() {
+        Some(exe) => !ends_with_game_executable(&exe.to_string_lossy()),
+        None => true,
+    }
+}
+
+fn get_game_dir(process: &Process) -> Option<String> {
+    match process.exe() {
+        Some(exe) if ends_with_game_executable(&exe.to_string_lossy()) => {
+            exe.parent().map(|dir| dir.to_string_lossy().to_string())
+        }
+        // Wine processes report the loader as their executable, but the launcher
+        // always starts the game with its own directory as working directory
+        _ => process.cwd().map(|dir| dir.to_string_lossy().to_string()),
+    }
+}
+
+fn to_game_process(process: &Process) -> GameProcess {
+    GameProcess {
+        pid: process.pid().as_u32(),
+        name: process.name().to_string(),
+        game_dir: get_game_dir(process),
+        wine: is_wine_process(process),
+        cpu_usage: process.cpu_usage(),
+        memory: process.memory(),
+        run_time: process.run_time(),
+    }
+}
+
+pub fn is_process_alive(pid: u32) -> bool {
+    let mut system = System::new();
+    system.refresh_process_specifics(Pid::from_u32(pid), ProcessRefreshKind::new())
+}
+
+/// Finds all running game instances. CPU usage is measured between two
+/// refreshes, so this blocks for `sysinfo::MINIMUM_CPU_UPDATE_INTERVAL`.
+pub fn find_game_processes() -> Vec<GameProcess> {
+    let mut system = System::new();
+    system.refresh_processes_specifics(refresh_kind());
+
+    let pids: Vec<Pid> = system
+        .processes()
+        .values()
+        .filter(|process| is_game_process(process))
+        .map(|process| process.pid())
+        .collect();
+
+    if pids.is_empty() {
+        return Vec::new();
+    }
+
+    std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+    system.refresh_pids_specifics(&pids, refresh_kind());
+
+    pids.iter()
+        .filter_map(|pid| system.process(*pid))
+        .map(to_game_process)
+        .collect()
+}
+
+/// Finds game instances that were started from the given game directory.
+/// Unlike [`find_game_processes`] this doesn't wait for CPU usage samples.
+pub fn find_game_processes_in_dir(game_dir: &str) -> Vec<GameProcess> {
+    let mut system = System::new();
+    system.refresh_processes_specifics(refresh_kind());
+
+    system
+        .processes()
+        .values()
+        .filter(|process| is_game_process(process))
+        .map(to_game_process)
+        .filter(|process| match &process.game_dir {
+            Some(dir) => is_same_game_dir(dir, game_dir),
+            None => false,
+        })
+        .collect()
+}
+
+/// Kills a game process. Refuses to touch anything that isn't a game instance.
+pub fn terminate_game_process(pid: u32) -> Result<(), LauncherError> {
+    let mut system = System::new();
+    let pid = Pid::from_u32(pid);
+    system.refresh_process_specifics(pid, refresh_kind());
+
+    match system.process(pid) {
+        Some(process) => {
+            if !is_game_process(process) {
+                return Err(LauncherError::NotAGameProcess(format!(
+                    "{} (pid: {})",
+                    process.name(),
+                    pid
+                )));
+            }
+
+            if process.kill() {
+                Ok(())
+            } else {
+                Err(LauncherError::Other(format!(
+                    "Killing game process {} failed",
+                    pid
+                )))
+            }
+        }
+        None => Err(LauncherError::NotFound(format!(
+            "No process with pid {}",
+            pid
+        ))),
+    }
+}
+
+/// Returns whether two game directories point to the same place, ignoring
+/// trailing separators and, on Windows, letter case
+pub fn is_same_game_dir(a: &str, b: &str) -> bool {
+    let normalize = |path: &str| {
+        let path = Path::new(path.trim_end_matches(['/', '\\']));
+        let path = path
+            .canonicalize()
+            .unwrap_or_else(|_| path.to_path_buf())
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        if cfg!(target_os = "windows") {
+            path.to_lowercase()
+        } else {
+            path
+        }
+    };
+
+    normalize(a) == normalize(b)
+}
@@ -12,16 +12,22 @@ use tokio::net::{lookup_host, UdpSocket};
 use tokio::time::timeout_at;
 use tokio::time::Instant;
 
+use crate::encoding::{self, EncodingHint, EncodingSource};
 use crate::helpers;
+use crate::text::{self, ColoredText};
 
 static OMP_EXTRA_INFO_LAST_UPDATE_LIST: Lazy<Mutex<HashMap<String, u64>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 const OMP_EXTRA_INFO_UPDATE_COOLDOWN_SECS: u64 = 3;
 
 pub struct Query {
+    /// Address as given, resolved one in `address`
+    ip: String,
     address: Ipv4Addr,
     port: i32,
     socket: UdpSocket,
+    /// Encoding label the user set for the server, if any
+    encoding_override: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Default)]
@@ -32,14 +38,27 @@ pub struct InfoPacket {
     pub hostname: String,
     pub gamemode: String,
     pub language: String,
+    /// The above split at their color tags, with plain text forms
+    pub hostname_text: ColoredText,
+    pub gamemode_text: ColoredText,
+    pub language_text: ColoredText,
+    /// Encoding the strings were decoded with, e.g. `windows-1251`
+    pub encoding: String,
+    pub encoding_source: EncodingSource,
 }
 
 #[derive(Serialize, Deserialize, Clone, Default)]
 pub struct Player {
     pub name: String,
+    /// The name split at its color tags, with a plain text form
+    pub name_text: ColoredText,
     pub score: i32,
 }
 
+/// A rule as its name, value and the value split at its color tags. Sent as
+/// an array so the name and value stay at the indices they always had.
+pub type Rule = (String, String, ColoredText);
+
 #[derive(Serialize, Deserialize, Clone, Default)]
 pub struct ExtraInfoPacket {
     pub discord_link: String,
@@ -96,9 +115,11 @@ impl Query {
         };
 
         let data = Self {
+            ip: addr.to_string(),
             address: address.parse::<Ipv4Addr>().map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Invalid IP address: {}", e)))?,
             port,
             socket: UdpSocket::bind("0.0.0.0:0").await.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to bind UDP socket: {}", e)))?,
+            encoding_override: None,
         };
 
         data.socket
@@ -173,17 +194,42 @@ impl Query {
         let hostname_len = packet.read_u32::<LittleEndian>().unwrap();
         let mut hostname_buf = vec![0u8; hostname_len as usize];
         packet.read_exact(&mut hostname_buf).unwrap();
-        data.hostname = helpers::decode_buffer(hostname_buf).0;
 
         let gamemode_len = packet.read_u32::<LittleEndian>().unwrap();
         let mut gamemode_buf = vec![0u8; gamemode_len as usize];
         packet.read_exact(&mut gamemode_buf).unwrap();
-        data.gamemode = helpers::decode_buffer(gamemode_buf).0;
 
         let language_len = packet.read_u32::<LittleEndian>().unwrap();
         let mut language_buf = vec![0u8; language_len as usize];
         packet.read_exact(&mut language_buf).unwrap();
-        data.language = helpers::decode_buffer(language_buf).0;
+
+        // The language is decoded first since it's a hint for the others
+        let (language, language_encoding, language_source) =
+            self.decode("language", language_buf.clone(), self.encoding_hint(None));
+        let hint = self.encoding_hint(Some(&text::parse(&language).plain));
+        let (hostname, hostname_encoding, hostname_source) =
+            self.decode("hostname", hostname_buf.clone(), hint);
+        let (gamemode, gamemode_encoding, gamemode_source) =
+            self.decode("gamemode", gamemode_buf.clone(), hint);
+
+        // Reported is the encoding of the first string that needed one
+        (data.encoding, data.encoding_source) = [
+            (&hostname_buf, hostname_encoding, hostname_source),
+            (&gamemode_buf, gamemode_encoding, gamemode_source),
+            (&language_buf, language_encoding, language_source),
+        ]
+        .into_iter()
+        .find(|(buf, ..)| !buf.is_ascii())
+        .map(|(_, name, source)| (name, source))
+        .unwrap_or_else(|| ("UTF-8".to_string(), EncodingSource::Detected));
+
+        data.hostname = hostname;
+        data.gamemode = gamemode;
+        data.language = language;
+
+        data.hostname_text = text::parse(&data.hostname);
+        data.gamemode_text = text::parse(&data.gamemode);
+        data.language_text = text::parse(&data.language);
 
         Ok(serde_json::to_string(&data).unwrap())
     }
@@ -197,23 +243,23 @@ impl Query {
         let discord_link_len = packet.read_u32::<LittleEndian>().unwrap();
         let mut discord_link_buf = vec![0u8; discord_link_len as usize];
         packet.read_exact(&mut discord_link_buf).unwrap();
-        data.discord_link = helpers::decode_buffer(discord_link_buf).0;
+        data.discord_link = helpers::decode_buffer(discord_link_buf, None).0;
 
         let mut banner_url_len = packet.read_u32::<LittleEndian>().unwrap();
         let mut banner_url_buf = vec![0u8; banner_url_len as usize];
         packet.read_exact(&mut banner_url_buf).unwrap();
-        data.light_banner_url = helpers::decode_buffer(banner_url_buf).0;
+        data.light_banner_url = helpers::decode_buffer(banner_url_buf, None).0;
 
         banner_url_len = packet.read_u32::<LittleEndian>().unwrap();
         banner_url_buf = vec![0u8; banner_url_len as usize];
         packet.read_exact(&mut banner_url_buf).unwrap();
-        data.dark_banner_url = helpers::decode_buffer(banner_url_buf).0;
+        data.dark_banner_url = helpers::decode_buffer(banner_url_buf, None).0;
 
         if packet.remaining() > 0 {
             let logo_url_len = packet.read_u32::<LittleEndian>().unwrap();
             let mut logo_url_buf = vec![0u8; logo_url_len as usize];
             packet.read_exact(&mut logo_url_buf).unwrap();
-            data.logo_url = helpers::decode_buffer(logo_url_buf).0;
+            data.logo_url = helpers::decode_buffer(logo_url_buf, None).0;
         }
 
         Ok(serde_json::to_string(&data).unwrap())
@@ -223,6 +269,7 @@ impl Query {
         let player_count = packet.read_u16::<LittleEndian>().unwrap();
         let default_player = Player::default();
         let mut players = vec![default_player; player_count as usize];
+        let hint = self.encoding_hint(None);
 
         for i in 0..player_count {
             let player = &mut players[i as usize];
@@ -230,7 +277,8 @@ impl Query {
             let player_name_len = packet.read_u8().unwrap();
             let mut player_name_buf = vec![0u8; player_name_len as usize];
             packet.read_exact(&mut player_name_buf).unwrap();
-            player.name = helpers::decode_buffer(player_name_buf).0;
+            player.name = self.decode("player_name", player_name_buf, hint).0;
+            player.name_text = text::parse(&player.name);
 
             player.score = packet.read_i32::<LittleEndian>().unwrap();
         }
@@ -240,26 +288,61 @@ impl Query {
 
     fn build_rules_packet(&self, mut packet: Cursor<Vec<u8>>) -> Result<String, std::io::Error> {
         let rule_count = packet.read_u16::<LittleEndian>().unwrap();
-        let mut rules: Vec<Vec<String>> = Vec::new();
+        let mut raw_rules: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
 
         for _ in 0..rule_count {
-            let mut rule: Vec<String> = Vec::new();
-
             let rule_name_len = packet.read_u8().unwrap();
             let mut rule_name_buf = vec![0u8; rule_name_len as usize];
             packet.read_exact(&mut rule_name_buf).unwrap();
-            rule.push(helpers::decode_buffer(rule_name_buf).0);
 
             let rule_value_len = packet.read_u8().unwrap();
             let mut rule_value_buf = vec![0u8; rule_value_len as usize];
             packet.read_exact(&mut rule_value_buf).unwrap();
-            rule.push(helpers::decode_buffer(rule_value_buf).0);
 
-            rules.push(rule);
+            raw_rules.push((rule_name_buf, rule_value_buf));
         }
 
+        // Rules are the longest text a server sends, what they're detected as
+        // is used for the server's other strings from now on
+        let values: Vec<Vec<u8>> = raw_rules.iter().map(|(_, value)| value.clone()).collect();
+        encoding::learn_from_rules(&self.ip, self.port, &values);
+        let hint = self.encoding_hint(None);
+
+        let rules: Vec<Rule> = raw_rules
+            .into_iter()
+            .map(|(name, value)| {
+                let value = self.decode("rule_value", value, hint).0;
+                let value_text = text::parse(&value);
+                (self.decode("rule_name", name, hint).0, value, value_text)
+            })
+            .collect();
+
         Ok(serde_json::to_string(&rules).unwrap())
     }
+
+    fn decode(
+        &self,
+        kind: &str,
+        buf: Vec<u8>,
+        hint: Option<EncodingHint>,
+    ) -> (String, String, EncodingSource) {
+        if !encoding::capturing_samples() {
+            return encoding::decode_for_server(&self.ip, self.port, buf, hint);
+        }
+
+        let decoded = encoding::decode_for_server(&self.ip, self.port, buf.clone(), hint);
+        encoding::capture_sample(&self.ip, self.port, kind, &buf, &decoded.0, &decoded.1);
+        decoded
+    }
+
+    fn encoding_hint(&self, language: Option<&str>) -> Option<EncodingHint> {
+        encoding::resolve_hint(
+            &self.ip,
+            self.port,
+            self.encoding_override.as_deref(),
+            language,
+        )
+    }
 }
 
 #[tauri::command]
@@ -271,9 +354,12 @@ pub async fn query_server(
     players: bool,
     rules: bool,
     ping: bool,
+    encoding: Option<String>,
 ) -> Result<String, String> {
     match Query::new(ip, port).await {
-        Ok(q) => {
+        Ok(mut q) => {
+            q.encoding_override = encoding;
+
             let mut result = ServerQueryResponse {
                 info: None,
                 extra_info: None,
@@ -8,6 +8,8 @@ use std::error::Error;
 use std::fs::File;
 use std::io::Read;
 
+use crate::copy_engine::OverwritePolicy;
+use crate::errors::LauncherError;
 use crate::helpers;
 use crate::nativestorage;
 
@@ -36,6 +38,8 @@ struct Extract7zParams {
 struct CopyFilesToGtaSaParams {
     src: String,
     gtasa_dir: String,
+    #[serde(default)]
+    overwrite_policy: OverwritePolicy,
 }
 
 fn get_checksum_of_files(list: Vec<String>) -> Vec<String> {
@@ -75,15 +79,20 @@ fn get_checksum_of_files(list: Vec<String>) -> Vec<String> {
     result
 }
 
-fn extract_7z(path: &str, output_path: &str) -> Result<String, String> {
-    match decompress_file(path, output_path) {
-        Ok(_) => Ok("success".to_string()),
-        Err(e) => Err(e.to_string()),
-    }
+fn extract_7z(path: &str, output_path: &str) -> Result<(), LauncherError> {
+    decompress_file(path, output_path).map_err(|e| match e {
+        sevenz_rust::Error::FileOpen(e, file) => LauncherError::from_io(e, file),
+        sevenz_rust::Error::Io(e, _) => LauncherError::from_io(e, path),
+        e => LauncherError::CorruptFile(format!("{}: {}", path, e)),
+    })
 }
 
-fn copy_files_to_gtasa(src: &str, gtasa_dir: &str) -> Result<(), String> {
-    helpers::copy_files(src, gtasa_dir)
+fn copy_files_to_gtasa(
+    src: &str,
+    gtasa_dir: &str,
+    policy: OverwritePolicy,
+) -> Result<(), LauncherError> {
+    helpers::copy_files(src, gtasa_dir, policy)
 }
 
 async fn rpc_handler(
@@ -104,7 +113,9 @@ async fn rpc_handler(
     */
     else if path.method == "extract_7z" {
         let params: Extract7zParams = serde_json::from_str(params_str.as_str())?;
-        extract_7z(params.path.as_str(), &params.output_path)?;
+        if let Err(e) = extract_7z(params.path.as_str(), &params.output_path) {
+            return Ok(HttpResponse::Ok().body(serde_json::to_string(&json!({ "error": e }))?));
+        }
         return Ok(HttpResponse::Ok().body("{}"));
     }
     /*
@@ -112,9 +123,13 @@ async fn rpc_handler(
     */
     else if path.method == "copy_files_to_gtasa" {
         let params: CopyFilesToGtaSaParams = serde_json::from_str(params_str.as_str())?;
-        let result = copy_files_to_gtasa(params.src.as_str(), params.gtasa_dir.as_str());
-        if result.is_err() {
-            return Ok(HttpResponse::Ok().body(result.err().unwrap()));
+        let result = copy_files_to_gtasa(
+            params.src.as_str(),
+            params.gtasa_dir.as_str(),
+            params.overwrite_policy,
+        );
+        if let Err(e) = result {
+            return Ok(HttpResponse::Ok().body(serde_json::to_string(&json!({ "error": e }))?));
         }
         return Ok(HttpResponse::Ok().body("{}"));
     }
@@ -1,22 +1,37 @@
-#[cfg(target_os = "windows")]
+#[cfg(not(target_os = "windows"))]
+use crate::discovery;
+use crate::errors::LauncherError;
 use crate::helpers;
-#[cfg(target_os = "windows")]
-use byteorder::{LittleEndian, ReadBytesExt};
+use crate::samp_config;
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use encoding_rs::{
+    Encoding, GB18030, WINDOWS_1250, WINDOWS_1251, WINDOWS_1252, WINDOWS_1253, WINDOWS_1254,
+};
+use log::info;
 use serde::{Deserialize, Serialize};
-#[cfg(target_os = "windows")]
-use std::fs::File;
-#[cfg(target_os = "windows")]
-use std::io::Cursor;
-#[cfg(target_os = "windows")]
-use std::io::Read;
-#[cfg(target_os = "windows")]
-use std::path::Path;
-#[cfg(target_os = "windows")]
-use tauri::api::path::document_dir;
-#[cfg(target_os = "windows")]
-use winreg::enums::*;
-#[cfg(target_os = "windows")]
-use winreg::RegKey;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The game's user files directory, relative to the documents directory.
+/// SA-MP keeps its own files in the `SAMP` directory inside.
+const USER_FILES_DIR: &str = "GTA San Andreas User Files";
+const USERDATA_FILE_NAME: &str = "USERDATA.DAT";
+
+/// Magic `USERDATA.DAT` starts with
+const USERDATA_FILE_ID: &[u8; 4] = b"SAMP";
+/// File format version the SA-MP clients write
+const USERDATA_FILE_VERSION: u32 = 1;
+/// Size of a server entry with all strings empty: four lengths and the port
+const MIN_SERVER_ENTRY_SIZE: usize = 5 * 4;
+
+/// Code pages tried, in order, when exporting without an explicit encoding
+const USERDATA_ENCODINGS: [&Encoding; 5] = [
+    WINDOWS_1252,
+    WINDOWS_1251,
+    WINDOWS_1250,
+    WINDOWS_1253,
+    WINDOWS_1254,
+];
 
 #[derive(Serialize, Deserialize)]
 pub struct SAMPServerInfo {
@@ -35,134 +50,441 @@ pub struct SAMPUserData {
     pub favorite_servers: Vec<SAMPServerInfo>,
 }
 
-#[cfg(not(target_os = "windows"))]
+/// Reads the game path SA-MP is set up with. Outside of Windows that's only
+/// there if SA-MP was used in a Wine prefix, the frontend falls back to
+/// `discover_gta_installations` otherwise.
 pub fn get_gtasa_path() -> String {
-    "".to_string()
+    samp_config::open_store()
+        .and_then(|store| store.game_dir())
+        .unwrap_or_default()
 }
 
-#[cfg(not(target_os = "windows"))]
 pub fn get_nickname() -> String {
-    "".to_string()
+    samp_config::open_store()
+        .and_then(|store| store.player_name())
+        .unwrap_or_default()
 }
 
-#[cfg(not(target_os = "windows"))]
-pub fn get_samp_favorite_list() -> String {
-    "[]".to_string()
+/// Reads a `USERDATA.DAT` one field at a time, failing instead of panicking
+/// when the file ends early
+struct UserDataReader<'a> {
+    buffer: &'a [u8],
+    pos: usize,
 }
 
-#[cfg(target_os = "windows")]
-pub fn get_gtasa_path() -> String {
-    let key = RegKey::predef(HKEY_CURRENT_USER).open_subkey_with_flags(r"Software\SAMP", KEY_READ);
-    match key {
-        Ok(s) => {
-            let result: Result<String, _> = s.get_value("gta_sa_exe");
-            match result {
-                Ok(val) => val.replace("\\gta_sa.exe", ""),
-                Err(_) => "".to_string(),
-            }
+impl<'a> UserDataReader<'a> {
+    fn take(&mut self, len: usize, field: &str) -> Result<&'a [u8], LauncherError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.buffer.len())
+            .ok_or_else(|| {
+                LauncherError::CorruptFile(format!(
+                    "{} at offset {} runs past the end of the file ({} bytes)",
+                    field,
+                    self.pos,
+                    self.buffer.len()
+                ))
+            })?;
+
+        let bytes = &self.buffer[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self, field: &str) -> Result<u32, LauncherError> {
+        Ok(LittleEndian::read_u32(self.take(4, field)?))
+    }
+
+    fn read_string(&mut self, field: &str) -> Result<String, LauncherError> {
+        let len = self.read_u32(field)? as usize;
+        let bytes = self.take(len, field)?;
+        if bytes.is_empty() {
+            return Ok(String::new());
         }
-        Err(_) => "".to_string(),
+        Ok(helpers::decode_buffer(bytes.to_vec(), None).0)
     }
 }
 
-#[cfg(target_os = "windows")]
-pub fn get_nickname() -> String {
-    let key = RegKey::predef(HKEY_CURRENT_USER).open_subkey_with_flags(r"Software\SAMP", KEY_READ);
-    match key {
-        Ok(s) => {
-            let result = s.get_value("PlayerName");
-            match result {
-                Ok(val) => val,
-                Err(_) => "".to_string(),
-            }
+/// Parses the contents of a SA-MP `USERDATA.DAT`. Error details only ever
+/// mention fields and offsets, never their values, since the file holds
+/// server and RCON passwords.
+pub fn parse_userdata(buffer: &[u8]) -> Result<SAMPUserData, LauncherError> {
+    let mut reader = UserDataReader { buffer, pos: 0 };
+
+    if reader.take(4, "file id")? != USERDATA_FILE_ID {
+        return Err(LauncherError::CorruptFile(
+            "Not a SA-MP USERDATA.DAT, the file id doesn't match".to_string(),
+        ));
+    }
+
+    let file_version = reader.read_u32("file version")?;
+    let server_count = reader.read_u32("server count")?;
+
+    // Checked up front so a garbage count can't make us allocate gigabytes
+    let remaining = buffer.len() - reader.pos;
+    if (server_count as usize).saturating_mul(MIN_SERVER_ENTRY_SIZE) > remaining {
+        return Err(LauncherError::CorruptFile(format!(
+            "{} servers can't fit in the remaining {} bytes",
+            server_count, remaining
+        )));
+    }
+
+    let mut favorite_servers = Vec::with_capacity(server_count as usize);
+    for _ in 0..server_count {
+        favorite_servers.push(SAMPServerInfo {
+            ip: reader.read_string("server address")?,
+            port: reader.read_u32("server port")?,
+            name: reader.read_string("server name")?,
+            password: reader.read_string("server password")?,
+            rcon: reader.read_string("rcon password")?,
+        });
+    }
+
+    Ok(SAMPUserData {
+        file_id: String::from_utf8_lossy(USERDATA_FILE_ID).to_string(),
+        file_version,
+        server_count,
+        favorite_servers,
+    })
+}
+
+/// Documents directories of the users in a Wine prefix
+fn prefix_documents_dirs(prefix: &Path) -> Vec<PathBuf> {
+    let mut users: Vec<PathBuf> = match fs::read_dir(prefix.join("drive_c/users")) {
+        Ok(entries) => entries.flatten().map(|entry| entry.path()).collect(),
+        Err(_) => return Vec::new(),
+    };
+    // The actual user comes before the shared one
+    users.sort_by_key(|user| user.ends_with("Public"));
+
+    users
+        .into_iter()
+        // Older Wine releases call it "My Documents"
+        .flat_map(|user| [user.join("Documents"), user.join("My Documents")])
+        .collect()
+}
+
+/// Documents directories SA-MP may have been run from: the user's own and,
+/// outside of Windows, those of the users in every Wine/Proton prefix
+fn documents_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = dirs_next::document_dir().into_iter().collect();
+
+    #[cfg(not(target_os = "windows"))]
+    for prefix in discovery::find_wine_prefixes() {
+        dirs.extend(prefix_documents_dirs(&prefix));
+    }
+
+    dirs
+}
+
+/// The Wine prefix a game directory is in, if any: the closest parent with
+/// both a `drive_c` and a `user.reg`
+fn wine_prefix_of(game_dir: &Path) -> Option<PathBuf> {
+    game_dir
+        .ancestors()
+        .find(|dir| dir.join("drive_c").is_dir() && dir.join("user.reg").is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Whether a game installation has user files of its own, which is only the
+/// case inside a Wine prefix. All others share the user's documents.
+pub fn has_own_user_files(game_dir: &str) -> bool {
+    wine_prefix_of(Path::new(game_dir)).is_some()
+}
+
+/// SA-MP's own directory in the game's user files (where `USERDATA.DAT`,
+/// `sa-mp.cfg`, chat logs and screenshots go) for a game installation. A game
+/// inside a Wine prefix uses the documents of that prefix, any other the
+/// user's own documents. The directory may not exist yet.
+pub fn samp_user_files_dir(game_dir: Option<&str>) -> Option<PathBuf> {
+    let documents = match game_dir.map(Path::new).and_then(wine_prefix_of) {
+        Some(prefix) => {
+            let candidates = prefix_documents_dirs(&prefix);
+            candidates
+                .iter()
+                .find(|dir| dir.join(USER_FILES_DIR).is_dir())
+                .or_else(|| candidates.iter().find(|dir| dir.is_dir()))
+                .cloned()?
+        }
+        None => dirs_next::document_dir()?,
+    };
+
+    Some(documents.join(USER_FILES_DIR).join("SAMP"))
+}
+
+/// Looks for SA-MP's `USERDATA.DAT`, see `documents_dirs` for where
+pub fn find_userdata_file() -> Option<PathBuf> {
+    documents_dirs()
+        .into_iter()
+        .map(|dir| {
+            dir.join(USER_FILES_DIR)
+                .join("SAMP")
+                .join(USERDATA_FILE_NAME)
+        })
+        .find(|path| path.is_file())
+}
+
+/// Reads the favorites list from `path`, or from the `USERDATA.DAT` found by
+/// `find_userdata_file` if there's no path given
+pub fn get_samp_favorite_list(path: Option<&Path>) -> Result<SAMPUserData, LauncherError> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => find_userdata_file().ok_or_else(|| {
+            LauncherError::NotFound(format!("No SA-MP {} found", USERDATA_FILE_NAME))
+        })?,
+    };
+
+    let buffer = fs::read(&path).map_err(|e| LauncherError::from_io(e, &path))?;
+    let data = parse_userdata(&buffer).map_err(|e| match e {
+        LauncherError::CorruptFile(details) => {
+            LauncherError::CorruptFile(format!("{}: {}", path.display(), details))
+        }
+        e => e,
+    })?;
+
+    info!(
+        "[samp.rs] Read {} favorite server(s) from {}",
+        data.server_count,
+        path.display()
+    );
+    Ok(data)
+}
+
+/// Picks the first legacy code page every string of the list can be written
+/// in. SA-MP reads the file with the system's ANSI code page, so a single one
+/// is used for the whole file.
+fn pick_userdata_encoding(servers: &[SAMPServerInfo]) -> &'static Encoding {
+    let strings: Vec<&str> = servers
+        .iter()
+        .flat_map(|server| {
+            [
+                server.ip.as_str(),
+                server.name.as_str(),
+                server.password.as_str(),
+                server.rcon.as_str(),
+            ]
+        })
+        .collect();
+
+    USERDATA_ENCODINGS
+        .iter()
+        .copied()
+        .find(|encoding| strings.iter().all(|s| !encoding.encode(s).2))
+        .unwrap_or(GB18030)
+}
+
+/// Serializes a favorites list into the format of SA-MP's `USERDATA.DAT`.
+/// Strings are written in `encoding`, or the first fitting legacy code page
+/// if there's none given.
+pub fn write_userdata(servers: &[SAMPServerInfo], encoding: Option<&'static Encoding>) -> Vec<u8> {
+    let encoding = encoding.unwrap_or_else(|| pick_userdata_encoding(servers));
+    let mut buffer = Vec::new();
+
+    // Strings are prefixed with their length in bytes, not characters
+    let write_string = |buffer: &mut Vec<u8>, value: &str| {
+        let (bytes, _, _) = encoding.encode(value);
+        buffer
+            .write_u32::<LittleEndian>(bytes.len() as u32)
+            .unwrap();
+        buffer.extend_from_slice(&bytes);
+    };
+
+    buffer.extend_from_slice(USERDATA_FILE_ID);
+    buffer
+        .write_u32::<LittleEndian>(USERDATA_FILE_VERSION)
+        .unwrap();
+    buffer
+        .write_u32::<LittleEndian>(servers.len() as u32)
+        .unwrap();
+
+    for server in servers {
+        write_string(&mut buffer, &server.ip);
+        buffer.write_u32::<LittleEndian>(server.port).unwrap();
+        write_string(&mut buffer, &server.name);
+        write_string(&mut buffer, &server.password);
+        write_string(&mut buffer, &server.rcon);
+    }
+
+    buffer
+}
+
+/// The launcher doesn't keep rcon passwords, SA-MP does. Servers without one
+/// get the password stored for the same address in `existing`.
+fn carry_over_rcon(servers: &mut [SAMPServerInfo], existing: &[SAMPServerInfo]) {
+    for server in servers.iter_mut().filter(|server| server.rcon.is_empty()) {
+        if let Some(stored) = existing
+            .iter()
+            .find(|stored| stored.ip.eq_ignore_ascii_case(&server.ip) && stored.port == server.port)
+        {
+            server.rcon.clone_from(&stored.rcon);
         }
-        Err(_) => "".to_string(),
     }
 }
 
-#[cfg(target_os = "windows")]
-pub fn get_samp_favorite_list() -> String {
-    let mut samp_user_data: SAMPUserData = SAMPUserData {
-        file_id: "none".to_string(),
-        file_version: 0,
-        server_count: 0,
-        favorite_servers: Vec::<SAMPServerInfo>::from([]),
+/// Writes a favorites list to `path` as a SA-MP `USERDATA.DAT`. `encoding` is
+/// an encoding label (e.g. `windows-1251`), picked automatically if not given.
+#[tauri::command]
+pub fn export_samp_favorite_list(
+    servers: Vec<SAMPServerInfo>,
+    path: String,
+    encoding: Option<String>,
+) -> Result<(), LauncherError> {
+    let encoding = match encoding {
+        Some(label) => Some(
+            Encoding::for_label(label.as_bytes())
+                .ok_or_else(|| LauncherError::Other(format!("Unknown encoding {}", label)))?,
+        ),
+        None => None,
     };
 
-    let documents_path = document_dir()
-        .expect("unable to retrieve computer's documents path")
-        .to_str()
-        .expect("couldn't convert Documents path to &str")
-        .to_string();
+    let path = PathBuf::from(path);
+    let mut servers = servers;
+
+    // Whatever file is replaced is the most likely one to have rcon passwords
+    // for these servers, SA-MP's own file otherwise
+    let existing = if path.is_file() {
+        get_samp_favorite_list(Some(&path))
+    } else {
+        get_samp_favorite_list(None)
+    };
+    match existing {
+        Ok(existing) => carry_over_rcon(&mut servers, &existing.favorite_servers),
+        Err(e) => info!("[samp.rs] Not carrying over rcon passwords: {}", e),
+    }
+
+    helpers::write_file_atomic(&path, &write_userdata(&servers, encoding))?;
 
-    let userdata_path = format!(
-        "{}\\GTA San Andreas User Files\\SAMP\\USERDATA.DAT",
-        documents_path
+    info!(
+        "[samp.rs] Exported {} favorite server(s) to {}",
+        servers.len(),
+        path.display()
     );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if Path::new(userdata_path.as_str()).exists() {
-        let mut file = File::open(userdata_path).unwrap();
-
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer).unwrap();
-        let mut r = Cursor::new(buffer.clone());
-
-        // it's usually "SAMP"
-        let mut file_id = [0; 4];
-        r.read_exact(&mut file_id).unwrap();
-        samp_user_data.file_id = String::from_utf8(file_id.to_vec()).unwrap();
-
-        samp_user_data.file_version = r.read_u32::<LittleEndian>().unwrap();
-        samp_user_data.server_count = r.read_u32::<LittleEndian>().unwrap();
-
-        for _ in 0..samp_user_data.server_count {
-            let mut server_info = SAMPServerInfo {
-                ip: "".to_string(),
-                port: 0,
-                name: "".to_string(),
-                password: "".to_string(),
-                rcon: "".to_string(),
-            };
-
-            let ip_len = r.read_u32::<LittleEndian>().unwrap();
-            let mut pos: u32 = r.position().try_into().unwrap();
-            let server_ip = buffer[pos as usize..(pos + ip_len) as usize].to_vec();
-            server_info.ip = helpers::decode_buffer(server_ip).0;
-            r.set_position((pos + ip_len).try_into().unwrap());
-
-            server_info.port = r.read_u32::<LittleEndian>().unwrap();
-
-            let name_len = r.read_u32::<LittleEndian>().unwrap();
-            pos = r.position().try_into().unwrap();
-            let server_name = buffer[pos as usize..(pos + name_len) as usize].to_vec();
-            server_info.name = helpers::decode_buffer(server_name).0;
-            r.set_position((pos + name_len).try_into().unwrap());
-
-            let password_len = r.read_u32::<LittleEndian>().unwrap();
-            if password_len != 0 {
-                pos = r.position().try_into().unwrap();
-                let server_password = buffer[pos as usize..(pos + password_len) as usize].to_vec();
-                server_info.password = helpers::decode_buffer(server_password).0;
-                r.set_position((pos + password_len).try_into().unwrap());
-            }
-
-            let rcon_len = r.read_u32::<LittleEndian>().unwrap();
-            if rcon_len != 0 {
-                pos = r.position().try_into().unwrap();
-                let server_rcon = buffer[pos as usize..(pos + rcon_len) as usize].to_vec();
-                server_info.rcon = helpers::decode_buffer(server_rcon).0;
-                r.set_position((pos + rcon_len).try_into().unwrap());
-            }
-
-            println!(
-                "{} | + {}",
-                serde_json::to_string(&server_info).unwrap(),
-                r.position()
-            );
-
-            samp_user_data.favorite_servers.push(server_info);
+    fn server(ip: &str, port: u32, name: &str, password: &str, rcon: &str) -> SAMPServerInfo {
+        SAMPServerInfo {
+            ip: ip.to_string(),
+            port,
+            name: name.to_string(),
+            password: password.to_string(),
+            rcon: rcon.to_string(),
         }
     }
 
-    serde_json::to_string(&samp_user_data).unwrap()
+    #[test]
+    fn userdata_round_trip() {
+        let servers = vec![
+            server("127.0.0.1", 7777, "Local Server", "", ""),
+            server(
+                "play.example.com",
+                7778,
+                "Roleplay | Freeroam",
+                "secret",
+                "admin123",
+            ),
+            server(
+                "192.168.0.10",
+                8888,
+                "(Retrieving info...)",
+                "",
+                "rcon-only",
+            ),
+        ];
+
+        let buffer = write_userdata(&servers, None);
+        assert_eq!(&buffer[0..4], USERDATA_FILE_ID);
+
+        let parsed = parse_userdata(&buffer).unwrap();
+        assert_eq!(parsed.file_id, "SAMP");
+        assert_eq!(parsed.file_version, USERDATA_FILE_VERSION);
+        assert_eq!(parsed.server_count, servers.len() as u32);
+
+        for (expected, actual) in servers.iter().zip(&parsed.favorite_servers) {
+            assert_eq!(expected.ip, actual.ip);
+            assert_eq!(expected.port, actual.port);
+            assert_eq!(expected.name, actual.name);
+            assert_eq!(expected.password, actual.password);
+            assert_eq!(expected.rcon, actual.rcon);
+        }
+
+        // Written again, the parsed list comes out byte for byte the same
+        assert_eq!(write_userdata(&parsed.favorite_servers, None), buffer);
+    }
+
+    #[test]
+    fn userdata_legacy_code_page() {
+        let servers = vec![server(
+            "127.0.0.1",
+            7777,
+            "Русский сервер для ролевой игры",
+            "",
+            "",
+        )];
+
+        let buffer = write_userdata(&servers, None);
+        // One byte per character in windows-1251, not UTF-8's two
+        let name_len = u32::from_le_bytes(buffer[29..33].try_into().unwrap()) as usize;
+        assert_eq!(name_len, servers[0].name.chars().count());
+
+        let (expected, _, _) = WINDOWS_1251.encode(&servers[0].name);
+        assert_eq!(&buffer[33..33 + name_len], &expected[..]);
+    }
+
+    #[test]
+    fn userdata_carries_over_rcon() {
+        let mut servers = vec![
+            server("play.example.com", 7777, "Server", "", ""),
+            server("127.0.0.1", 7777, "Local", "", "set-by-user"),
+            server("127.0.0.1", 7778, "Other port", "", ""),
+        ];
+        let existing = vec![
+            server("PLAY.example.com", 7777, "Old name", "", "stored"),
+            server("127.0.0.1", 7777, "Local", "", "old"),
+        ];
+
+        carry_over_rcon(&mut servers, &existing);
+        assert_eq!(servers[0].rcon, "stored");
+        assert_eq!(servers[1].rcon, "set-by-user");
+        assert_eq!(servers[2].rcon, "");
+    }
+
+    #[test]
+    fn userdata_corrupt_files() {
+        let buffer = write_userdata(&[server("127.0.0.1", 7777, "Server", "secret", "")], None);
+
+        // Cut off anywhere, the file is reported as corrupt instead of panicking
+        for len in 0..buffer.len() {
+            let error = parse_userdata(&buffer[..len]).err().unwrap();
+            assert_eq!(error.code(), "corrupt_file");
+            assert!(!error.details().contains("secret"));
+        }
+
+        let mut wrong_id = buffer.clone();
+        wrong_id[0..4].copy_from_slice(b"PMAS");
+        assert_eq!(
+            parse_userdata(&wrong_id).err().unwrap().code(),
+            "corrupt_file"
+        );
+
+        // A string claiming to be longer than what's left of the file
+        let mut long_string = buffer.clone();
+        long_string[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            parse_userdata(&long_string).err().unwrap().code(),
+            "corrupt_file"
+        );
+
+        let mut huge_count = buffer;
+        huge_count[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            parse_userdata(&huge_count).err().unwrap().code(),
+            "corrupt_file"
+        );
+    }
 }
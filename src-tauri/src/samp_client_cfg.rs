@@ -0,0 +1,454 @@
+use encoding_rs::{Encoding, UTF_8};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::errors::LauncherError;
+use crate::helpers;
+use crate::installations;
+use crate::samp;
+
+pub const CLIENT_CFG_FILE_NAME: &str = "sa-mp.cfg";
+
+const PAGE_SIZE: &str = "pagesize";
+const FPS_LIMIT: &str = "fpslimit";
+const TIMESTAMP: &str = "timestamp";
+const AUDIO_MSG_OFF: &str = "audiomsgoff";
+const AUDIO_PROXY_OFF: &str = "audioproxyoff";
+const DISABLE_HEAD_MOVE: &str = "disableheadmove";
+const MULTICORE: &str = "multicore";
+const DIRECT_MODE: &str = "directmode";
+const IME: &str = "ime";
+const NO_NAME_TAG_STATUS: &str = "nonametagstatus";
+const FONT_FACE: &str = "fontface";
+const FONT_WEIGHT: &str = "fontweight";
+
+/// Values the client accepts, anything else it ignores or clamps
+const PAGE_SIZE_RANGE: (u32, u32) = (10, 20);
+const FPS_LIMIT_RANGE: (u32, u32) = (20, 90);
+
+/// Options of the SA-MP client. `None` means the option isn't in the file (the
+/// client's default applies), or when setting, that it's left as it is.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct SampClientSettings {
+    /// Number of chat lines shown, 10 to 20
+    pub page_size: Option<u32>,
+    /// Frame rate limit, 20 to 90
+    pub fps_limit: Option<u32>,
+    /// Show the time next to chat messages
+    pub timestamp: Option<bool>,
+    /// Hide the "Audio stream" chat messages
+    pub audio_msg_off: Option<bool>,
+    pub audio_proxy_off: Option<bool>,
+    /// Stop the player's head from following the camera
+    pub disable_head_move: Option<bool>,
+    pub multicore: Option<bool>,
+    pub direct_mode: Option<bool>,
+    pub ime: Option<bool>,
+    pub no_name_tag_status: Option<bool>,
+    /// Chat font, e.g. `Arial`
+    pub font_face: Option<String>,
+    /// Bold chat font
+    pub font_weight: Option<bool>,
+}
+
+/// Client settings along with the file they're kept in
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SampClientConfig {
+    pub path: String,
+    /// The file isn't specific to the requested installation. Outside of Wine
+    /// prefixes (and so always on Windows) the client reads its `sa-mp.cfg`
+    /// from the user's documents, whichever game it runs from.
+    pub shared: bool,
+    pub settings: SampClientSettings,
+}
+
+enum CfgLine {
+    Entry {
+        key: String,
+        value: String,
+        raw: String,
+    },
+    /// Comments, blank lines and anything else that isn't `key=value`
+    Other(String),
+}
+
+/// Contents of a `sa-mp.cfg`. Lines are kept as they are unless their value
+/// is changed, so unknown keys and comments survive a round trip.
+pub struct ClientCfg {
+    lines: Vec<CfgLine>,
+    line_ending: &'static str,
+    /// Encoding the file was read in and is written back in. The client uses
+    /// the system code page, which only matters for the font name.
+    encoding: &'static Encoding,
+}
+
+impl ClientCfg {
+    pub fn parse(content: &str) -> Self {
+        let line_ending = if content.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+
+        let lines = content
+            .lines()
+            .map(|line| {
+                let trimmed = line.trim();
+                match trimmed.split_once('=') {
+                    Some((key, value))
+                        if !key.trim().is_empty()
+                            && !trimmed.starts_with(';')
+                            && !trimmed.starts_with('#') =>
+                    {
+                        CfgLine::Entry {
+                            key: key.trim().to_string(),
+                            value: value.trim().to_string(),
+                            raw: line.to_string(),
+                        }
+                    }
+                    _ => CfgLine::Other(line.to_string()),
+                }
+            })
+            .collect();
+
+        Self {
+            lines,
+            line_ending,
+            encoding: UTF_8,
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        if let Ok(content) = std::str::from_utf8(bytes) {
+            return Self::parse(content);
+        }
+
+        let (content, encoding) = helpers::decode_buffer(bytes.to_vec(), None);
+        Self {
+            encoding: Encoding::for_label(encoding.as_bytes()).unwrap_or(UTF_8),
+            ..Self::parse(&content)
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encoding.encode(&self.to_string()).0.into_owned()
+    }
+
+    /// Value of the first entry of a key, keys are case-insensitive
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.lines.iter().find_map(|line| match line {
+            CfgLine::Entry { key, value, .. } if key.eq_ignore_ascii_case(name) => {
+                Some(value.as_str())
+            }
+            _ => None,
+        })
+    }
+
+    /// Changes the first entry of a key, or appends one if there's none
+    pub fn set(&mut self, name: &str, new_value: &str) {
+        for line in self.lines.iter_mut() {
+            if let CfgLine::Entry { key, value, raw } = line {
+                if key.eq_ignore_ascii_case(name) {
+                    if value != new_value {
+                        *value = new_value.to_string();
+                        *raw = format!("{}={}", key, new_value);
+                    }
+                    return;
+                }
+            }
+        }
+
+        self.lines.push(CfgLine::Entry {
+            key: name.to_string(),
+            value: new_value.to_string(),
+            raw: format!("{}={}", name, new_value),
+        });
+    }
+
+    fn get_u32(&self, name: &str) -> Option<u32> {
+        self.get(name)?.parse().ok()
+    }
+
+    fn get_bool(&self, name: &str) -> Option<bool> {
+        self.get_u32(name).map(|value| value != 0)
+    }
+
+    fn set_bool(&mut self, name: &str, value: bool) {
+        self.set(name, if value { "1" } else { "0" });
+    }
+}
+
+impl fmt::Display for ClientCfg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            let raw = match line {
+                CfgLine::Entry { raw, .. } => raw,
+                CfgLine::Other(raw) => raw,
+            };
+            write!(f, "{}{}", raw, self.line_ending)?;
+        }
+        Ok(())
+    }
+}
+
+fn check_range(
+    name: &str,
+    value: Option<u32>,
+    (min, max): (u32, u32),
+) -> Result<(), LauncherError> {
+    match value {
+        Some(value) if value < min || value > max => Err(LauncherError::InvalidSetting(format!(
+            "{} must be between {} and {}, got {}",
+            name, min, max, value
+        ))),
+        _ => Ok(()),
+    }
+}
+
+impl SampClientSettings {
+    pub fn from_cfg(cfg: &ClientCfg) -> Self {
+        Self {
+            page_size: cfg.get_u32(PAGE_SIZE),
+            fps_limit: cfg.get_u32(FPS_LIMIT),
+            timestamp: cfg.get_bool(TIMESTAMP),
+            audio_msg_off: cfg.get_bool(AUDIO_MSG_OFF),
+            audio_proxy_off: cfg.get_bool(AUDIO_PROXY_OFF),
+            disable_head_move: cfg.get_bool(DISABLE_HEAD_MOVE),
+            multicore: cfg.get_bool(MULTICORE),
+            direct_mode: cfg.get_bool(DIRECT_MODE),
+            ime: cfg.get_bool(IME),
+            no_name_tag_status: cfg.get_bool(NO_NAME_TAG_STATUS),
+            font_face: cfg
+                .get(FONT_FACE)
+                .map(|face| face.trim_matches('"').to_string()),
+            font_weight: cfg.get_bool(FONT_WEIGHT),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), LauncherError> {
+        check_range(PAGE_SIZE, self.page_size, PAGE_SIZE_RANGE)?;
+        check_range(FPS_LIMIT, self.fps_limit, FPS_LIMIT_RANGE)?;
+
+        if let Some(face) = &self.font_face {
+            if face.trim().is_empty() || face.contains(['"', '\r', '\n']) {
+                return Err(LauncherError::InvalidSetting(format!(
+                    "{} must be a font name, got {:?}",
+                    FONT_FACE, face
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the options that are set into `cfg`, leaving the rest alone
+    pub fn apply_to(&self, cfg: &mut ClientCfg) {
+        let numbers = [(PAGE_SIZE, self.page_size), (FPS_LIMIT, self.fps_limit)];
+        for (name, value) in numbers {
+            if let Some(value) = value {
+                cfg.set(name, &value.to_string());
+            }
+        }
+
+        let flags = [
+            (TIMESTAMP, self.timestamp),
+            (AUDIO_MSG_OFF, self.audio_msg_off),
+            (AUDIO_PROXY_OFF, self.audio_proxy_off),
+            (DISABLE_HEAD_MOVE, self.disable_head_move),
+            (MULTICORE, self.multicore),
+            (DIRECT_MODE, self.direct_mode),
+            (IME, self.ime),
+            (NO_NAME_TAG_STATUS, self.no_name_tag_status),
+            (FONT_WEIGHT, self.font_weight),
+        ];
+        for (name, value) in flags {
+            if let Some(value) = value {
+                cfg.set_bool(name, value);
+            }
+        }
+
+        if let Some(face) = &self.font_face {
+            cfg.set(FONT_FACE, &format!("\"{}\"", face.trim()));
+        }
+    }
+}
+
+/// Location of `sa-mp.cfg` for an installation, or for a game directory if
+/// there's no installation id. Without either it's the one in the user's own
+/// documents. Also returns whether that file is shared, see `SampClientConfig`.
+fn get_client_cfg_path(
+    installation_id: Option<&str>,
+    game_dir: Option<&str>,
+) -> Result<(PathBuf, bool), LauncherError> {
+    let game_dir = match installation_id {
+        Some(id) => Some(installations::get_installation(id)?.path),
+        None => game_dir.map(str::to_string),
+    };
+
+    let shared = !game_dir.as_deref().is_some_and(samp::has_own_user_files);
+    samp::samp_user_files_dir(game_dir.as_deref())
+        .map(|dir| (dir.join(CLIENT_CFG_FILE_NAME), shared))
+        .ok_or_else(|| {
+            LauncherError::NotFound("Could not determine the documents directory".to_string())
+        })
+}
+
+/// Reads a `sa-mp.cfg`, a missing file is the same as an empty one
+pub fn read_client_cfg(path: &Path) -> Result<ClientCfg, LauncherError> {
+    if !path.exists() {
+        return Ok(ClientCfg::parse(""));
+    }
+
+    let bytes = fs::read(path).map_err(|e| LauncherError::from_io(e, path))?;
+    Ok(ClientCfg::from_bytes(&bytes))
+}
+
+pub fn write_client_cfg(path: &Path, cfg: &ClientCfg) -> Result<(), LauncherError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| LauncherError::from_io(e, parent))?;
+    }
+
+    helpers::write_file_atomic(path, &cfg.to_bytes())
+}
+
+pub fn get_client_settings(
+    installation_id: Option<&str>,
+    game_dir: Option<&str>,
+) -> Result<SampClientConfig, LauncherError> {
+    let (path, shared) = get_client_cfg_path(installation_id, game_dir)?;
+    Ok(SampClientConfig {
+        settings: SampClientSettings::from_cfg(&read_client_cfg(&path)?),
+        path: path.to_string_lossy().to_string(),
+        shared,
+    })
+}
+
+/// Changes the options that are set in `settings`, returns all of them after
+/// the change
+pub fn set_client_settings(
+    installation_id: Option<&str>,
+    game_dir: Option<&str>,
+    settings: SampClientSettings,
+) -> Result<SampClientConfig, LauncherError> {
+    settings.validate()?;
+
+    let (path, shared) = get_client_cfg_path(installation_id, game_dir)?;
+    let mut cfg = read_client_cfg(&path)?;
+    settings.apply_to(&mut cfg);
+    write_client_cfg(&path, &cfg)?;
+
+    info!("[samp_client_cfg.rs] Updated {}", path.display());
+    Ok(SampClientConfig {
+        settings: SampClientSettings::from_cfg(&cfg),
+        path: path.to_string_lossy().to_string(),
+        shared,
+    })
+}
+
+#[tauri::command]
+pub async fn get_samp_client_settings(
+    installation_id: Option<String>,
+    game_dir: Option<String>,
+) -> Result<SampClientConfig, LauncherError> {
+    tokio::task::spawn_blocking(move || {
+        get_client_settings(installation_id.as_deref(), game_dir.as_deref())
+    })
+    .await
+    .map_err(|e| LauncherError::Other(e.to_string()))?
+}
+
+#[tauri::command]
+pub async fn set_samp_client_settings(
+    installation_id: Option<String>,
+    game_dir: Option<String>,
+    settings: SampClientSettings,
+) -> Result<SampClientConfig, LauncherError> {
+    tokio::task::spawn_blocking(move || {
+        set_client_settings(installation_id.as_deref(), game_dir.as_deref(), settings)
+    })
+    .await
+    .map_err(|e| LauncherError::Other(e.to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CFG: &str = "pagesize=15\r\n; comment\r\nfpslimit=60\r\ntimestamp=1\r\nfontface=\"Arial\"\r\nunknown=abc\r\n";
+
+    #[test]
+    fn reads_settings() {
+        let settings = SampClientSettings::from_cfg(&ClientCfg::parse(CFG));
+        assert_eq!(settings.page_size, Some(15));
+        assert_eq!(settings.fps_limit, Some(60));
+        assert_eq!(settings.timestamp, Some(true));
+        assert_eq!(settings.font_face.as_deref(), Some("Arial"));
+        assert_eq!(settings.multicore, None);
+    }
+
+    #[test]
+    fn applies_only_set_options() {
+        let mut cfg = ClientCfg::parse(CFG);
+        SampClientSettings {
+            fps_limit: Some(90),
+            multicore: Some(false),
+            ..Default::default()
+        }
+        .apply_to(&mut cfg);
+
+        assert_eq!(
+            cfg.to_string(),
+            "pagesize=15\r\n; comment\r\nfpslimit=90\r\ntimestamp=1\r\nfontface=\"Arial\"\r\nunknown=abc\r\nmulticore=0\r\n"
+        );
+    }
+
+    #[test]
+    fn accepts_valid_settings() {
+        let settings = SampClientSettings {
+            page_size: Some(PAGE_SIZE_RANGE.0),
+            fps_limit: Some(FPS_LIMIT_RANGE.1),
+            font_face: Some("Courier New".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(settings.validate(), Ok(()));
+        assert_eq!(SampClientSettings::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let invalid = [
+            SampClientSettings {
+                page_size: Some(PAGE_SIZE_RANGE.0 - 1),
+                ..Default::default()
+            },
+            SampClientSettings {
+                page_size: Some(PAGE_SIZE_RANGE.1 + 1),
+                ..Default::default()
+            },
+            SampClientSettings {
+                fps_limit: Some(FPS_LIMIT_RANGE.0 - 1),
+                ..Default::default()
+            },
+            SampClientSettings {
+                fps_limit: Some(FPS_LIMIT_RANGE.1 + 1),
+                ..Default::default()
+            },
+            SampClientSettings {
+                font_face: Some("  ".to_string()),
+                ..Default::default()
+            },
+            SampClientSettings {
+                font_face: Some("Arial\"\r\npagesize=99".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        for settings in invalid {
+            let error = settings.validate().unwrap_err();
+            assert_eq!(error.code(), "invalid_setting", "{:?}", settings);
+        }
+    }
+}
@@ -0,0 +1,678 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::errors::LauncherError;
+use crate::helpers;
+
+/// Registry key SA-MP keeps its settings in, relative to HKEY_CURRENT_USER
+pub const SAMP_KEY: &str = r"Software\SAMP";
+pub const PLAYER_NAME_VALUE: &str = "PlayerName";
+pub const GAME_EXE_VALUE: &str = "gta_sa_exe";
+
+/// Seconds between 1601-01-01 (where Windows FILETIMEs start) and 1970-01-01
+const FILETIME_UNIX_EPOCH_OFFSET: u64 = 11_644_473_600;
+
+/// Where SA-MP's settings (player name, game path) are read from and written to
+pub trait SampConfigStore {
+    /// Reads a string value, `None` if it doesn't exist
+    fn read_value(&self, name: &str) -> Result<Option<String>, LauncherError>;
+
+    fn write_value(&mut self, name: &str, value: &str) -> Result<(), LauncherError>;
+
+    /// Converts a path as SA-MP stores it into one usable by the launcher
+    fn to_native_path(&self, path: &str) -> String {
+        path.to_string()
+    }
+
+    /// Converts a launcher path into one SA-MP understands. The frontend uses
+    /// forward slashes everywhere, SA-MP only knows backslashes.
+    fn to_samp_path(&self, path: &str) -> String {
+        path.replace('/', "\\")
+    }
+
+    /// Human readable description of where the settings live
+    fn location(&self) -> String;
+
+    /// When the settings were last changed, in seconds since the Unix epoch
+    fn modified_at(&self) -> Option<u64> {
+        None
+    }
+
+    fn player_name(&self) -> Option<String> {
+        self.read_value(PLAYER_NAME_VALUE)
+            .ok()
+            .flatten()
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Directory of the game executable SA-MP is set up to launch
+    fn game_dir(&self) -> Option<String> {
+        let exe = self.read_value(GAME_EXE_VALUE).ok().flatten()?;
+        let dir = match exe.rfind(['\\', '/']) {
+            Some(index) if exe[index + 1..].eq_ignore_ascii_case("gta_sa.exe") => &exe[..index],
+            _ => exe.as_str(),
+        };
+
+        if dir.is_empty() {
+            None
+        } else {
+            Some(self.to_native_path(dir))
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub struct RegistryStore;
+
+#[cfg(target_os = "windows")]
+impl SampConfigStore for RegistryStore {
+    fn read_value(&self, name: &str) -> Result<Option<String>, LauncherError> {
+        use winreg::enums::{HKEY_CURRENT_USER, KEY_READ};
+        use winreg::RegKey;
+
+        let key = match RegKey::predef(HKEY_CURRENT_USER).open_subkey_with_flags(SAMP_KEY, KEY_READ)
+        {
+            Ok(key) => key,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(LauncherError::from_io(e, SAMP_KEY)),
+        };
+
+        match key.get_value::<String, _>(name) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(LauncherError::from_io(e, SAMP_KEY)),
+        }
+    }
+
+    fn write_value(&mut self, name: &str, value: &str) -> Result<(), LauncherError> {
+        use winreg::enums::HKEY_CURRENT_USER;
+        use winreg::RegKey;
+
+        let (key, _) = RegKey::predef(HKEY_CURRENT_USER)
+            .create_subkey(SAMP_KEY)
+            .map_err(|e| LauncherError::from_io(e, SAMP_KEY))?;
+        key.set_value(name, &value.to_string())
+            .map_err(|e| LauncherError::from_io(e, SAMP_KEY))
+    }
+
+    fn location(&self) -> String {
+        format!(r"HKEY_CURRENT_USER\{}", SAMP_KEY)
+    }
+
+    fn modified_at(&self) -> Option<u64> {
+        use winreg::enums::{HKEY_CURRENT_USER, KEY_READ};
+        use winreg::RegKey;
+
+        let key = RegKey::predef(HKEY_CURRENT_USER)
+            .open_subkey_with_flags(SAMP_KEY, KEY_READ)
+            .ok()?;
+        let time = key.query_info().ok()?.last_write_time;
+        let filetime = ((time.dwHighDateTime as u64) << 32) | time.dwLowDateTime as u64;
+        (filetime / 10_000_000).checked_sub(FILETIME_UNIX_EPOCH_OFFSET)
+    }
+}
+
+/// SA-MP settings inside a Wine prefix, stored in its `user.reg` text file
+pub struct WineRegistryStore {
+    prefix: PathBuf,
+}
+
+impl WineRegistryStore {
+    pub fn new(prefix: impl AsRef<Path>) -> Self {
+        Self {
+            prefix: prefix.as_ref().to_path_buf(),
+        }
+    }
+
+    fn user_reg(&self) -> PathBuf {
+        self.prefix.join("user.reg")
+    }
+
+    /// Whether a wineserver is running for this prefix. Wine finds its server
+    /// through a socket in `/tmp/.wine-<uid>/server-<dev>-<inode>/`, named
+    /// after the prefix directory, so that's what we try to connect to.
+    #[cfg(unix)]
+    fn wineserver_running(&self) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        use std::os::unix::net::UnixStream;
+
+        let metadata = match fs::metadata(&self.prefix) {
+            Ok(metadata) => metadata,
+            Err(_) => return false,
+        };
+        let socket = PathBuf::from(format!("/tmp/.wine-{}", metadata.uid()))
+            .join(format!("server-{:x}-{:x}", metadata.dev(), metadata.ino()))
+            .join("socket");
+        UnixStream::connect(socket).is_ok()
+    }
+
+    #[cfg(not(unix))]
+    fn wineserver_running(&self) -> bool {
+        false
+    }
+
+    /// Where a drive letter points to, `dosdevices` holds the actual mapping
+    fn drive_root(&self, letter: char) -> PathBuf {
+        let letter = letter.to_ascii_lowercase();
+        let device = self.prefix.join("dosdevices").join(format!("{}:", letter));
+        if let Ok(target) = fs::canonicalize(&device) {
+            return target;
+        }
+
+        match letter {
+            'c' => self.prefix.join("drive_c"),
+            _ => PathBuf::from("/"),
+        }
+    }
+}
+
+impl SampConfigStore for WineRegistryStore {
+    fn read_value(&self, name: &str) -> Result<Option<String>, LauncherError> {
+        let file = self.user_reg();
+        let content = fs::read(&file).map_err(|e| LauncherError::from_io(e, &file))?;
+        let registry = WineRegistry::parse(&String::from_utf8_lossy(&content));
+        Ok(registry.get_string(SAMP_KEY, name))
+    }
+
+    fn write_value(&mut self, name: &str, value: &str) -> Result<(), LauncherError> {
+        // A running wineserver keeps the registry in memory and writes it back
+        // over user.reg later on, which would silently undo our change
+        if self.wineserver_running() {
+            return Err(LauncherError::Busy(format!(
+                "Wine is running in {}, close it before changing SA-MP's settings",
+                self.prefix.display()
+            )));
+        }
+
+        let file = self.user_reg();
+        let content = fs::read(&file).map_err(|e| LauncherError::from_io(e, &file))?;
+        let mut registry = WineRegistry::parse(&String::from_utf8_lossy(&content));
+        registry.set_string(SAMP_KEY, name, value);
+        helpers::write_file_atomic(&file, registry.to_string().as_bytes())
+    }
+
+    fn to_native_path(&self, path: &str) -> String {
+        let mut chars = path.chars();
+        match (chars.next(), chars.next()) {
+            (Some(letter), Some(':')) if letter.is_ascii_alphabetic() => {
+                let rest = path[2..].trim_start_matches('\\').replace('\\', "/");
+                self.drive_root(letter)
+                    .join(rest)
+                    .to_string_lossy()
+                    .to_string()
+            }
+            _ => path.replace('\\', "/"),
+        }
+    }
+
+    fn to_samp_path(&self, path: &str) -> String {
+        let path = Path::new(path);
+        let drive_c = self.drive_root('c');
+        let (letter, rest) = match path.strip_prefix(&drive_c) {
+            Ok(rest) => ('C', rest),
+            // Wine maps Z: to the root of the file system by default
+            Err(_) => ('Z', path.strip_prefix("/").unwrap_or(path)),
+        };
+
+        format!(r"{}:\{}", letter, rest.to_string_lossy().replace('/', "\\"))
+    }
+
+    fn location(&self) -> String {
+        self.user_reg().to_string_lossy().to_string()
+    }
+
+    fn modified_at(&self) -> Option<u64> {
+        let content = fs::read(self.user_reg()).ok()?;
+        WineRegistry::parse(&String::from_utf8_lossy(&content)).key_modified_at(SAMP_KEY)
+    }
+}
+
+/// A line of a Wine registry file, either belonging to the header or to a key
+enum RegistryLine {
+    /// `name` is unescaped, `raw_name` is kept to write it back unchanged
+    Value {
+        name: String,
+        raw_name: String,
+        raw: String,
+    },
+    Other(String),
+}
+
+struct RegistryKey {
+    /// Key path as written in the file, with escaped backslashes
+    header: String,
+    lines: Vec<RegistryLine>,
+}
+
+/// Minimal reader/writer for Wine's registry files. Everything that isn't
+/// touched is written back exactly as it was read.
+pub struct WineRegistry {
+    preamble: Vec<String>,
+    keys: Vec<RegistryKey>,
+}
+
+fn escape_key_path(path: &str) -> String {
+    path.replace('\\', "\\\\")
+}
+
+/// Undoes Wine's string escaping: `\\`, `\"`, `\n`, `\r`, `\t`, `\0` and
+/// `\xHHHH` for UTF-16 code units outside of printable ASCII
+fn unescape_string(raw: &str) -> String {
+    let mut units: Vec<u16> = Vec::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0u16; 2];
+            units.extend_from_slice(c.encode_utf16(&mut buf));
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => units.push('\n' as u16),
+            Some('r') => units.push('\r' as u16),
+            Some('t') => units.push('\t' as u16),
+            Some('0') => units.push(0),
+            Some('x') => {
+                let mut code = 0u16;
+                for _ in 0..4 {
+                    match chars.peek().and_then(|c| c.to_digit(16)) {
+                        Some(digit) => {
+                            code = code * 16 + digit as u16;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                units.push(code);
+            }
+            Some(other) => units.push(other as u16),
+            None => units.push('\\' as u16),
+        }
+    }
+
+    String::from_utf16_lossy(&units)
+}
+
+fn escape_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for unit in value.encode_utf16() {
+        match unit {
+            0x5C => escaped.push_str("\\\\"),
+            0x22 => escaped.push_str("\\\""),
+            0x0A => escaped.push_str("\\n"),
+            0x0D => escaped.push_str("\\r"),
+            0x09 => escaped.push_str("\\t"),
+            0x20..=0x7E => escaped.push(unit as u8 as char),
+            _ => escaped.push_str(&format!("\\x{:04x}", unit)),
+        }
+    }
+    escaped
+}
+
+/// Splits `"name"=value` into the raw name and the raw value
+fn parse_value_line(line: &str) -> Option<(String, String)> {
+    let rest = line.strip_prefix('"')?;
+    let mut end = None;
+    let mut escaped = false;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => {
+                end = Some(i);
+                break;
+            }
+            _ => escaped = false,
+        }
+    }
+
+    let end = end?;
+    let value = rest[end + 1..].strip_prefix('=')?;
+    Some((rest[..end].to_string(), value.to_string()))
+}
+
+/// 100ns intervals since 1601-01-01 (a Windows FILETIME), what `#time=` holds
+fn filetime_now() -> u64 {
+    let unix = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    (unix.as_secs() + FILETIME_UNIX_EPOCH_OFFSET) * 10_000_000 + unix.subsec_nanos() as u64 / 100
+}
+
+impl WineRegistry {
+    pub fn parse(content: &str) -> Self {
+        let mut registry = WineRegistry {
+            preamble: Vec::new(),
+            keys: Vec::new(),
+        };
+
+        for line in content.lines() {
+            if line.starts_with('[') {
+                registry.keys.push(RegistryKey {
+                    header: line.to_string(),
+                    lines: Vec::new(),
+                });
+                continue;
+            }
+
+            match registry.keys.last_mut() {
+                Some(key) => key.lines.push(match parse_value_line(line) {
+                    Some((raw_name, raw)) => RegistryLine::Value {
+                        name: unescape_string(&raw_name),
+                        raw_name,
+                        raw,
+                    },
+                    None => RegistryLine::Other(line.to_string()),
+                }),
+                None => registry.preamble.push(line.to_string()),
+            }
+        }
+
+        registry
+    }
+
+    fn find_key(&self, path: &str) -> Option<usize> {
+        let wanted = format!("[{}]", escape_key_path(path)).to_lowercase();
+        self.keys.iter().position(|key| {
+            // Headers carry a modification timestamp after the closing bracket
+            let header = key.header.to_lowercase();
+            header == wanted || header.starts_with(&format!("{} ", wanted))
+        })
+    }
+
+    /// Wine writes the time a key was last changed after its header, in
+    /// seconds since the Unix epoch
+    pub fn key_modified_at(&self, path: &str) -> Option<u64> {
+        let header = &self.keys[self.find_key(path)?].header;
+        let (_, time) = header.rsplit_once(']')?;
+        time.trim().parse().ok()
+    }
+
+    pub fn get_string(&self, path: &str, name: &str) -> Option<String> {
+        let key = &self.keys[self.find_key(path)?];
+        key.lines.iter().find_map(|line| match line {
+            RegistryLine::Value { name: n, raw, .. } if n.eq_ignore_ascii_case(name) => {
+                let raw = raw.strip_prefix("str(2):").unwrap_or(raw);
+                let inner = raw.strip_prefix('"')?.strip_suffix('"')?;
+                Some(unescape_string(inner))
+            }
+            _ => None,
+        })
+    }
+
+    pub fn set_string(&mut self, path: &str, name: &str, value: &str) {
+        let unix_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let header = format!("[{}] {}", escape_key_path(path), unix_time);
+        let raw = format!("\"{}\"", escape_string(value));
+
+        let index = match self.find_key(path) {
+            Some(index) => index,
+            None => {
+                self.keys.push(RegistryKey {
+                    header: header.clone(),
+                    lines: vec![RegistryLine::Other(String::new())],
+                });
+                self.keys.len() - 1
+            }
+        };
+
+        let key = &mut self.keys[index];
+        key.header = header;
+
+        for line in key.lines.iter_mut() {
+            if let RegistryLine::Other(text) = line {
+                if text.starts_with("#time=") {
+                    *text = format!("#time={:x}", filetime_now());
+                }
+            }
+        }
+
+        let existing = key.lines.iter_mut().find_map(|line| match line {
+            RegistryLine::Value { name: n, raw, .. } if n.eq_ignore_ascii_case(name) => Some(raw),
+            _ => None,
+        });
+
+        match existing {
+            Some(existing) => *existing = raw,
+            None => {
+                // Keep the blank line that separates keys at the end
+                let position = key
+                    .lines
+                    .iter()
+                    .rposition(|line| !matches!(line, RegistryLine::Other(text) if text.is_empty()))
+                    .map(|i| i + 1)
+                    .unwrap_or(0);
+                key.lines.insert(
+                    position,
+                    RegistryLine::Value {
+                        name: name.to_string(),
+                        raw_name: escape_string(name),
+                        raw,
+                    },
+                );
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for WineRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.preamble {
+            writeln!(f, "{}", line)?;
+        }
+
+        for key in &self.keys {
+            writeln!(f, "{}", key.header)?;
+            for line in &key.lines {
+                match line {
+                    RegistryLine::Value { raw_name, raw, .. } => {
+                        writeln!(f, "\"{}\"={}", raw_name, raw)?
+                    }
+                    RegistryLine::Other(text) => writeln!(f, "{}", text)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Opens the store SA-MP uses on this system: the registry on Windows, or the
+/// first Wine/Proton prefix that has SA-MP settings elsewhere
+pub fn open_store() -> Option<Box<dyn SampConfigStore>> {
+    #[cfg(target_os = "windows")]
+    {
+        Some(Box::new(RegistryStore))
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        crate::discovery::find_wine_prefixes()
+            .into_iter()
+            .map(WineRegistryStore::new)
+            .find(|store| {
+                store.read_value(PLAYER_NAME_VALUE).ok().flatten().is_some()
+                    || store.read_value(GAME_EXE_VALUE).ok().flatten().is_some()
+            })
+            .map(|store| Box::new(store) as Box<dyn SampConfigStore>)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SampConfig {
+    pub location: String,
+    pub player_name: Option<String>,
+    pub game_dir: Option<String>,
+}
+
+/// Writes the player name and/or game directory to SA-MP's settings
+pub fn write_config(
+    store: &mut dyn SampConfigStore,
+    player_name: Option<&str>,
+    game_dir: Option<&str>,
+) -> Result<(), LauncherError> {
+    if let Some(player_name) = player_name {
+        store.write_value(PLAYER_NAME_VALUE, player_name)?;
+    }
+
+    if let Some(game_dir) = game_dir {
+        let exe = format!(
+            r"{}\gta_sa.exe",
+            store.to_samp_path(game_dir).trim_end_matches('\\')
+        );
+        store.write_value(GAME_EXE_VALUE, &exe)?;
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_samp_config() -> Option<SampConfig> {
+    let store = open_store()?;
+    Some(SampConfig {
+        location: store.location(),
+        player_name: store.player_name(),
+        game_dir: store.game_dir(),
+    })
+}
+
+#[tauri::command]
+pub fn write_samp_config(
+    player_name: Option<String>,
+    game_dir: Option<String>,
+) -> Result<(), LauncherError> {
+    let mut store = open_store().ok_or_else(|| {
+        LauncherError::NotFound("No SA-MP settings found to write to".to_string())
+    })?;
+    write_config(store.as_mut(), player_name.as_deref(), game_dir.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER_REG: &str = r#"WINE REGISTRY Version 2
+;; All keys relative to \\User\\S-1-5-21-0-0-0-1000
+
+#arch=win64
+
+[Software\\SAMP] 1700000000
+#time=1da1234567890ab
+"gta_sa_exe"="C:\\Games\\GTA San Andreas\\gta_sa.exe"
+"PlayerName"="Pl\"ayer\x0436"
+
+[Software\\Wine] 1600000000
+#time=1d000000000000
+"Version"=str(2):"win10"
+"#;
+
+    #[test]
+    fn round_trips_untouched_content() {
+        assert_eq!(WineRegistry::parse(USER_REG).to_string(), USER_REG);
+    }
+
+    #[test]
+    fn reads_values() {
+        let registry = WineRegistry::parse(USER_REG);
+        assert_eq!(
+            registry.get_string(SAMP_KEY, GAME_EXE_VALUE).as_deref(),
+            Some(r"C:\Games\GTA San Andreas\gta_sa.exe")
+        );
+        assert_eq!(
+            registry
+                .get_string(r"software\samp", "playername")
+                .as_deref(),
+            Some("Pl\"ayerж")
+        );
+        assert_eq!(
+            registry.get_string(r"Software\Wine", "Version").as_deref(),
+            Some("win10")
+        );
+        assert_eq!(registry.get_string(SAMP_KEY, "Missing"), None);
+        assert_eq!(registry.get_string(r"Software\Missing", "Version"), None);
+        assert_eq!(registry.key_modified_at(SAMP_KEY), Some(1700000000));
+    }
+
+    #[test]
+    fn escapes_strings() {
+        for value in [
+            "plain",
+            r"C:\Games\gta_sa.exe",
+            "quo\"te",
+            "tab\tnew\nline",
+            "Игрок",
+            "名字",
+        ] {
+            assert_eq!(unescape_string(&escape_string(value)), value);
+        }
+        assert_eq!(escape_string(r#"a\"b"#), r#"a\\\"b"#);
+        assert_eq!(escape_string("ж"), r"\x0436");
+        assert_eq!(unescape_string(r"\x41\x0436"), "Aж");
+    }
+
+    #[test]
+    fn writes_only_changed_lines() {
+        let mut registry = WineRegistry::parse(USER_REG);
+        registry.set_string(SAMP_KEY, PLAYER_NAME_VALUE, "New Name");
+        registry.set_string(SAMP_KEY, "Added", "x");
+        let written = registry.to_string();
+
+        let registry = WineRegistry::parse(&written);
+        assert_eq!(
+            registry.get_string(SAMP_KEY, PLAYER_NAME_VALUE).as_deref(),
+            Some("New Name")
+        );
+        assert_eq!(registry.get_string(SAMP_KEY, "Added").as_deref(), Some("x"));
+        assert_ne!(registry.key_modified_at(SAMP_KEY), Some(1700000000));
+
+        // The other key, the header and the key separators are left alone
+        assert!(written.starts_with("WINE REGISTRY Version 2\n"));
+        assert!(written
+            .contains("\"Added\"=\"x\"\n\n[Software\\\\Wine] 1600000000\n#time=1d000000000000\n"));
+        assert!(
+            written.contains("\"gta_sa_exe\"=\"C:\\\\Games\\\\GTA San Andreas\\\\gta_sa.exe\"\n")
+        );
+        assert!(!written.contains("#time=1da1234567890ab"));
+    }
+
+    #[test]
+    fn creates_missing_keys() {
+        let mut registry = WineRegistry::parse("WINE REGISTRY Version 2\n\n");
+        registry.set_string(SAMP_KEY, PLAYER_NAME_VALUE, "Player");
+        let registry = WineRegistry::parse(&registry.to_string());
+        assert_eq!(
+            registry.get_string(SAMP_KEY, PLAYER_NAME_VALUE).as_deref(),
+            Some("Player")
+        );
+    }
+
+    #[test]
+    fn wine_store_converts_paths_and_writes_user_reg() {
+        let prefix = std::env::temp_dir().join(format!("omp-wine-test-{}", std::process::id()));
+        fs::create_dir_all(prefix.join("drive_c/Games")).unwrap();
+        fs::write(prefix.join("user.reg"), USER_REG).unwrap();
+        let mut store = WineRegistryStore::new(&prefix);
+
+        let game_dir = store.game_dir().unwrap();
+        assert_eq!(
+            Path::new(&game_dir),
+            prefix.join("drive_c/Games/GTA San Andreas")
+        );
+        assert_eq!(store.to_samp_path(&game_dir), r"C:\Games\GTA San Andreas");
+        assert_eq!(store.to_samp_path("/home/user/gta"), r"Z:\home\user\gta");
+
+        write_config(&mut store, Some("Renamed"), Some("/home/user/gta/")).unwrap();
+        assert_eq!(store.player_name().as_deref(), Some("Renamed"));
+        assert_eq!(
+            store.read_value(GAME_EXE_VALUE).unwrap().as_deref(),
+            Some(r"Z:\home\user\gta\gta_sa.exe")
+        );
+
+        fs::remove_dir_all(&prefix).unwrap();
+    }
+}
@@ -0,0 +1,258 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::errors::LauncherError;
+use crate::helpers::JsonStore;
+use crate::process;
+use crate::samp_config::{self, SampConfigStore, PLAYER_NAME_VALUE};
+
+/// Its lock serializes syncs, and with them reads and writes of the file
+static SYNC_STORE: JsonStore = JsonStore::new("samp_sync.json");
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncMode {
+    #[default]
+    Off,
+    /// The launcher's settings are written to SA-MP's, never the other way
+    OneWay,
+    /// Changes on either side are carried over to the other one, when both
+    /// changed the newer one wins
+    TwoWay,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncAction {
+    /// Both sides already agree
+    Unchanged,
+    /// The launcher's value was written to SA-MP's settings
+    WrittenToSamp,
+    /// SA-MP's value should be taken over by the launcher
+    TakenFromSamp,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FieldSyncResult {
+    pub action: SyncAction,
+    /// The value both sides have after the sync
+    pub value: Option<String>,
+    /// Both sides were changed since the last sync
+    pub conflict: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SyncResult {
+    pub location: String,
+    pub player_name: FieldSyncResult,
+    pub game_dir: FieldSyncResult,
+}
+
+/// Values as of the last sync, used to tell which side changed since
+#[derive(Serialize, Deserialize, Default)]
+struct SyncState {
+    player_name: Option<String>,
+    game_dir: Option<String>,
+}
+
+/// One side's value of a setting, with when it was last changed
+struct Side<'a> {
+    value: Option<&'a str>,
+    modified_at: Option<u64>,
+}
+
+fn load_sync_state() -> Result<SyncState, LauncherError> {
+    SYNC_STORE.load()
+}
+
+fn save_sync_state(state: &SyncState) -> Result<(), LauncherError> {
+    SYNC_STORE.save(state)
+}
+
+/// Decides which side of a single setting wins
+fn resolve(
+    mode: SyncMode,
+    launcher: &Side,
+    samp: &Side,
+    last_synced: Option<&str>,
+    same: impl Fn(&str, &str) -> bool,
+) -> FieldSyncResult {
+    let equal = |a: Option<&str>, b: Option<&str>| match (a, b) {
+        (Some(a), Some(b)) => same(a, b),
+        (None, None) => true,
+        _ => false,
+    };
+    let result = |action, value: Option<&str>, conflict| FieldSyncResult {
+        action,
+        value: value.map(str::to_string),
+        conflict,
+    };
+
+    if equal(launcher.value, samp.value) {
+        return result(SyncAction::Unchanged, launcher.value, false);
+    }
+
+    // An empty launcher setting is never pushed, it'd only wipe SA-MP's
+    if launcher.value.is_none() {
+        return match mode {
+            SyncMode::TwoWay => result(SyncAction::TakenFromSamp, samp.value, false),
+            _ => result(SyncAction::Unchanged, samp.value, false),
+        };
+    }
+
+    if mode == SyncMode::OneWay || samp.value.is_none() {
+        return result(SyncAction::WrittenToSamp, launcher.value, false);
+    }
+
+    let launcher_changed = !equal(launcher.value, last_synced);
+    let samp_changed = !equal(samp.value, last_synced);
+
+    match (launcher_changed, samp_changed) {
+        (true, false) => result(SyncAction::WrittenToSamp, launcher.value, false),
+        (false, true) => result(SyncAction::TakenFromSamp, samp.value, false),
+        // Changed on both sides (or never synced before), the newer change
+        // wins. Without a timestamp for SA-MP's side the launcher wins.
+        _ => match (launcher.modified_at, samp.modified_at) {
+            (Some(launcher_time), Some(samp_time)) if samp_time > launcher_time => {
+                result(SyncAction::TakenFromSamp, samp.value, true)
+            }
+            _ => result(SyncAction::WrittenToSamp, launcher.value, true),
+        },
+    }
+}
+
+fn is_game_dir(dir: Option<&str>) -> bool {
+    dir.map(|dir| Path::new(dir).join(process::GAME_EXECUTABLE).is_file())
+        .unwrap_or(false)
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().filter(|value| !value.is_empty())
+}
+
+/// Syncs the launcher's nickname and game directory with SA-MP's settings.
+/// Values SA-MP has that the launcher should take over are returned, it's up
+/// to the caller to apply them.
+pub fn sync(
+    store: &mut dyn SampConfigStore,
+    mode: SyncMode,
+    nickname: Option<String>,
+    nickname_modified_at: Option<u64>,
+    game_dir: Option<String>,
+    game_dir_modified_at: Option<u64>,
+) -> Result<SyncResult, LauncherError> {
+    let _guard = SYNC_STORE.lock()?;
+    let state = load_sync_state()?;
+
+    let samp_modified_at = store.modified_at();
+    let samp_nickname = store.player_name();
+    let samp_game_dir = store.game_dir();
+
+    let player_name = resolve(
+        mode,
+        &Side {
+            value: non_empty(&nickname),
+            modified_at: nickname_modified_at,
+        },
+        &Side {
+            value: non_empty(&samp_nickname),
+            modified_at: samp_modified_at,
+        },
+        non_empty(&state.player_name),
+        |a, b| a == b,
+    );
+
+    let launcher_game_dir = game_dir;
+    let mut game_dir = resolve(
+        mode,
+        &Side {
+            value: non_empty(&launcher_game_dir),
+            modified_at: game_dir_modified_at,
+        },
+        &Side {
+            value: non_empty(&samp_game_dir),
+            modified_at: samp_modified_at,
+        },
+        non_empty(&state.game_dir),
+        process::is_same_game_dir,
+    );
+
+    // SA-MP's path may point to a game that was moved or removed since, don't
+    // hand that to the launcher
+    if game_dir.action == SyncAction::TakenFromSamp && !is_game_dir(game_dir.value.as_deref()) {
+        info!(
+            "[samp_sync.rs] Not taking over game path {:?}, there's no {} in it",
+            game_dir.value,
+            process::GAME_EXECUTABLE
+        );
+        game_dir = FieldSyncResult {
+            action: SyncAction::Unchanged,
+            value: non_empty(&launcher_game_dir).map(str::to_string),
+            conflict: game_dir.conflict,
+        };
+    }
+
+    if player_name.action == SyncAction::WrittenToSamp {
+        if let Some(value) = &player_name.value {
+            store.write_value(PLAYER_NAME_VALUE, value)?;
+        }
+    }
+
+    if game_dir.action == SyncAction::WrittenToSamp {
+        if let Some(value) = &game_dir.value {
+            samp_config::write_config(store, None, Some(value))?;
+        }
+    }
+
+    info!(
+        "[samp_sync.rs] Synced with {}: nickname {:?}, game path {:?}",
+        store.location(),
+        player_name.action,
+        game_dir.action
+    );
+
+    save_sync_state(&SyncState {
+        player_name: player_name.value.clone(),
+        game_dir: game_dir.value.clone(),
+    })?;
+
+    Ok(SyncResult {
+        location: store.location(),
+        player_name,
+        game_dir,
+    })
+}
+
+/// Syncs the launcher's settings with SA-MP's. Returns `None` when syncing is
+/// off or there are no SA-MP settings to sync with.
+#[tauri::command]
+pub fn sync_samp_config(
+    mode: SyncMode,
+    nickname: Option<String>,
+    nickname_modified_at: Option<u64>,
+    game_dir: Option<String>,
+    game_dir_modified_at: Option<u64>,
+) -> Result<Option<SyncResult>, LauncherError> {
+    if mode == SyncMode::Off {
+        return Ok(None);
+    }
+
+    let mut store = match samp_config::open_store() {
+        Some(store) => store,
+        None => {
+            info!("[samp_sync.rs] No SA-MP settings found to sync with");
+            return Ok(None);
+        }
+    };
+
+    sync(
+        store.as_mut(),
+        mode,
+        nickname,
+        nickname_modified_at,
+        game_dir,
+        game_dir_modified_at,
+    )
+    .map(Some)
+}
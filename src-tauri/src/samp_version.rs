@@ -0,0 +1,294 @@
+use md5::compute;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::errors::LauncherError;
+use crate::helpers;
+
+/// Known samp.dll releases, the same ones shipped in samp_clients.7z
+static SAMP_VERSIONS: Lazy<Vec<SampVersionEntry>> = Lazy::new(|| {
+    serde_json::from_str(include_str!("../data/samp_versions.json"))
+        .expect("data/samp_versions.json is invalid")
+});
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SampVersion {
+    R1,
+    R2,
+    R3,
+    #[serde(rename = "R3-1")]
+    R3_1,
+    R4,
+    R5,
+    DL,
+    #[serde(rename = "unknown")]
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SampVersionEntry {
+    pub version: SampVersion,
+    /// Release name, also the directory it's extracted to under `samp/`
+    pub name: String,
+    /// Resource name the frontend uses for this release (e.g. "037R1_samp.dll")
+    pub resource: String,
+    pub md5: Vec<String>,
+}
+
+/// Why a client version was picked for a server
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionSource {
+    /// The user chose a version for this server
+    Override,
+    /// Derived from the version the server reports in its rules
+    Server,
+    /// The server doesn't need a specific version, the user's default is used
+    Fallback,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ClientSelection {
+    pub version: SampVersion,
+    pub resource: String,
+    pub dll_path: String,
+    pub source: SelectionSource,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SampClientInfo {
+    pub version: SampVersion,
+    pub name: Option<String>,
+    pub resource: Option<String>,
+    pub md5: String,
+}
+
+pub fn get_version_entry(version: SampVersion) -> Option<&'static SampVersionEntry> {
+    SAMP_VERSIONS.iter().find(|entry| entry.version == version)
+}
+
+pub fn get_version_by_resource(resource: &str) -> Option<SampVersion> {
+    SAMP_VERSIONS
+        .iter()
+        .find(|entry| entry.resource == resource)
+        .map(|entry| entry.version)
+}
+
+/// Maps the `version` rule a server reports to the client release it needs.
+/// Returns `None` when any client works, which is the case for open.mp servers
+/// (they accept every 0.3.7 and 0.3.DL release) and versions we don't know.
+pub fn get_version_for_server(server_version: &str) -> Option<SampVersion> {
+    let server_version = server_version.trim().to_lowercase();
+
+    if server_version.starts_with("0.3.dl") {
+        return Some(SampVersion::DL);
+    }
+
+    let release = server_version
+        .strip_prefix("0.3.7")?
+        .trim_start_matches(['-', ' ']);
+
+    if release.starts_with("r3-1") || release.starts_with("r3.1") {
+        Some(SampVersion::R3_1)
+    } else if release.starts_with("r2") {
+        Some(SampVersion::R2)
+    } else if release.starts_with("r3") {
+        Some(SampVersion::R3)
+    } else if release.starts_with("r4") {
+        Some(SampVersion::R4)
+    } else if release.starts_with("r5") {
+        Some(SampVersion::R5)
+    } else {
+        // Plain "0.3.7" and "0.3.7-R1"
+        Some(SampVersion::R1)
+    }
+}
+
+/// Picks the client release to launch a server with. A per-server override
+/// always wins, then the version the server asks for, then the user's default.
+pub fn select_client(
+    server_version: Option<&str>,
+    override_version: Option<SampVersion>,
+    fallback_version: Option<SampVersion>,
+) -> Result<ClientSelection, LauncherError> {
+    let (version, source) = if let Some(version) = override_version {
+        (version, SelectionSource::Override)
+    } else if let Some(version) = server_version.and_then(get_version_for_server) {
+        (version, SelectionSource::Server)
+    } else {
+        (
+            fallback_version.unwrap_or(SampVersion::R1),
+            SelectionSource::Fallback,
+        )
+    };
+
+    let entry = get_version_entry(version).ok_or_else(|| no_bundled_dll(version))?;
+
+    Ok(ClientSelection {
+        version,
+        resource: entry.resource.clone(),
+        dll_path: get_bundled_dll_path(version)?.to_string_lossy().to_string(),
+        source,
+    })
+}
+
+fn no_bundled_dll(version: SampVersion) -> LauncherError {
+    LauncherError::NotFound(format!("No bundled samp.dll for {:?}", version))
+}
+
+/// Returns where the launcher keeps its own copy of the given release
+pub fn get_bundled_dll_path(version: SampVersion) -> Result<PathBuf, LauncherError> {
+    let entry = get_version_entry(version).ok_or_else(|| no_bundled_dll(version))?;
+
+    Ok(helpers::get_launcher_data_dir()?
+        .join("samp")
+        .join(&entry.name)
+        .join("samp.dll"))
+}
+
+pub fn identify_samp_dll(buf: &[u8]) -> SampClientInfo {
+    let md5 = format!("{:x}", compute(buf));
+    let entry = SAMP_VERSIONS
+        .iter()
+        .find(|entry| entry.md5.iter().any(|hash| hash.eq_ignore_ascii_case(&md5)));
+
+    SampClientInfo {
+        version: entry.map(|e| e.version).unwrap_or(SampVersion::Unknown),
+        name: entry.map(|e| e.name.clone()),
+        resource: entry.map(|e| e.resource.clone()),
+        md5,
+    }
+}
+
+/// Identifies the samp.dll installed in the given game directory, returns
+/// `None` if there's no samp.dll at all
+pub fn get_installed_samp_version(game_dir: &str) -> Result<Option<SampClientInfo>, LauncherError> {
+    let dll_path = Path::new(game_dir).join("samp.dll");
+    if !dll_path.exists() {
+        return Ok(None);
+    }
+
+    let buf = fs::read(&dll_path).map_err(|e| LauncherError::from_io(e, &dll_path))?;
+    Ok(Some(identify_samp_dll(&buf)))
+}
+
+#[tauri::command]
+pub fn detect_samp_version(game_dir: &str) -> Result<Option<SampClientInfo>, LauncherError> {
+    get_installed_samp_version(game_dir)
+}
+
+#[tauri::command]
+pub fn get_known_samp_versions() -> Vec<SampVersionEntry> {
+    SAMP_VERSIONS.clone()
+}
+
+/// Takes the frontend's resource names (e.g. "037R1_samp.dll") for the
+/// override and fallback versions
+#[tauri::command]
+pub fn select_samp_client(
+    server_version: Option<String>,
+    override_resource: Option<String>,
+    fallback_resource: Option<String>,
+) -> Result<ClientSelection, LauncherError> {
+    select_client(
+        server_version.as_deref(),
+        override_resource
+            .as_deref()
+            .and_then(get_version_by_resource),
+        fallback_resource
+            .as_deref()
+            .and_then(get_version_by_resource),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_server_versions_to_releases() {
+        let cases = [
+            ("0.3.7", Some(SampVersion::R1)),
+            ("0.3.7-R1", Some(SampVersion::R1)),
+            ("0.3.7-R2", Some(SampVersion::R2)),
+            ("0.3.7-R3", Some(SampVersion::R3)),
+            ("0.3.7-R3-1", Some(SampVersion::R3_1)),
+            ("0.3.7 R3.1", Some(SampVersion::R3_1)),
+            ("0.3.7-R4", Some(SampVersion::R4)),
+            ("0.3.7-R5", Some(SampVersion::R5)),
+            (" 0.3.7-r2 ", Some(SampVersion::R2)),
+            ("0.3.DL-R1", Some(SampVersion::DL)),
+            ("0.3.dl", Some(SampVersion::DL)),
+            // open.mp servers and anything else we don't know accept any client
+            ("omp 1.1.0.2612", None),
+            ("0.3z", None),
+            ("", None),
+        ];
+
+        for (server_version, expected) in cases {
+            assert_eq!(
+                get_version_for_server(server_version),
+                expected,
+                "{:?}",
+                server_version
+            );
+        }
+    }
+
+    #[test]
+    fn every_release_is_bundled() {
+        for version in [
+            SampVersion::R1,
+            SampVersion::R2,
+            SampVersion::R3,
+            SampVersion::R3_1,
+            SampVersion::R4,
+            SampVersion::R5,
+            SampVersion::DL,
+        ] {
+            let entry = get_version_entry(version).expect("release should have an entry");
+            assert_eq!(get_version_by_resource(&entry.resource), Some(version));
+        }
+        assert!(get_version_entry(SampVersion::Unknown).is_none());
+    }
+
+    #[test]
+    fn selects_override_then_server_then_fallback() {
+        let selection = select_client(
+            Some("0.3.7-R2"),
+            Some(SampVersion::R4),
+            Some(SampVersion::R3),
+        )
+        .unwrap();
+        assert_eq!(selection.version, SampVersion::R4);
+        assert_eq!(selection.source, SelectionSource::Override);
+
+        let selection = select_client(Some("0.3.7-R2"), None, Some(SampVersion::R3)).unwrap();
+        assert_eq!(selection.version, SampVersion::R2);
+        assert_eq!(selection.source, SelectionSource::Server);
+        assert_eq!(selection.resource, "037R2_samp.dll");
+        assert!(Path::new(&selection.dll_path).ends_with("samp/0.3.7-R2/samp.dll"));
+
+        let selection = select_client(Some("omp 1.1.0.2612"), None, Some(SampVersion::R3)).unwrap();
+        assert_eq!(selection.version, SampVersion::R3);
+        assert_eq!(selection.source, SelectionSource::Fallback);
+
+        let selection = select_client(None, None, None).unwrap();
+        assert_eq!(selection.version, SampVersion::R1);
+        assert_eq!(selection.source, SelectionSource::Fallback);
+    }
+
+    #[test]
+    fn unknown_releases_have_no_bundled_dll() {
+        assert!(matches!(
+            select_client(None, Some(SampVersion::Unknown), None),
+            Err(LauncherError::NotFound(_))
+        ));
+
+        let info = identify_samp_dll(b"not a samp.dll");
+        assert_eq!(info.version, SampVersion::Unknown);
+        assert!(info.name.is_none());
+    }
+}
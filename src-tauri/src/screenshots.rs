@@ -0,0 +1,411 @@
+use image::imageops::FilterType;
+use log::{error, info};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::errors::LauncherError;
+use crate::helpers::{self, JsonStore};
+use crate::installations;
+use crate::playtime::{self, ActiveSession};
+use crate::samp;
+
+/// How often the screenshots directory is checked while a session runs
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Bounding box thumbnails are scaled into, keeping the aspect ratio
+const THUMBNAIL_SIZE: (u32, u32) = (320, 180);
+
+/// Names the client gives screenshots
+static SCREENSHOT_NAME: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)^sa-mp-\d+\.png$").unwrap());
+
+/// Index of the screenshots, its lock also covers the thumbnails
+static SCREENSHOTS_STORE: JsonStore = JsonStore::new("screenshots/screenshots.json");
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Screenshot {
+    pub id: String,
+    pub path: String,
+    /// Scaled down copy in the launcher's data directory, `None` if it
+    /// couldn't be created
+    pub thumbnail: Option<String>,
+    /// When the file was written, in seconds since the Unix epoch
+    pub taken_at: u64,
+    /// Server of the game session the screenshot was taken in, `None` if it
+    /// wasn't taken in one the launcher started
+    pub ip: Option<String>,
+    pub port: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ScreenshotsData {
+    screenshots: Vec<Screenshot>,
+}
+
+fn get_screenshots_dir() -> Result<PathBuf, String> {
+    Ok(helpers::get_launcher_data_dir()?.join("screenshots"))
+}
+
+fn load_screenshots_data() -> Result<ScreenshotsData, LauncherError> {
+    SCREENSHOTS_STORE.load()
+}
+
+fn save_screenshots_data(data: &ScreenshotsData) -> Result<(), LauncherError> {
+    SCREENSHOTS_STORE.save(data)
+}
+
+fn lock() -> Result<std::sync::MutexGuard<'static, ()>, LauncherError> {
+    SCREENSHOTS_STORE.lock()
+}
+
+/// Where the client saves screenshots for a game directory
+fn get_game_screenshots_dir(game_dir: Option<&str>) -> Option<PathBuf> {
+    samp::samp_user_files_dir(game_dir).map(|dir| dir.join("screens"))
+}
+
+fn modified_at(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_secs())
+}
+
+fn create_thumbnail(source: &Path, id: &str) -> Result<PathBuf, LauncherError> {
+    let dir = get_screenshots_dir()?.join("thumbnails");
+    fs::create_dir_all(&dir).map_err(|e| LauncherError::from_io(e, &dir))?;
+
+    let thumbnail = dir.join(format!("{}.png", id));
+    let (width, height) = THUMBNAIL_SIZE;
+    image::open(source)
+        .map_err(|e| LauncherError::CorruptFile(format!("{}: {}", source.display(), e)))?
+        .resize(width, height, FilterType::Triangle)
+        .save(&thumbnail)
+        .map_err(|e| LauncherError::Other(format!("{}: {}", thumbnail.display(), e)))?;
+    Ok(thumbnail)
+}
+
+/// Ids are derived from what identifies a screenshot file, so indexing the same
+/// file twice gives the same id and different files never share one
+fn screenshot_id(path: &str, taken_at: u64) -> String {
+    format!("{:x}", md5::compute(format!("{}\n{}", path, taken_at)))
+}
+
+fn is_indexed(data: &ScreenshotsData, path: &str, taken_at: u64) -> bool {
+    data.screenshots
+        .iter()
+        .any(|screenshot| screenshot.path == path && screenshot.taken_at == taken_at)
+}
+
+/// Adds the screenshots in `dir` that aren't indexed yet. Ones written during
+/// `session` are linked to its server. Files replaced since they were indexed
+/// (the client reuses names once old screenshots are deleted) are indexed
+/// anew. Thumbnails are created without holding the index lock.
+fn index_dir(dir: &Path, session: Option<&ActiveSession>) -> Result<usize, LauncherError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(0),
+    };
+
+    let files: Vec<(PathBuf, u64)> = entries
+        .flatten()
+        .filter(|entry| SCREENSHOT_NAME.is_match(&entry.file_name().to_string_lossy()))
+        .filter_map(|entry| {
+            let path = entry.path();
+            modified_at(&path).map(|taken_at| (path, taken_at))
+        })
+        .collect();
+
+    let new_files: Vec<(PathBuf, u64)> = {
+        let _guard = lock()?;
+        let data = load_screenshots_data()?;
+        files
+            .into_iter()
+            .filter(|(path, taken_at)| !is_indexed(&data, &path.to_string_lossy(), *taken_at))
+            .collect()
+    };
+    if new_files.is_empty() {
+        return Ok(0);
+    }
+
+    let screenshots: Vec<Screenshot> = new_files
+        .into_iter()
+        .map(|(path, taken_at)| {
+            let id = screenshot_id(&path.to_string_lossy(), taken_at);
+            let thumbnail = match create_thumbnail(&path, &id) {
+                Ok(thumbnail) => Some(thumbnail.to_string_lossy().to_string()),
+                Err(e) => {
+                    error!("[screenshots.rs] Creating thumbnail failed: {}", e);
+                    None
+                }
+            };
+            let session = session.filter(|session| taken_at >= session.started_at);
+
+            Screenshot {
+                id,
+                path: path.to_string_lossy().to_string(),
+                thumbnail,
+                taken_at,
+                ip: session.map(|session| session.ip.clone()),
+                port: session.map(|session| session.port),
+            }
+        })
+        .collect();
+
+    let _guard = lock()?;
+    let mut data = load_screenshots_data()?;
+    let mut added = 0;
+
+    for screenshot in screenshots {
+        // Indexed by someone else in the meantime, under the same id and
+        // thumbnail
+        if is_indexed(&data, &screenshot.path, screenshot.taken_at) {
+            continue;
+        }
+
+        for stale in data
+            .screenshots
+            .iter()
+            .filter(|stale| stale.path == screenshot.path)
+        {
+            if let Some(thumbnail) = &stale.thumbnail {
+                let _ = fs::remove_file(thumbnail);
+            }
+        }
+        data.screenshots
+            .retain(|stale| stale.path != screenshot.path);
+
+        data.screenshots.push(screenshot);
+        added += 1;
+    }
+
+    if added > 0 {
+        save_screenshots_data(&data)?;
+        info!(
+            "[screenshots.rs] Indexed {} new screenshot(s) in {}",
+            added,
+            dir.display()
+        );
+    }
+    Ok(added)
+}
+
+/// Keeps indexing the game's screenshots directory while the session runs,
+/// so new screenshots are linked to the session's server
+pub fn watch_session(session: ActiveSession) {
+    let dir = match get_game_screenshots_dir(Some(&session.game_dir)) {
+        Some(dir) => dir,
+        None => return,
+    };
+
+    thread::spawn(move || {
+        let mut session = session;
+        loop {
+            // Pick up the session's new server if the game was moved to one
+            let current = playtime::get_active_sessions()
+                .into_iter()
+                .find(|active| active.pid == session.pid);
+            let active = current.is_some();
+            if let Some(current) = current {
+                session = current;
+            }
+
+            // Once more after the session ended, for screenshots taken right
+            // before quitting
+            if let Err(e) = index_dir(&dir, Some(&session)) {
+                error!("[screenshots.rs] Indexing {} failed: {}", dir.display(), e);
+            }
+
+            if !active {
+                break;
+            }
+            thread::sleep(WATCH_INTERVAL);
+        }
+    });
+}
+
+/// Indexes the screenshots of a running session right away, linking them to
+/// the server it's currently on
+pub fn index_session(session: &ActiveSession) {
+    if let Some(dir) = get_game_screenshots_dir(Some(&session.game_dir)) {
+        if let Err(e) = index_dir(&dir, Some(session)) {
+            error!("[screenshots.rs] Indexing {} failed: {}", dir.display(), e);
+        }
+    }
+}
+
+/// Indexes the screenshots in the user's own documents and those of every
+/// registered installation. Ones in the directory of a running session are
+/// linked to its server, the rest were taken outside of the launcher's
+/// sessions.
+fn index_known_dirs() {
+    let mut game_dirs: Vec<Option<String>> = vec![None];
+    if let Ok(list) = installations::list_installations() {
+        game_dirs.extend(list.into_iter().map(|installation| Some(installation.path)));
+    }
+
+    let sessions = playtime::get_active_sessions();
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for game_dir in game_dirs {
+        let dir = match get_game_screenshots_dir(game_dir.as_deref()) {
+            Some(dir) => dir,
+            None => continue,
+        };
+        // Installations without their own user files share the documents one
+        if dirs.contains(&dir) {
+            continue;
+        }
+
+        let session = sessions.iter().find(|session| {
+            get_game_screenshots_dir(Some(&session.game_dir)).as_ref() == Some(&dir)
+        });
+        if let Err(e) = index_dir(&dir, session) {
+            error!("[screenshots.rs] Indexing {} failed: {}", dir.display(), e);
+        }
+        dirs.push(dir);
+    }
+}
+
+/// Indexed screenshots, newest first, optionally only those of one server.
+/// Screenshots whose files are gone are dropped from the index.
+#[tauri::command]
+pub async fn get_screenshots(
+    ip: Option<String>,
+    port: Option<i32>,
+) -> Result<Vec<Screenshot>, LauncherError> {
+    tokio::task::spawn_blocking(move || list_screenshots(ip, port))
+        .await
+        .map_err(|e| LauncherError::Other(e.to_string()))?
+}
+
+fn list_screenshots(
+    ip: Option<String>,
+    port: Option<i32>,
+) -> Result<Vec<Screenshot>, LauncherError> {
+    index_known_dirs();
+
+    let _guard = lock()?;
+    let mut data = load_screenshots_data()?;
+
+    let count = data.screenshots.len();
+    data.screenshots.retain(|screenshot| {
+        let exists = Path::new(&screenshot.path).is_file();
+        if !exists {
+            if let Some(thumbnail) = &screenshot.thumbnail {
+                let _ = fs::remove_file(thumbnail);
+            }
+        }
+        exists
+    });
+    if data.screenshots.len() != count {
+        save_screenshots_data(&data)?;
+    }
+
+    let mut screenshots: Vec<Screenshot> = data
+        .screenshots
+        .into_iter()
+        .filter(|screenshot| ip.is_none() || screenshot.ip == ip)
+        .filter(|screenshot| port.is_none() || screenshot.port == port)
+        .collect();
+    screenshots.sort_by_key(|screenshot| std::cmp::Reverse(screenshot.taken_at));
+    Ok(screenshots)
+}
+
+/// Deletes a screenshot from the disk, along with its thumbnail
+#[tauri::command]
+pub fn delete_screenshot(id: String) -> Result<(), LauncherError> {
+    let _guard = lock()?;
+    let mut data = load_screenshots_data()?;
+
+    let index = data
+        .screenshots
+        .iter()
+        .position(|screenshot| screenshot.id == id)
+        .ok_or_else(|| LauncherError::NotFound(format!("No screenshot with id {}", id)))?;
+    let screenshot = data.screenshots.remove(index);
+
+    if let Err(e) = fs::remove_file(&screenshot.path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            return Err(LauncherError::from_io(e, &screenshot.path));
+        }
+    }
+    if let Some(thumbnail) = &screenshot.thumbnail {
+        let _ = fs::remove_file(thumbnail);
+    }
+
+    save_screenshots_data(&data)
+}
+
+/// Copies screenshots into `dest`, returns the paths of the copies. Names
+/// taken in `dest` get the screenshot id appended instead of being replaced.
+#[tauri::command]
+pub async fn export_screenshots(
+    ids: Vec<String>,
+    dest: String,
+) -> Result<Vec<String>, LauncherError> {
+    tokio::task::spawn_blocking(move || copy_screenshots(ids, dest))
+        .await
+        .map_err(|e| LauncherError::Other(e.to_string()))?
+}
+
+fn copy_screenshots(ids: Vec<String>, dest: String) -> Result<Vec<String>, LauncherError> {
+    let _guard = lock()?;
+    let data = load_screenshots_data()?;
+    let dest = PathBuf::from(dest);
+    fs::create_dir_all(&dest).map_err(|e| LauncherError::from_io(e, &dest))?;
+
+    let mut exported = Vec::new();
+    for id in ids {
+        let screenshot = data
+            .screenshots
+            .iter()
+            .find(|screenshot| screenshot.id == id)
+            .ok_or_else(|| LauncherError::NotFound(format!("No screenshot with id {}", id)))?;
+
+        let source = Path::new(&screenshot.path);
+        let stem = source
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| id.clone());
+        let mut target = dest.join(format!("{}.png", stem));
+        if target.exists() {
+            target = dest.join(format!("{}-{}.png", stem, id));
+        }
+
+        fs::copy(source, &target).map_err(|e| LauncherError::from_io(e, source))?;
+        exported.push(target.to_string_lossy().to_string());
+    }
+
+    Ok(exported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_depend_on_path_and_time_only() {
+        let path = "/home/user/Documents/GTA San Andreas User Files/SAMP/screens/sa-mp-000.png";
+        assert_eq!(
+            screenshot_id(path, 1700000000),
+            screenshot_id(path, 1700000000)
+        );
+        // The client reuses names once old screenshots are deleted
+        assert_ne!(
+            screenshot_id(path, 1700000000),
+            screenshot_id(path, 1700000060)
+        );
+        assert_ne!(
+            screenshot_id(path, 1700000000),
+            screenshot_id(&path.replace("000", "001"), 1700000000)
+        );
+        // Usable as a file name for the thumbnail
+        assert!(screenshot_id(path, 0)
+            .chars()
+            .all(|c| c.is_ascii_hexdigit()));
+    }
+}
@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+
+/// A run of text drawn in a single color
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct TextSpan {
+    pub text: String,
+    /// `RRGGBB` (upper case) set by the `{RRGGBB}` tag in front of the text,
+    /// `None` for text before the first tag, drawn in the default color
+    pub color: Option<String>,
+}
+
+/// A string as SA-MP servers and the client write it, split into colored spans
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct ColoredText {
+    /// The text without color tags and control characters, trimmed. Meant for
+    /// searching and sorting.
+    pub plain: String,
+    pub spans: Vec<TextSpan>,
+}
+
+/// The `RRGGBB` of a `{RRGGBB}` tag at the start of `text`
+fn color_tag(text: &str) -> Option<&str> {
+    let bytes = text.as_bytes();
+    if bytes.len() >= 8
+        && bytes[0] == b'{'
+        && bytes[7] == b'}'
+        && bytes[1..7].iter().all(u8::is_ascii_hexdigit)
+    {
+        Some(&text[1..7])
+    } else {
+        None
+    }
+}
+
+/// Splits a string at its `{RRGGBB}` color tags. Tabs and line breaks become
+/// spaces, other control characters are dropped. Braces that aren't part of
+/// a tag are kept as text.
+pub fn parse_spans(text: &str) -> Vec<TextSpan> {
+    let mut spans = Vec::new();
+    let mut color: Option<String> = None;
+    let mut current = String::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        if let Some(tag) = color_tag(rest) {
+            if !current.is_empty() {
+                spans.push(TextSpan {
+                    text: std::mem::take(&mut current),
+                    color: color.clone(),
+                });
+            }
+            color = Some(tag.to_ascii_uppercase());
+            rest = &rest[8..];
+            continue;
+        }
+
+        if c == '\t' || c == '\n' {
+            current.push(' ');
+        } else if !c.is_control() {
+            current.push(c);
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+
+    if !current.is_empty() {
+        spans.push(TextSpan {
+            text: current,
+            color,
+        });
+    }
+    spans
+}
+
+pub fn parse(text: &str) -> ColoredText {
+    let spans = parse_spans(text);
+    ColoredText {
+        plain: spans
+            .iter()
+            .map(|span| span.text.as_str())
+            .collect::<String>()
+            .trim()
+            .to_string(),
+        spans,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(text: &str, color: Option<&str>) -> TextSpan {
+        TextSpan {
+            text: text.to_string(),
+            color: color.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn tags_at_start_and_end() {
+        assert_eq!(
+            parse_spans("{FF0000}Red {00FF00}Green{0000FF}"),
+            [span("Red ", Some("FF0000")), span("Green", Some("00FF00"))]
+        );
+        assert_eq!(parse_spans("{FFFFFF}"), []);
+        assert_eq!(parse("{FFFFFF}"), ColoredText::default());
+    }
+
+    #[test]
+    fn text_before_first_tag_has_no_color() {
+        assert_eq!(
+            parse_spans("Plain {ff8800}orange"),
+            [span("Plain ", None), span("orange", Some("FF8800"))]
+        );
+    }
+
+    #[test]
+    fn lowercase_hex_is_upper_cased() {
+        assert_eq!(parse_spans("{abcdef}x"), [span("x", Some("ABCDEF"))]);
+    }
+
+    #[test]
+    fn broken_tags_are_kept_as_text() {
+        for text in [
+            "{FFF}short",
+            "{GGGGGG}not hex",
+            "{FF0000",
+            "{FF00000}",
+            "{}",
+            "{",
+        ] {
+            assert_eq!(parse_spans(text), [span(text, None)], "{:?}", text);
+        }
+        assert_eq!(
+            parse_spans("{FFF}{FF0000}red"),
+            [span("{FFF}", None), span("red", Some("FF0000"))]
+        );
+    }
+
+    #[test]
+    fn multibyte_text_around_tags() {
+        let text = parse("Привет{00FF00}мир 世界{FF0000}ü");
+        assert_eq!(
+            text.spans,
+            [
+                span("Привет", None),
+                span("мир 世界", Some("00FF00")),
+                span("ü", Some("FF0000"))
+            ]
+        );
+        assert_eq!(text.plain, "Приветмир 世界ü");
+
+        // A brace right before a multibyte character must not be taken for a
+        // tag, nor be cut in the middle of the character
+        assert_eq!(parse_spans("{Привет}"), [span("{Привет}", None)]);
+        assert_eq!(parse_spans("{ABCDEé}"), [span("{ABCDEé}", None)]);
+    }
+
+    #[test]
+    fn control_characters() {
+        let text = parse(" {FF0000}a\tb\nc\u{1}d ");
+        assert_eq!(text.plain, "a b cd");
+        assert_eq!(
+            text.spans,
+            [span(" ", None), span("a b cd ", Some("FF0000"))]
+        );
+    }
+}
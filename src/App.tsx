@@ -20,6 +20,7 @@ import SettingsModal from "./containers/Settings";
 import WindowTitleBar from "./containers/WindowTitleBar";
 import i18n from "./locales";
 import { useGenericPersistentState } from "./states/genericStates";
+import { useSettings } from "./states/settings";
 import { useTheme } from "./states/theme";
 import { debounce } from "./utils/debounce";
 import {
@@ -27,6 +28,7 @@ import {
   fetchServers,
   fetchUpdateInfo,
   generateLanguageFilters,
+  syncSampConfig,
 } from "./utils/helpers";
 import { sc } from "./utils/sizeScaler";
 // import MouseFollower from "./components/MouseFollower";
@@ -36,6 +38,7 @@ const App = () => {
   const [maximized, setMaximized] = useState(false);
   const { theme } = useTheme();
   const { language } = useGenericPersistentState();
+  const { nickName, gtasaPath, sampSyncMode } = useSettings();
   const windowSize = useRef<PhysicalSize>();
   const mainWindowSize = useRef<LogicalSize>();
 
@@ -70,6 +73,15 @@ const App = () => {
     i18n.changeLanguage(language);
   }, [language]);
 
+  const debouncedSyncSampConfig = useCallback(
+    debounce(() => syncSampConfig(), 2000),
+    []
+  );
+
+  useEffect(() => {
+    debouncedSyncSampConfig();
+  }, [nickName, gtasaPath, sampSyncMode]);
+
   useEffect(() => {
     let killResizeListener: (() => void) | null = null;
 
@@ -12,6 +12,7 @@ import i18n from "../../locales";
 import { useGenericPersistentState } from "../../states/genericStates";
 import { useTheme } from "../../states/theme";
 import { formatBytes } from "../../utils/helpers";
+import { LauncherError } from "../../utils/types";
 import { sc } from "../../utils/sizeScaler";
 import { getUpdateInfo } from "../../api/apis";
 import { UpdateInfo, useAppState } from "../../states/app";
@@ -21,6 +22,9 @@ const LoadingScreen = (props: { onEnd: () => void }) => {
   const { language } = useGenericPersistentState();
   const [downloading, setDownloading] = useState(false);
   const downloadedSize = useRef(0);
+  const [extractError, setExtractError] = useState<LauncherError | undefined>(
+    undefined
+  );
   const [downloadInfo, setDownloadInfo] = useState<{
     size: number;
     total: number;
@@ -58,12 +62,20 @@ const LoadingScreen = (props: { onEnd: () => void }) => {
             percent: (downloadedSize.current * 100) / total,
           });
           if (downloadedSize.current >= total) {
-            await invoke_rpc("extract_7z", {
+            const response = await invoke_rpc("extract_7z", {
               path: archive,
               output_path: samp,
             });
+            const { error } = JSON.parse(response) as {
+              error?: LauncherError;
+            };
             downloadedSize.current = 0;
             setDownloading(false);
+            if (error) {
+              console.log("extract error", error);
+              setExtractError(error);
+              return;
+            }
             processFileChecksums(false);
           }
           // console.log(`Downloaded ${downloadedSize.current} of ${total} bytes`);
@@ -352,6 +364,20 @@ const LoadingScreen = (props: { onEnd: () => void }) => {
             {progressBar}
           </>
         ) : null}
+        {extractError ? (
+          <Text
+            semibold
+            color={theme.textPrimary}
+            style={{
+              textAlign: "left",
+              width: "100%",
+              fontSize: sc(15),
+            }}
+          >
+            Extracting resources failed ({extractError.code}):{" "}
+            {extractError.details}
+          </Text>
+        ) : null}
       </View>
       <div
         data-tauri-drag-region
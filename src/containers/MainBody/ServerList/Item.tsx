@@ -151,7 +151,17 @@ const ServerItem = memo((props: IProps) => {
             ]}
           >
             <Text style={{ fontSize: sc(17) }} color={theme.textPrimary}>
-              {server.hostname}
+              {server.hostnameSpans && server.hostnameSpans.length
+                ? server.hostnameSpans.map((span, i) => (
+                    <Text
+                      key={i}
+                      style={{ fontSize: sc(17) }}
+                      color={span.color ? `#${span.color}` : theme.textPrimary}
+                    >
+                      {span.text}
+                    </Text>
+                  ))
+                : server.hostname}
             </Text>
           </View>
           <View
@@ -5,7 +5,11 @@ import Text from "../../../components/Text";
 import { useGenericPersistentState } from "../../../states/genericStates";
 import { useTheme } from "../../../states/theme";
 import { sc } from "../../../utils/sizeScaler";
-import { exportFavoriteListFile, importFavoriteListFile } from "../../../utils/game";
+import {
+  exportFavoriteListFile,
+  exportFavoriteListToSAMP,
+  importFavoriteListFile,
+} from "../../../utils/game";
 
 const Advanced = () => {
   const { theme } = useTheme();
@@ -73,6 +77,21 @@ const Advanced = () => {
             {t("settings_import_favorite_list_file")}
           </Text>
         </TouchableOpacity>
+
+        <TouchableOpacity 
+          style={[
+            styles.importButton, 
+            { 
+              backgroundColor: `${theme.primary}BB`,
+              borderColor: theme.primary
+            }
+          ]} 
+          onPress={() => exportFavoriteListToSAMP()}
+        >
+          <Text semibold color={"#FFFFFF"} size={2}>
+            {t("settings_export_samp_favorite_list")}
+          </Text>
+        </TouchableOpacity>
       </View>
       <View style={styles.pathInputContainer}></View>
     </View>
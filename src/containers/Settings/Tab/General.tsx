@@ -4,6 +4,7 @@ import { t } from "i18next";
 import { StyleSheet, TextInput, TouchableOpacity, View } from "react-native";
 import Text from "../../../components/Text";
 import { useAppState } from "../../../states/app";
+import { useMessageBox } from "../../../states/messageModal";
 import { usePersistentServers } from "../../../states/servers";
 import { useSettings } from "../../../states/settings";
 import { useTheme } from "../../../states/theme";
@@ -11,13 +12,27 @@ import { checkDirectoryValidity } from "../../../utils/game";
 import { Log } from "../../../utils/logger";
 import { stateStorage } from "../../../utils/stateStorage";
 import { sc } from "../../../utils/sizeScaler";
-import { Server } from "../../../utils/types";
+import {
+  InstallCandidate,
+  LauncherError,
+  SAMPUserData,
+  Server,
+} from "../../../utils/types";
 
 const General = () => {
   const { hostOS } = useAppState();
   const { theme } = useTheme();
-  const { gtasaPath, setGTASAPath, setNickName } = useSettings();
+  const {
+    gtasaPath,
+    setGTASAPath,
+    setNickName,
+    sampSyncMode,
+    setSampSyncMode,
+    launchPolicy,
+    setLaunchPolicy,
+  } = useSettings();
   const { updateInfo, version } = useAppState();
+  const { showMessageBox, hideMessageBox } = useMessageBox();
 
   const selectPath = async () => {
     const selected: string = (await open({
@@ -36,7 +51,20 @@ const General = () => {
 
   const importDataFromSAMP = async () => {
     try {
-      const path: string = await invoke("get_gtasa_path_from_samp");
+      let path: string = await invoke("get_gtasa_path_from_samp");
+      if (!path.length) {
+        // SA-MP isn't set up (e.g. outside of Wine), look for the game instead,
+        // preferring installations the client can hook into
+        const candidates = await invoke<InstallCandidate[]>(
+          "discover_gta_installations",
+          {}
+        );
+        const candidate =
+          candidates.find((candidate) => candidate.exe?.samp === "compatible") ??
+          candidates[0];
+        path = candidate?.path ?? "";
+      }
+
       if (path.length) {
         const newPath = path.replace(/\\/g, "/");
         const isDirValid = await checkDirectoryValidity(newPath);
@@ -53,59 +81,61 @@ const General = () => {
   };
 
   const importFavListFromSAMP = async () => {
-    await invoke("get_samp_favorite_list").then((a) => {
-      const userData: {
-        file_id: string;
-        file_version: number;
-        server_count: number;
-        favorite_servers: {
-          ip: string;
-          port: number;
-          name: string;
-          password: string;
-          rcon: string;
-        }[];
-      } = JSON.parse(a as string);
-
-      if (userData.file_id === "SAMP") {
-        const { addToFavorites } = usePersistentServers.getState();
-        userData.favorite_servers.forEach((server) => {
-          const serverInfo: Server = {
-            ip: "",
-            port: 0,
-            hostname: "No information",
-            playerCount: 0,
-            maxPlayers: 0,
-            gameMode: "-",
-            language: "-",
-            hasPassword: false,
-            version: "-",
-            usingOmp: false,
-            partner: false,
-            ping: 0,
-            password: "",
-            players: [],
-            rules: {} as Server["rules"],
-          };
-
-          if (server.ip.length) {
-            serverInfo.ip = server.ip;
-            serverInfo.port = server.port;
-            if (server.name.includes("(Retrieving info...)")) {
-              serverInfo.hostname += ` (${serverInfo.ip}:${serverInfo.port})`;
-            } else {
-              serverInfo.hostname = server.name;
-            }
+    try {
+      const userData = await invoke<SAMPUserData>("get_samp_favorite_list");
+      const { addToFavorites } = usePersistentServers.getState();
+      userData.favorite_servers.forEach((server) => {
+        const serverInfo: Server = {
+          ip: "",
+          port: 0,
+          hostname: "No information",
+          playerCount: 0,
+          maxPlayers: 0,
+          gameMode: "-",
+          language: "-",
+          hasPassword: false,
+          version: "-",
+          usingOmp: false,
+          partner: false,
+          ping: 0,
+          password: "",
+          players: [],
+          rules: {} as Server["rules"],
+        };
 
-            if (server.password.length) {
-              serverInfo.password = server.password;
-            }
+        if (server.ip.length) {
+          serverInfo.ip = server.ip;
+          serverInfo.port = server.port;
+          if (server.name.includes("(Retrieving info...)")) {
+            serverInfo.hostname += ` (${serverInfo.ip}:${serverInfo.port})`;
+          } else {
+            serverInfo.hostname = server.name;
+          }
 
-            addToFavorites(serverInfo);
+          if (server.password.length) {
+            serverInfo.password = server.password;
           }
-        });
-      }
-    });
+
+          addToFavorites(serverInfo);
+        }
+      });
+    } catch (e) {
+      Log.debug(e);
+      showMessageBox({
+        title: t("import_failed_title"),
+        description: t(
+          (e as LauncherError)?.code === "corrupt_file"
+            ? "import_samp_corrupt_userdata_description"
+            : "import_samp_no_userdata_description"
+        ),
+        buttons: [
+          {
+            title: "OK",
+            onPress: () => hideMessageBox(),
+          },
+        ],
+      });
+    }
   };
 
   return (
@@ -161,6 +191,50 @@ const General = () => {
           {t("settings_import_nickname_gta_path_from_samp")}
         </Text>
       </TouchableOpacity>
+      <TouchableOpacity
+        style={[
+          styles.importButton,
+          {
+            backgroundColor: `${theme.primary}BB`,
+            borderColor: theme.textSecondary,
+          },
+        ]}
+        onPress={() =>
+          setSampSyncMode(
+            sampSyncMode === "off"
+              ? "one_way"
+              : sampSyncMode === "one_way"
+              ? "two_way"
+              : "off"
+          )
+        }
+      >
+        <Text semibold color={"#FFFFFF"} size={2}>
+          {t(`settings_samp_sync_${sampSyncMode}`)}
+        </Text>
+      </TouchableOpacity>
+      <TouchableOpacity
+        style={[
+          styles.importButton,
+          {
+            backgroundColor: `${theme.primary}BB`,
+            borderColor: theme.textSecondary,
+          },
+        ]}
+        onPress={() =>
+          setLaunchPolicy(
+            launchPolicy === "ask"
+              ? "connect"
+              : launchPolicy === "connect"
+              ? "refuse"
+              : "ask"
+          )
+        }
+      >
+        <Text semibold color={"#FFFFFF"} size={2}>
+          {t(`settings_launch_policy_${launchPolicy}`)}
+        </Text>
+      </TouchableOpacity>
       <TouchableOpacity
         style={[
           styles.importButton,
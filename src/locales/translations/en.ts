@@ -53,7 +53,21 @@ export default {
   browse: "BROWSE",
   settings_import_nickname_gta_path_from_samp:
     "Import nickname and gtasa path from SA-MP settings",
+  settings_samp_sync_off: "Sync nickname and GTA path with SA-MP: Off",
+  settings_samp_sync_one_way:
+    "Sync nickname and GTA path with SA-MP: Launcher to SA-MP",
+  settings_samp_sync_two_way:
+    "Sync nickname and GTA path with SA-MP: Both ways",
+  settings_launch_policy_connect:
+    "When the game is already running: Connect it to the new server",
+  settings_launch_policy_ask: "When the game is already running: Ask me",
+  settings_launch_policy_refuse:
+    "When the game is already running: Don't launch",
   settings_import_samp_favorite_list: "Import favorite list from SA-MP data",
+  import_samp_no_userdata_description:
+    "Couldn't find SA-MP's favorite list (USERDATA.DAT).",
+  import_samp_corrupt_userdata_description:
+    "SA-MP's favorite list (USERDATA.DAT) is damaged and can't be read.",
   settings_reset_application_data:
     "Reset application data (clears settings and lists)",
   settings_new_update_available: "⚠ New Update Available. Click to Download! ⚠",
@@ -103,6 +117,7 @@ export default {
     "(Requires restarting the game to take action)",
   settings_export_favorite_list_file: "Export favorites list to file",
   settings_import_favorite_list_file: "Import favorites list from file",
+  settings_export_samp_favorite_list: "Export favorites list to SA-MP (USERDATA.DAT)",
   export_no_servers_description: "You don't have any favourite servers to export.",
   export_successful_title: "Export Complete",
   export_successful_description: "Server list exported successfully.",
@@ -116,4 +131,16 @@ export default {
   gta_path: "GTA Path",
   optional: "optional",
   server_join_prompt_gtapath_input_placeholder: "Use global GTA path or set server-specific path",
+  game_already_running_title: "Game is already running!",
+  game_already_running_description:
+    'GTA: San Andreas is already running from this directory:\n  - "{{ path }}"\nClose it first, or connect the running game to this server instead.',
+  game_already_running_refused_description:
+    'GTA: San Andreas is already running from this directory:\n  - "{{ path }}"\nClose it before joining another server.',
+  connect_running_game: "Connect",
+  connect_running_game_failed_title: "Connecting failed!",
+  connect_running_game_failed_description:
+    'The game running from this directory could not be connected to the server:\n  - "{{ path }}"\nMake sure it was started by the launcher, or close it and join again.',
+  unsupported_gta_exe_title: "Unsupported game version!",
+  unsupported_gta_exe_description:
+    'The gta_sa.exe in this directory is not supported:\n  - "{{ path }}"\nSA-MP and open.mp only work with GTA: San Andreas version 1.0, please downgrade your game.',
 };
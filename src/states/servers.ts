@@ -33,6 +33,11 @@ interface ServersPersistentState {
     version: SAMPDLLVersions | undefined,
     gtasaPath: string | undefined
   ) => void;
+  setServerInstallation: (
+    server: Server,
+    installationId: string | undefined
+  ) => void;
+  setServerEncoding: (server: Server, encoding: string | undefined) => void;
   getServerSettings: (server: Server) => PerServerSettings | undefined;
 }
 
@@ -187,6 +192,8 @@ const usePersistentServers = create<ServersPersistentState>()(
               nickname,
               sampVersion: version,
               gtasaPath,
+              installationId: list[index].installationId,
+              encoding: list[index].encoding,
             };
           } else {
             list.push({
@@ -197,6 +204,42 @@ const usePersistentServers = create<ServersPersistentState>()(
             });
           }
 
+          return { perServerSettings: list };
+        }),
+      setServerInstallation: (server, installationId) =>
+        set(() => {
+          const list = [...get().perServerSettings];
+
+          const index = list.findIndex(
+            (srv) => srv.ipPort === `${server.ip}:${server.port}`
+          );
+          if (index !== -1) {
+            list[index] = { ...list[index], installationId };
+          } else {
+            list.push({
+              ipPort: `${server.ip}:${server.port}`,
+              installationId,
+            });
+          }
+
+          return { perServerSettings: list };
+        }),
+      setServerEncoding: (server, encoding) =>
+        set(() => {
+          const list = [...get().perServerSettings];
+
+          const index = list.findIndex(
+            (srv) => srv.ipPort === `${server.ip}:${server.port}`
+          );
+          if (index !== -1) {
+            list[index] = { ...list[index], encoding };
+          } else {
+            list.push({
+              ipPort: `${server.ip}:${server.port}`,
+              encoding,
+            });
+          }
+
           return { perServerSettings: list };
         }),
       getServerSettings: (server) => {
@@ -1,16 +1,23 @@
 import { create } from "zustand";
 import { createJSONStorage, persist } from "zustand/middleware";
 import { stateStorage } from "../utils/stateStorage";
-import { SAMPDLLVersions } from "../utils/types";
+import { LaunchPolicy, SAMPDLLVersions, SampSyncMode } from "../utils/types";
 
 interface SettingsPersistentState {
   nickName: string;
   gtasaPath: string;
   sampVersion: SAMPDLLVersions;
   dataMerged: boolean;
+  launchPolicy: LaunchPolicy;
+  sampSyncMode: SampSyncMode;
+  // Seconds since epoch, used to resolve sync conflicts with SA-MP's settings
+  nickNameModifiedAt?: number;
+  gtasaPathModifiedAt?: number;
   setNickName: (name: string) => void;
   setGTASAPath: (path: string) => void;
   setSampVersion: (version: SAMPDLLVersions) => void;
+  setLaunchPolicy: (policy: LaunchPolicy) => void;
+  setSampSyncMode: (mode: SampSyncMode) => void;
 }
 
 const useSettings = create<SettingsPersistentState>()(
@@ -20,9 +27,21 @@ const useSettings = create<SettingsPersistentState>()(
       gtasaPath: "",
       sampVersion: "custom",
       dataMerged: false,
-      setNickName: (name) => set({ nickName: name }),
-      setGTASAPath: (path) => set({ gtasaPath: path }),
+      launchPolicy: "ask",
+      sampSyncMode: "off",
+      setNickName: (name) =>
+        set({
+          nickName: name,
+          nickNameModifiedAt: Math.floor(Date.now() / 1000),
+        }),
+      setGTASAPath: (path) =>
+        set({
+          gtasaPath: path,
+          gtasaPathModifiedAt: Math.floor(Date.now() / 1000),
+        }),
       setSampVersion: (version) => set({ sampVersion: version }),
+      setLaunchPolicy: (policy) => set({ launchPolicy: policy }),
+      setSampSyncMode: (mode) => set({ sampSyncMode: mode }),
     }),
     {
       name: "settings-storage",
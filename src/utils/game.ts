@@ -1,4 +1,4 @@
-import { fs, invoke, path, process, shell } from "@tauri-apps/api";
+import { fs, invoke, path, shell } from "@tauri-apps/api";
 import { open, save } from "@tauri-apps/api/dialog";
 import { exists, readTextFile, writeTextFile } from "@tauri-apps/api/fs";
 import { t } from "i18next";
@@ -19,30 +19,87 @@ import { useSettingsModal } from "../states/settingsModal";
 import { fetchServers, getIpAddress } from "../utils/helpers";
 import { Log } from "./logger";
 import { sc } from "./sizeScaler";
-import { Server } from "./types";
-
-export const getGTAPathForServer = (server: Server): string => {
+import {
+  Installation,
+  LauncherError,
+  LaunchOutcome,
+  SAMPDLLVersions,
+  Server,
+} from "./types";
+
+export const getGTAPathForServer = async (server: Server): Promise<string> => {
   const { getServerSettings } = usePersistentServers.getState();
   const { gtasaPath: globalGtasaPath } = useSettings.getState();
   
   const serverSettings = getServerSettings(server);
+  if (serverSettings?.installationId) {
+    try {
+      const installation = await invoke<Installation>(
+        "get_installation_by_id",
+        { id: serverSettings.installationId }
+      );
+      return installation.path;
+    } catch (e) {
+      // The installation was removed, fall back to the configured paths
+      Log.debug(e);
+    }
+  }
   return serverSettings?.gtasaPath || globalGtasaPath;
 };
 
-export const copySharedFilesIntoGameFolder = async () => {
-  const { gtasaPath } = useSettings.getState();
+export const getSampVersionForServer = async (
+  server: Server
+): Promise<SAMPDLLVersions> => {
+  const { getServerSettings } = usePersistentServers.getState();
+  const { sampVersion: globalSampVersion } = useSettings.getState();
+
+  const overrideVersion = getServerSettings(server)?.sampVersion;
+  // "custom" means the samp.dll already in the game folder is used as is
+  if (
+    overrideVersion === "custom" ||
+    (overrideVersion === undefined && globalSampVersion === "custom")
+  ) {
+    return "custom";
+  }
+
+  try {
+    const selection: { resource: SAMPDLLVersions } = await invoke(
+      "select_samp_client",
+      {
+        serverVersion: server.rules?.version,
+        overrideResource: overrideVersion,
+        fallbackResource: globalSampVersion,
+      }
+    );
+    return selection.resource;
+  } catch (e) {
+    Log.debug(e);
+    return overrideVersion ?? globalSampVersion;
+  }
+};
+
+export const copySharedFilesIntoGameFolder = async (gtasaPath: string) => {
   const dir = await path.appLocalDataDir();
   const shared = await path.join(dir, "samp", "shared");
-  await invoke_rpc("copy_files_to_gtasa", {
+  const response = await invoke_rpc("copy_files_to_gtasa", {
     src: shared,
     gtasa_dir: gtasaPath,
-  }).then((e) => {
-    throw e;
   });
+  const { error } = JSON.parse(response) as { error?: LauncherError };
+  if (error) {
+    throw error;
+  }
 };
 
-const isFileAvailableinGTASADir = async (file: ResourceInfo) => {
-  const { gtasaPath } = useSettings.getState();
+export const isAdminRequiredError = (e: unknown) => {
+  const code = (e as LauncherError | undefined)?.code;
+  return code === "permission_denied" || code === "elevation_required";
+};
+
+const isFileAvailableinGTASADir = async (
+  file: ResourceInfo,
+  gtasaPath: string
+) => {
   const tempPath = await path.join(
     file.path.replace("samp/shared/", ""),
     file.name
@@ -52,14 +109,14 @@ const isFileAvailableinGTASADir = async (file: ResourceInfo) => {
   return check;
 };
 
-export const checkResourceFilesAvailability = async () => {
+export const checkResourceFilesAvailability = async (gtasaPath: string) => {
   const promises: Promise<boolean>[] = [];
   validFileChecksums.forEach((file) => {
     if (file.requiredInGameDir) {
       promises.push(
         new Promise(async (resolve, reject) => {
           try {
-            resolve(await isFileAvailableinGTASADir(file));
+            resolve(await isFileAvailableinGTASADir(file, gtasaPath));
           } catch (e) {
             reject(e);
           }
@@ -84,11 +141,11 @@ export const startGame = async (
     return;
   }
 
-  const gtasaPath = getGTAPathForServer(server);
+  const gtasaPath = await getGTAPathForServer(server);
   const { addToRecentlyJoined } = usePersistentServers.getState();
   const { showMessageBox, hideMessageBox } = useMessageBox.getState();
   const { show: showSettings } = useSettingsModal.getState();
-  const { sampVersion } = useSettings.getState();
+  const { launchPolicy } = useSettings.getState();
   const { showPrompt, setServer } = useJoinServerPrompt.getState();
   const { setSelected } = useServers.getState();
   const { shouldUpdateDiscordStatus } = useGenericPersistentState.getState();
@@ -119,6 +176,8 @@ export const startGame = async (
     return;
   }
 
+  const sampVersion = await getSampVersionForServer(server);
+
   if (!nickname || nickname.length < 1) {
     showMessageBox({
       title: t("nickname_modal_name_not_set_title"),
@@ -153,15 +212,15 @@ export const startGame = async (
   if (sampVersion !== "custom") {
     let failExecution = false;
     try {
-      const checks = await checkResourceFilesAvailability();
+      const checks = await checkResourceFilesAvailability(gtasaPath);
       if (checks.includes(false)) {
         Log.debug(
           "Failed file validation, let's copy files into GTASA directory"
         );
-        await copySharedFilesIntoGameFolder();
+        await copySharedFilesIntoGameFolder(gtasaPath);
       }
     } catch (e) {
-      if (e === "need_admin") {
+      if (isAdminRequiredError(e)) {
         const { showMessageBox, hideMessageBox } = useMessageBox.getState();
         showMessageBox({
           title: t("admin_permissions_required_modal_title"),
@@ -173,7 +232,7 @@ export const startGame = async (
               onPress: async () => {
                 shell
                   .open("https://assets.open.mp/run_as_admin.gif")
-                  .then(() => process.exit());
+                  .then(() => invoke("exit_launcher"));
                 // await invoke("rerun_as_admin").then(() => {
                 //   process.exit();
                 // });
@@ -258,13 +317,94 @@ export const startGame = async (
     ompFile: ompFile,
     password: password,
     discord: shouldUpdateDiscordStatus,
+    launchPolicy: launchPolicy,
   })
-    .then(() => {
+    .then(async (result) => {
+      const outcome = result as LaunchOutcome;
+      if (outcome.action === "already_running") {
+        const ip = await getIpAddress(server.ip);
+        showMessageBox({
+          title: t("game_already_running_title"),
+          description: t("game_already_running_description", {
+            path: gtasaPath,
+          }),
+          boxWidth: 360,
+          buttonWidth: 150,
+          buttons: [
+            {
+              title: t("connect_running_game"),
+              onPress: () => {
+                hideMessageBox();
+                invoke("connect_running_game", {
+                  pid: outcome.pid,
+                  ip: ip,
+                  port: server.port,
+                  password: password,
+                })
+                  .then(() => {
+                    addToRecentlyJoined(server);
+                    setSelected(undefined);
+                  })
+                  .catch((e) => {
+                    Log.debug(e);
+                    showMessageBox({
+                      title: t("connect_running_game_failed_title"),
+                      description: t("connect_running_game_failed_description", {
+                        path: gtasaPath,
+                      }),
+                      buttons: [
+                        {
+                          title: t("cancel"),
+                          onPress: () => hideMessageBox(),
+                        },
+                      ],
+                    });
+                  });
+              },
+            },
+            {
+              title: t("cancel"),
+              onPress: () => hideMessageBox(),
+            },
+          ],
+        });
+        return;
+      }
+
+      if (outcome.action === "refused") {
+        showMessageBox({
+          title: t("game_already_running_title"),
+          description: t("game_already_running_refused_description", {
+            path: gtasaPath,
+          }),
+          buttons: [
+            {
+              title: t("cancel"),
+              onPress: () => hideMessageBox(),
+            },
+          ],
+        });
+        return;
+      }
+
       addToRecentlyJoined(server);
       setSelected(undefined);
     })
     .catch(async (e) => {
-      if (e == "need_admin") {
+      if (e?.code === "unsupported_gta_exe") {
+        showMessageBox({
+          title: t("unsupported_gta_exe_title"),
+          description: t("unsupported_gta_exe_description", {
+            path: gtasaPath,
+          }),
+          buttons: [
+            {
+              title: t("cancel"),
+              onPress: () => hideMessageBox(),
+            },
+          ],
+        });
+      } else if (isAdminRequiredError(e)) {
         showMessageBox({
           title: t("admin_permissions_required_modal_title"),
           description: t("admin_permissions_required_modal_description"),
@@ -274,7 +414,7 @@ export const startGame = async (
               onPress: async () => {
                 shell
                   .open("https://assets.open.mp/run_as_admin.gif")
-                  .then(() => process.exit());
+                  .then(() => invoke("exit_launcher"));
                 // await invoke("rerun_as_admin").then(() => {
                 //   process.exit();
                 // });
@@ -405,6 +545,68 @@ export const exportFavoriteListFile = async () => {
   }
 };
 
+export const exportFavoriteListToSAMP = async () => {
+  const { favorites } = usePersistentServers.getState();
+  const { showMessageBox, hideMessageBox } = useMessageBox.getState();
+
+  if (!favorites.length) {
+    showMessageBox({
+      title: t("export_failed_title"),
+      description: t("export_no_servers_description"),
+      buttons: [
+        {
+          title: "OK",
+          onPress: () => hideMessageBox(),
+        },
+      ],
+    });
+    return;
+  }
+
+  try {
+    const savePath = await save({
+      filters: [
+        {
+          name: "SA-MP USERDATA",
+          extensions: ["DAT"],
+        },
+      ],
+      defaultPath: "USERDATA.DAT",
+    });
+
+    if (savePath) {
+      await invoke("export_samp_favorite_list", {
+        servers: favorites.map((server) => ({
+          ip: server.ip,
+          port: server.port,
+          name: server.hostname,
+          password: server.password || "",
+          rcon: "",
+        })),
+        path: savePath,
+      });
+
+      const { showNotification } = useNotification.getState();
+      showNotification(
+        t("export_successful_title"),
+        t("export_successful_description")
+      );
+    }
+  } catch (error) {
+    Log.debug("Error exporting servers to SA-MP format:", error);
+    showMessageBox({
+      title: t("export_failed_title"),
+      description: t("export_failed_description"),
+      buttons: [
+        {
+          title: "OK",
+          onPress: () => hideMessageBox(),
+        },
+      ],
+    });
+  }
+};
+
 export const importFavoriteListFile = async () => {
   const { showMessageBox, hideMessageBox } = useMessageBox.getState();
 
@@ -6,12 +6,14 @@ import { getCachedList, getUpdateInfo } from "../api/apis";
 import { useAppState } from "../states/app";
 import { useMessageBox } from "../states/messageModal";
 import { usePersistentServers, useServers } from "../states/servers";
+import { useSettings } from "../states/settings";
 import { Log } from "./logger";
 import { queryServer } from "./query";
 import {
   APIResponseServer,
   Player,
   SAMPDLLVersions,
+  SampSyncResult,
   SearchData,
   Server,
 } from "./types";
@@ -447,3 +449,46 @@ export async function checkIfProcessAlive(pid: number) {
     return false;
   }
 }
+
+export const syncSampConfig = async () => {
+  const {
+    sampSyncMode,
+    nickName,
+    nickNameModifiedAt,
+    gtasaPath,
+    gtasaPathModifiedAt,
+    setNickName,
+    setGTASAPath,
+  } = useSettings.getState();
+
+  if (sampSyncMode === "off") {
+    return;
+  }
+
+  try {
+    const result = await invoke<SampSyncResult | null>("sync_samp_config", {
+      mode: sampSyncMode,
+      nickname: nickName,
+      nicknameModifiedAt: nickNameModifiedAt,
+      gameDir: gtasaPath,
+      gameDirModifiedAt: gtasaPathModifiedAt,
+    });
+
+    if (!result) {
+      return;
+    }
+
+    if (
+      result.player_name.action === "taken_from_samp" &&
+      result.player_name.value
+    ) {
+      setNickName(result.player_name.value);
+    }
+
+    if (result.game_dir.action === "taken_from_samp" && result.game_dir.value) {
+      setGTASAPath(result.game_dir.value.replace(/\\/g, "/"));
+    }
+  } catch (e) {
+    Log.debug(e);
+  }
+};
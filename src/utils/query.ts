@@ -1,7 +1,7 @@
 import { invoke } from "@tauri-apps/api";
 import { usePersistentServers, useServers } from "../states/servers";
 import { Log } from "./logger";
-import { ListType, Server } from "./types";
+import { ColoredText, ListType, Player, Server } from "./types";
 
 export const queryServer = (
   server: Server,
@@ -37,6 +37,10 @@ const queryServerImpl = async (
   listType: ListType
 ) => {
   try {
+    const settings = usePersistentServers
+      .getState()
+      .getServerSettings({ ip, port } as Server);
+
     const result: any = JSON.parse(
       await invoke("query_server", {
         ip: ip,
@@ -46,6 +50,7 @@ const queryServerImpl = async (
         players,
         rules,
         ping,
+        encoding: settings?.encoding,
       })
     );
 
@@ -96,9 +101,12 @@ const setServerInfo = async (
       hasPassword: res.password,
       playerCount: res.players,
       maxPlayers: res.max_players,
-      hostname: res.hostname,
-      gameMode: res.gamemode,
-      language: res.language,
+      hostname: res.hostname_text ? res.hostname_text.plain : res.hostname,
+      hostnameSpans: res.hostname_text ? res.hostname_text.spans : undefined,
+      gameMode: res.gamemode_text ? res.gamemode_text.plain : res.gamemode,
+      language: res.language_text ? res.language_text.plain : res.language,
+      encoding: res.encoding,
+      encodingSource: res.encoding_source,
     };
 
     let server = getServerFromList(ip, port, listType);
@@ -121,7 +129,12 @@ const setServerPlayers = async (
     let server = getServerFromList(ip, port, listType);
     if (server) {
       if (Array.isArray(res)) {
-        server = { ...server, players: [...res] };
+        const players: Player[] = res.map((player: any) => ({
+          name: player.name_text ? player.name_text.plain : player.name,
+          nameSpans: player.name_text ? player.name_text.spans : undefined,
+          score: player.score,
+        }));
+        server = { ...server, players };
         updateServerEveryWhere(server);
       }
     }
@@ -141,8 +154,8 @@ const setServerRules = async (
     if (server) {
       const rules: Server["rules"] = {} as Server["rules"];
 
-      res.forEach((rule: [string, string]) => {
-        rules[rule[0]] = rule[1];
+      res.forEach((rule: [string, string, ColoredText?]) => {
+        rules[rule[0]] = rule[2] ? rule[2].plain : rule[1];
       });
 
       let isOmp = false;
@@ -10,10 +10,25 @@ export type Rule =
 export type ListType = "favorites" | "internet" | "partners" | "recentlyjoined";
 export type SortType = "none" | "ascending" | "descending";
 
+export interface TextSpan {
+  text: string;
+  color: string | null;
+}
+
+export interface ColoredText {
+  plain: string;
+  spans: TextSpan[];
+}
+
+export type EncodingSource = "override" | "rules" | "language" | "detected";
+
 export interface Server {
   ip: string;
   port: number;
   hostname: string;
+  hostnameSpans?: TextSpan[];
+  encoding?: string;
+  encodingSource?: EncodingSource;
   playerCount: number;
   maxPlayers: number;
   gameMode: string;
@@ -46,6 +61,7 @@ export interface Server {
 
 export interface Player {
   name: string;
+  nameSpans?: TextSpan[];
   score: number;
 }
 
@@ -89,9 +105,130 @@ export type SAMPDLLVersions =
   | "03DL_samp.dll"
   | "custom";
 
+export type LaunchPolicy = "connect" | "ask" | "refuse";
+
+export type LaunchOutcome =
+  | { action: "launched" }
+  | { action: "connected_to_running_game"; pid: number }
+  | { action: "already_running"; pid: number }
+  | { action: "refused"; pid: number };
+
+export type SampSyncMode = "off" | "one_way" | "two_way";
+
+export interface SampSyncFieldResult {
+  action: "unchanged" | "written_to_samp" | "taken_from_samp";
+  value?: string;
+  conflict: boolean;
+}
+
+export interface SampSyncResult {
+  location: string;
+  player_name: SampSyncFieldResult;
+  game_dir: SampSyncFieldResult;
+}
+
+export type LauncherErrorCode =
+  | "permission_denied"
+  | "elevation_required"
+  | "already_exists"
+  | "not_found"
+  | "busy"
+  | "unsupported_gta_exe"
+  | "not_a_game_process"
+  | "corrupt_file"
+  | "invalid_setting"
+  | "rollback_incomplete"
+  | "io"
+  | "other";
+
+export interface LauncherError {
+  code: LauncherErrorCode;
+  details: string;
+}
+
+export interface SAMPServerInfo {
+  ip: string;
+  port: number;
+  name: string;
+  password: string;
+  rcon: string;
+}
+
+export interface SAMPUserData {
+  file_id: string;
+  file_version: number;
+  server_count: number;
+  favorite_servers: SAMPServerInfo[];
+}
+
+export interface SampClientSettings {
+  page_size: number | null;
+  fps_limit: number | null;
+  timestamp: boolean | null;
+  audio_msg_off: boolean | null;
+  audio_proxy_off: boolean | null;
+  disable_head_move: boolean | null;
+  multicore: boolean | null;
+  direct_mode: boolean | null;
+  ime: boolean | null;
+  no_name_tag_status: boolean | null;
+  font_face: string | null;
+  font_weight: boolean | null;
+}
+
+export interface ArchivedChatlog {
+  id: string;
+  ip: string;
+  port: number;
+  started_at: number;
+  ended_at: number;
+  line_count: number;
+}
+
+export interface ChatLine {
+  time: string | null;
+  text: string;
+  spans: TextSpan[];
+}
+
+export interface ChatlogMatch {
+  chatlog: ArchivedChatlog;
+  line_number: number;
+  line: ChatLine;
+}
+
+export interface Screenshot {
+  id: string;
+  path: string;
+  thumbnail: string | null;
+  taken_at: number;
+  ip: string | null;
+  port: number | null;
+}
+
 export interface PerServerSettings {
   ipPort: string;
   nickname?: string;
   sampVersion?: SAMPDLLVersions;
   gtasaPath?: string;
+  installationId?: string;
+  encoding?: string;
+}
+
+export interface InstallCandidate {
+  path: string;
+  source: string;
+  prefix?: string;
+  exe?: { version: string; md5: string; samp: string; omp: string };
+}
+
+export interface Installation {
+  id: string;
+  name: string;
+  path: string;
+  exe?: { version: string; md5: string; samp: string; omp: string };
+  samp?: { version: string; name?: string; md5: string };
+  mods: string[];
+  added_at: number;
+  scanned_at: number;
 }